@@ -0,0 +1,197 @@
+use std::fmt::Debug;
+
+/// The load of a coroutine pool observed by the [`Autoscaler`].
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct PoolLoad {
+    /// The number of tasks waiting in the queue.
+    pub queue_depth: usize,
+    /// How long the last started task waited in the queue, in ns.
+    pub wait_time: u64,
+    /// The number of workers.
+    pub workers: usize,
+    /// The number of coroutines suspended in syscalls.
+    pub syscall_workers: usize,
+}
+
+/// The decisions made by the [`Autoscaler`].
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ScaleEvent {
+    /// `count` workers were created because of the `load`.
+    Grow {
+        /// The number of created workers.
+        count: usize,
+        /// The load before growing.
+        load: PoolLoad,
+    },
+    /// An idle worker exited before its keep alive time.
+    Shrink {
+        /// The load before shrinking.
+        load: PoolLoad,
+    },
+}
+
+/// A trait to observe the decisions of the [`Autoscaler`].
+pub trait ScaleListener: Debug {
+    /// Callback after the autoscaler of the pool named `pool` makes a decision.
+    fn on_scale(&self, pool: &str, event: ScaleEvent);
+}
+
+/// Grow the workers of a coroutine pool up to `max_size` when the latency rises,
+/// and shrink them toward `min_size` faster than `keep_alive_time` when they are idle.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+pub struct Autoscaler {
+    //每个worker平均排队的任务数超过此值时扩容
+    queue_depth: usize,
+    //任务排队时间达到此值时扩容，单位ns，0表示不检查
+    wait_time: u64,
+    //陷入系统调用的worker占比达到此值时扩容，单位%
+    syscall_percent: usize,
+    //非核心worker空闲达到此值时退出，单位ns
+    idle_time: u64,
+    //每次最多扩容的worker数
+    max_step: usize,
+}
+
+impl Default for Autoscaler {
+    fn default() -> Self {
+        Autoscaler {
+            queue_depth: 4,
+            wait_time: 10_000_000,
+            syscall_percent: 50,
+            idle_time: 1_000_000_000,
+            max_step: 4,
+        }
+    }
+}
+
+impl Autoscaler {
+    /// Get the average number of queued tasks per worker above which the pool grows.
+    #[must_use]
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth
+    }
+
+    /// Get the task wait time in ns at which the pool grows.
+    #[must_use]
+    pub fn wait_time(&self) -> u64 {
+        self.wait_time
+    }
+
+    /// Get the percentage of workers in syscalls at which the pool grows.
+    #[must_use]
+    pub fn syscall_percent(&self) -> usize {
+        self.syscall_percent
+    }
+
+    /// Get the idle time in ns after which a non-core worker exits.
+    #[must_use]
+    pub fn idle_time(&self) -> u64 {
+        self.idle_time
+    }
+
+    /// Get the maximum number of workers created at once.
+    #[must_use]
+    pub fn max_step(&self) -> usize {
+        self.max_step
+    }
+
+    /// Set the average number of queued tasks per worker above which the pool grows.
+    pub fn set_queue_depth(&mut self, queue_depth: usize) -> &mut Self {
+        self.queue_depth = queue_depth;
+        self
+    }
+
+    /// Set the task wait time at which the pool grows.
+    /// `wait_time` has `ns` units, `0` means never check it.
+    pub fn set_wait_time(&mut self, wait_time: u64) -> &mut Self {
+        self.wait_time = wait_time;
+        self
+    }
+
+    /// Set the percentage of workers in syscalls at which the pool grows.
+    pub fn set_syscall_percent(&mut self, syscall_percent: usize) -> &mut Self {
+        assert!(
+            syscall_percent <= 100,
+            "syscall_percent must be less than or equal to 100"
+        );
+        self.syscall_percent = syscall_percent;
+        self
+    }
+
+    /// Set the idle time after which a non-core worker exits, even if its
+    /// keep alive time is not reached. `idle_time` has `ns` units.
+    pub fn set_idle_time(&mut self, idle_time: u64) -> &mut Self {
+        self.idle_time = idle_time;
+        self
+    }
+
+    /// Set the maximum number of workers created at once.
+    pub fn set_max_step(&mut self, max_step: usize) -> &mut Self {
+        assert!(max_step > 0, "max_step must be greater than 0");
+        self.max_step = max_step;
+        self
+    }
+
+    /// Returns how many workers should be created for the `load`.
+    pub(crate) fn grow_count(&self, load: &PoolLoad, max_size: usize) -> usize {
+        if 0 == load.queue_depth {
+            return 0;
+        }
+        let workers = load.workers.max(1);
+        let overloaded = load.queue_depth > self.queue_depth.saturating_mul(workers)
+            || 0 < self.wait_time && self.wait_time <= load.wait_time
+            || self.syscall_percent.saturating_mul(workers)
+                <= load.syscall_workers.saturating_mul(100);
+        if !overloaded {
+            return 0;
+        }
+        load.queue_depth
+            .min(self.max_step)
+            .min(max_size.saturating_sub(load.workers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grow_count() {
+        let autoscaler = Autoscaler::default();
+        let load = PoolLoad {
+            queue_depth: 2,
+            wait_time: 0,
+            workers: 2,
+            syscall_workers: 0,
+        };
+        assert_eq!(0, autoscaler.grow_count(&load, 16));
+        // the tasks wait too long
+        let load = PoolLoad {
+            wait_time: 20_000_000,
+            ..load
+        };
+        assert_eq!(2, autoscaler.grow_count(&load, 16));
+        // most workers are blocked in syscalls
+        let load = PoolLoad {
+            queue_depth: 10,
+            wait_time: 0,
+            workers: 2,
+            syscall_workers: 1,
+        };
+        assert_eq!(4, autoscaler.grow_count(&load, 16));
+        assert_eq!(1, autoscaler.grow_count(&load, 3));
+        assert_eq!(
+            0,
+            autoscaler.grow_count(
+                &PoolLoad {
+                    queue_depth: 0,
+                    ..load
+                },
+                16
+            )
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use crate::co_pool::task::Task;
+use std::collections::VecDeque;
+
+/// The metrics of a task group.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct GroupMetrics {
+    /// The maximum number of tasks in flight.
+    pub limit: usize,
+    /// The maximum number of tasks waiting in the group, `0` means unbounded.
+    pub capacity: usize,
+    /// The number of tasks queued in the pool or running.
+    pub in_flight: usize,
+    /// The number of tasks waiting in the group.
+    pub waiting: usize,
+}
+
+/// A bulkhead which limits how many tasks of the group are in flight, the excess
+/// tasks wait in the group instead of taking the workers.
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct TaskGroup<'g> {
+    limit: usize,
+    capacity: usize,
+    in_flight: usize,
+    waiting: VecDeque<Task<'g>>,
+}
+
+impl<'g> TaskGroup<'g> {
+    pub(crate) fn new(limit: usize, capacity: usize) -> Self {
+        TaskGroup {
+            limit,
+            capacity,
+            in_flight: 0,
+            waiting: VecDeque::new(),
+        }
+    }
+
+    /// Change the limits, returns the waiting tasks which can run now.
+    pub(crate) fn set_limit(&mut self, limit: usize, capacity: usize) -> Vec<Task<'g>> {
+        self.limit = limit;
+        self.capacity = capacity;
+        self.drain()
+    }
+
+    /// Returns `true` if no more task can run or wait in this group.
+    pub(crate) fn is_full(&self) -> bool {
+        self.in_flight >= self.limit && self.capacity > 0 && self.waiting.len() >= self.capacity
+    }
+
+    /// Returns the task if it can run now, otherwise it waits in this group.
+    pub(crate) fn enter(&mut self, task: Task<'g>) -> Option<Task<'g>> {
+        if self.in_flight < self.limit {
+            self.in_flight += 1;
+            return Some(task);
+        }
+        self.waiting.push_back(task);
+        None
+    }
+
+    /// A task in flight is finished, returns the waiting tasks which can run now.
+    pub(crate) fn leave(&mut self) -> Vec<Task<'g>> {
+        self.in_flight = self.in_flight.saturating_sub(1);
+        self.drain()
+    }
+
+    fn drain(&mut self) -> Vec<Task<'g>> {
+        let mut ready = Vec::new();
+        while self.in_flight < self.limit {
+            let Some(task) = self.waiting.pop_front() else {
+                break;
+            };
+            self.in_flight += 1;
+            ready.push(task);
+        }
+        ready
+    }
+
+    pub(crate) fn metrics(&self) -> GroupMetrics {
+        GroupMetrics {
+            limit: self.limit,
+            capacity: self.capacity,
+            in_flight: self.in_flight,
+            waiting: self.waiting.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::co_pool::task::TaskPriority;
+
+    #[test]
+    fn test_group() {
+        let task = |name: &str| Task::new(String::from(name), |p| p, None, TaskPriority::Normal);
+        let mut group = TaskGroup::new(1, 1);
+        assert!(group.enter(task("first")).is_some());
+        assert!(group.enter(task("second")).is_none());
+        assert!(group.is_full());
+        assert_eq!(
+            GroupMetrics {
+                limit: 1,
+                capacity: 1,
+                in_flight: 1,
+                waiting: 1,
+            },
+            group.metrics()
+        );
+        let ready = group.leave();
+        assert_eq!(
+            vec!["second"],
+            ready.iter().map(Task::name).collect::<Vec<_>>()
+        );
+        assert!(group.enter(task("third")).is_none());
+        assert!(group.is_full());
+        // raise the limit to run the waiting tasks
+        let ready = group.set_limit(2, 0);
+        assert_eq!(
+            vec!["third"],
+            ready.iter().map(Task::name).collect::<Vec<_>>()
+        );
+        assert_eq!(2, group.metrics().in_flight);
+        assert!(!group.is_full());
+        assert!(group.enter(task("fourth")).is_none());
+        let ready = group.leave();
+        assert_eq!(
+            vec!["fourth"],
+            ready.iter().map(Task::name).collect::<Vec<_>>()
+        );
+        assert!(group.leave().is_empty());
+        assert_eq!(0, group.metrics().waiting);
+        assert_eq!(1, group.metrics().in_flight);
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt::Debug;
+
+/// A trait to observe the lifecycle of tasks, mainly used for audit logs and metrics.
+///
+/// `wait_time` is how long the task waited in the queue, `run_time` is how long it ran,
+/// both have `ns` units.
+#[allow(unused_variables)]
+pub trait TaskListener: Debug {
+    /// Callback when the task is submitted, before it's queued or rejected.
+    fn on_submit(&self, name: &str) {}
+
+    /// Callback before the task starts running.
+    fn on_start(&self, name: &str, wait_time: u64) {}
+
+    /// Callback after the task is completed.
+    fn on_complete(&self, name: &str, wait_time: u64, run_time: u64, result: Option<usize>) {}
+
+    /// Callback after the task is completed with errors, usually, panic occurs.
+    fn on_error(&self, name: &str, wait_time: u64, run_time: u64, message: &str) {}
+
+    /// Callback after the task is rejected or discarded by the full task queue.
+    fn on_reject(&self, name: &str) {}
+
+    /// Callback after the cancelled task is dropped by the pool, `run_time` is `0` if
+    /// it was cancelled before it started running.
+    fn on_cancel(&self, name: &str, wait_time: u64, run_time: u64) {}
+}
+
+/// The kinds of [`TaskEvent`], each one matches a callback of [`TaskListener`].
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TaskEventKind {
+    /// see [`TaskListener::on_submit`].
+    Submit,
+    /// see [`TaskListener::on_start`].
+    Start,
+    /// see [`TaskListener::on_complete`].
+    Complete,
+    /// see [`TaskListener::on_error`].
+    Error,
+    /// see [`TaskListener::on_reject`].
+    Reject,
+    /// see [`TaskListener::on_cancel`].
+    Cancel,
+}
+
+/// A callback of [`TaskListener`] passed through the C ABI, the strings are only valid
+/// during the callback.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct TaskEvent {
+    /// Which callback it is.
+    pub kind: TaskEventKind,
+    /// The utf-8 name of the task.
+    pub name: *const u8,
+    /// The length of `name`.
+    pub name_len: usize,
+    /// see [`TaskListener`], `0` if the callback has no such argument.
+    pub wait_time: u64,
+    /// see [`TaskListener`], `0` if the callback has no such argument.
+    pub run_time: u64,
+    /// Whether the completed task returns a `result`.
+    pub has_result: bool,
+    /// The result of the completed task.
+    pub result: usize,
+    /// The utf-8 error message, it's only set for [`TaskEventKind::Error`].
+    pub message: *const u8,
+    /// The length of `message`.
+    pub message_len: usize,
+}
+
+impl TaskEvent {
+    fn new(kind: TaskEventKind, name: &str) -> Self {
+        TaskEvent {
+            kind,
+            name: name.as_ptr(),
+            name_len: name.len(),
+            wait_time: 0,
+            run_time: 0,
+            has_result: false,
+            result: 0,
+            message: "".as_ptr(),
+            message_len: 0,
+        }
+    }
+
+    /// Call the matching callback of the `listener`.
+    ///
+    /// # Safety
+    /// the event must be received by [`UserTaskListenerFunc`], during the callback.
+    pub unsafe fn dispatch(&self, listener: &dyn TaskListener) {
+        let str = |ptr: *const u8, len: usize| {
+            std::str::from_utf8_unchecked(std::slice::from_raw_parts(ptr, len))
+        };
+        let name = str(self.name, self.name_len);
+        match self.kind {
+            TaskEventKind::Submit => listener.on_submit(name),
+            TaskEventKind::Start => listener.on_start(name, self.wait_time),
+            TaskEventKind::Complete => listener.on_complete(
+                name,
+                self.wait_time,
+                self.run_time,
+                self.has_result.then_some(self.result),
+            ),
+            TaskEventKind::Error => listener.on_error(
+                name,
+                self.wait_time,
+                self.run_time,
+                str(self.message, self.message_len),
+            ),
+            TaskEventKind::Reject => listener.on_reject(name),
+            TaskEventKind::Cancel => listener.on_cancel(name, self.wait_time, self.run_time),
+        }
+    }
+}
+
+/// 做C兼容时会用到，第一个参数是注册监听器时传入的参数
+pub type UserTaskListenerFunc = extern "C" fn(usize, &TaskEvent);
+
+/// The [`TaskListener`] which reports the callbacks through the C ABI.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct UserTaskListener(UserTaskListenerFunc, usize);
+
+impl UserTaskListener {
+    /// Report the callbacks to `f`, along with the `param`.
+    #[must_use]
+    pub fn new(f: UserTaskListenerFunc, param: usize) -> Self {
+        UserTaskListener(f, param)
+    }
+
+    fn report(&self, event: &TaskEvent) {
+        (self.0)(self.1, event);
+    }
+}
+
+impl TaskListener for UserTaskListener {
+    fn on_submit(&self, name: &str) {
+        self.report(&TaskEvent::new(TaskEventKind::Submit, name));
+    }
+
+    fn on_start(&self, name: &str, wait_time: u64) {
+        self.report(&TaskEvent {
+            wait_time,
+            ..TaskEvent::new(TaskEventKind::Start, name)
+        });
+    }
+
+    fn on_complete(&self, name: &str, wait_time: u64, run_time: u64, result: Option<usize>) {
+        self.report(&TaskEvent {
+            wait_time,
+            run_time,
+            has_result: result.is_some(),
+            result: result.unwrap_or_default(),
+            ..TaskEvent::new(TaskEventKind::Complete, name)
+        });
+    }
+
+    fn on_error(&self, name: &str, wait_time: u64, run_time: u64, message: &str) {
+        self.report(&TaskEvent {
+            wait_time,
+            run_time,
+            message: message.as_ptr(),
+            message_len: message.len(),
+            ..TaskEvent::new(TaskEventKind::Error, name)
+        });
+    }
+
+    fn on_reject(&self, name: &str) {
+        self.report(&TaskEvent::new(TaskEventKind::Reject, name));
+    }
+
+    fn on_cancel(&self, name: &str, wait_time: u64, run_time: u64) {
+        self.report(&TaskEvent {
+            wait_time,
+            run_time,
+            ..TaskEvent::new(TaskEventKind::Cancel, name)
+        });
+    }
+}
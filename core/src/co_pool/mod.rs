@@ -1,55 +1,147 @@
+use crate::co_pool::autoscaler::{Autoscaler, PoolLoad, ScaleEvent, ScaleListener};
 use crate::co_pool::creator::CoroutineCreator;
-use crate::co_pool::task::Task;
-use crate::common::beans::BeanFactory;
-use crate::common::constants::PoolState;
-use crate::common::work_steal::{LocalQueue, WorkStealQueue};
-use crate::common::{get_timeout_time, now, CondvarBlocker};
+use crate::co_pool::group::{GroupMetrics, TaskGroup};
+use crate::co_pool::listener::TaskListener;
+use crate::co_pool::queue::{PriorityQueue, QueuePermit, QueueSlots};
+use crate::co_pool::retry::{Attempt, Retry, RetryPolicy};
+use crate::co_pool::schedule::Periodic;
+use crate::co_pool::store::TaskStore;
+use crate::co_pool::task::{
+    is_cancelled, ResultSlot, Task, TaskCanceller, TaskId, TaskPriority, TASK_CANCELLED,
+};
+use crate::co_pool::waiter::{wait_results, TaskResult, Waiter};
+use crate::common::constants::{PoolState, RejectPolicy};
+use crate::common::timer::TimerList;
+use crate::common::{get_timeout_time, hash, now};
 use crate::coroutine::suspender::Suspender;
-use crate::scheduler::{SchedulableCoroutine, Scheduler};
-use crate::{impl_current_for, impl_display_by_debug, impl_for_named, trace};
+use crate::coroutine::CoroutineId;
+use crate::preempt::PreemptionGuard;
+use crate::scheduler::{SchedulableCoroutine, SchedulableSuspender, Scheduler};
+use crate::{catch, impl_current_for, impl_display_by_debug, impl_for_named, trace};
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
+use derivative::Derivative;
+use once_cell::sync::{Lazy, OnceCell};
 use std::cell::Cell;
+use std::collections::VecDeque;
+use std::hash::Hash;
 use std::io::{Error, ErrorKind};
 use std::ops::{Deref, DerefMut};
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
-use std::sync::{Arc, Condvar, Mutex};
+use std::sync::{Arc, Mutex, RwLock, Weak};
 use std::time::Duration;
 
 /// Task abstraction and impl.
 pub mod task;
 
+/// Task queues with priorities.
+mod queue;
+
+/// Periodic task schedules.
+mod schedule;
+
+/// The results of the tasks submitted to a pool.
+mod store;
+
 /// Coroutine pool state abstraction and impl.
 mod state;
 
 /// Creator for coroutine pool.
 mod creator;
 
+/// Autoscaler for coroutine pool.
+pub mod autoscaler;
+
+/// Task listener abstraction.
+pub mod listener;
+
+/// Retry abstraction for failed tasks.
+pub mod retry;
+
+/// Bulkheads which limit the concurrency of task groups.
+pub mod group;
+
+/// Waiters for task results.
+pub(crate) mod waiter;
+
+/// The state of the submitted tasks, tasks may be stolen by other pools,
+/// so it's shared by all pools.
+static TASK_STATES: Lazy<DashMap<TaskId, TaskState>> = Lazy::new(DashMap::new);
+
+//协程本地变量中正在执行的任务
+const CURRENT_TASK: &str = "open-coroutine-current-task";
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum TaskState {
+    Queued,
+    Running,
+    Cancelled,
+}
+
+/// Default time after which a waiting task is promoted by one priority.
+pub const DEFAULT_AGING_TIME: u64 = 100_000_000;
+
+/// Default time a submitter waits for a place at most under [`RejectPolicy::Block`].
+pub const DEFAULT_BLOCK_TIMEOUT: u64 = 10_000_000_000;
+
 /// The coroutine pool impls.
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Derivative)]
+#[derivative(Debug)]
 pub struct CoroutinePool<'p> {
     //协程池状态
     state: Cell<PoolState>,
-    //任务队列
-    task_queue: LocalQueue<'p, Task<'p>>,
+    //任务队列，每个优先级一个
+    task_queue: PriorityQueue<'p>,
     //工作协程组
     workers: Scheduler<'p>,
     //当前协程数
     running: AtomicUsize,
-    //尝试取出任务失败的次数
-    pop_fail_times: AtomicUsize,
     //最小协程数，即核心协程数
     min_size: AtomicUsize,
     //最大协程数
     max_size: AtomicUsize,
     //非核心协程的最大存活时间，单位ns
     keep_alive_time: AtomicU64,
-    //阻滞器
-    blocker: Arc<CondvarBlocker>,
-    //正在等待结果的
-    waits: DashMap<&'p str, Arc<(Mutex<bool>, Condvar)>>,
-    //任务执行结果
-    results: DashMap<String, Result<Option<usize>, &'p str>>,
+    //任务队列容量，0表示不限制
+    queue_capacity: AtomicUsize,
+    //排队中的任务数
+    slots: Arc<QueueSlots>,
+    //任务队列已满时的拒绝策略
+    reject_policy: Cell<RejectPolicy>,
+    //阻塞策略下最多等待名额的时间，单位ns
+    block_timeout: AtomicU64,
+    //被拒绝的任务数
+    rejected: AtomicU64,
+    //任务每等待这么久就提升一级优先级，单位ns，0表示不提升
+    aging_time: AtomicU64,
+    //停车等待任务的worker协程
+    idle: Mutex<VecDeque<CoroutineId>>,
+    //用户指定的、尚未执行完的任务名
+    names: DashMap<String, TaskId>,
+    //排队中的任务，取消时据此立即释放名额和闭包
+    cancellers: DashMap<TaskId, TaskCanceller<'p>>,
+    //提交到本池的任务的结果，任务被其他线程池窃取后也保存到这里
+    store: Arc<TaskStore<'p>>,
+    //延迟执行的任务，到期后才进入任务队列
+    delayed: Mutex<TimerList<Task<'p>>>,
+    //延迟执行的任务的到期时间，取消时据此从定时器中移除
+    timestamps: DashMap<TaskId, u64>,
+    //正在执行的key及其后续排队的任务
+    keys: DashMap<u64, VecDeque<Task<'p>>>,
+    //任务分组的并发限制及其排队的任务
+    groups: DashMap<String, TaskGroup<'p>>,
+    //自动扩缩容，None表示关闭
+    autoscaler: Cell<Option<Autoscaler>>,
+    //最近开始执行的任务的排队时间，单位ns
+    wait_time: AtomicU64,
+    //运行期间也能添加，所以需要加锁
+    scale_listeners: RwLock<Vec<&'p dyn ScaleListener>>,
+    //运行期间也能添加，所以需要加锁
+    task_listeners: RwLock<Vec<&'p dyn TaskListener>>,
+    //其他线程唤醒本池的协程后，用它唤醒调度线程
+    #[derivative(Debug = "ignore")]
+    waker: OnceCell<Box<dyn Fn() + 'p>>,
 }
 
 impl Drop for CoroutinePool<'_> {
@@ -125,17 +217,29 @@ impl<'p> CoroutinePool<'p> {
             state: Cell::new(PoolState::Running),
             workers,
             running: AtomicUsize::new(0),
-            pop_fail_times: AtomicUsize::new(0),
             min_size: AtomicUsize::new(min_size),
             max_size: AtomicUsize::new(max_size),
-            task_queue: BeanFactory::get_or_default::<WorkStealQueue<Task<'p>>>(
-                crate::common::constants::TASK_GLOBAL_QUEUE_BEAN,
-            )
-            .local_queue(),
+            task_queue: PriorityQueue::new(),
             keep_alive_time: AtomicU64::new(keep_alive_time),
-            blocker: Arc::default(),
-            results: DashMap::new(),
-            waits: DashMap::default(),
+            queue_capacity: AtomicUsize::new(0),
+            slots: Arc::default(),
+            reject_policy: Cell::default(),
+            block_timeout: AtomicU64::new(DEFAULT_BLOCK_TIMEOUT),
+            rejected: AtomicU64::new(0),
+            aging_time: AtomicU64::new(DEFAULT_AGING_TIME),
+            idle: Mutex::default(),
+            names: DashMap::default(),
+            cancellers: DashMap::default(),
+            store: Arc::default(),
+            delayed: Mutex::default(),
+            timestamps: DashMap::default(),
+            keys: DashMap::default(),
+            groups: DashMap::default(),
+            autoscaler: Cell::default(),
+            wait_time: AtomicU64::new(0),
+            scale_listeners: RwLock::default(),
+            task_listeners: RwLock::default(),
+            waker: OnceCell::new(),
         }
     }
 
@@ -177,6 +281,196 @@ impl<'p> CoroutinePool<'p> {
         self.keep_alive_time.load(Ordering::Acquire)
     }
 
+    /// Set the maximum number of tasks waiting in the queue of this pool, `0` means unbounded.
+    pub fn set_queue_capacity(&self, queue_capacity: usize) {
+        self.queue_capacity.store(queue_capacity, Ordering::Release);
+    }
+
+    /// Get the maximum number of tasks waiting in the queue of this pool.
+    pub fn get_queue_capacity(&self) -> usize {
+        self.queue_capacity.load(Ordering::Acquire)
+    }
+
+    /// Set what to do when a task is submitted to a full task queue.
+    pub fn set_reject_policy(&self, reject_policy: RejectPolicy) {
+        self.reject_policy.set(reject_policy);
+    }
+
+    /// Get what to do when a task is submitted to a full task queue.
+    pub fn get_reject_policy(&self) -> RejectPolicy {
+        self.reject_policy.get()
+    }
+
+    /// Set how long a submitter waits for a place at most under [`RejectPolicy::Block`],
+    /// the task is rejected after it, `block_timeout` has `ns` units.
+    pub fn set_block_timeout(&self, block_timeout: u64) {
+        self.block_timeout.store(block_timeout, Ordering::Release);
+    }
+
+    /// Get how long a submitter waits for a place at most under [`RejectPolicy::Block`].
+    /// Returns in `ns` units.
+    pub fn get_block_timeout(&self) -> u64 {
+        self.block_timeout.load(Ordering::Acquire)
+    }
+
+    /// Gets the number of tasks rejected or discarded because the task queue is full,
+    /// the tasks which are run by the caller or wait for a place are not counted.
+    pub fn get_rejected_count(&self) -> u64 {
+        self.rejected.load(Ordering::Acquire)
+    }
+
+    /// Set how long an unjoined result is kept, the expired results are evicted.
+    /// `result_ttl` has `ns` units, `0` means keep them until they are joined.
+    pub fn set_result_ttl(&self, result_ttl: u64) {
+        self.store.set_result_ttl(result_ttl);
+    }
+
+    /// Get how long an unjoined result is kept.
+    /// Returns in `ns` units.
+    pub fn get_result_ttl(&self) -> u64 {
+        self.store.get_result_ttl()
+    }
+
+    /// Set the maximum number of unjoined results kept in this pool, the oldest ones are
+    /// evicted first, `0` means unbounded.
+    pub fn set_result_capacity(&self, result_capacity: usize) {
+        self.store.set_result_capacity(result_capacity);
+    }
+
+    /// Get the maximum number of unjoined results kept in this pool.
+    pub fn get_result_capacity(&self) -> usize {
+        self.store.get_result_capacity()
+    }
+
+    /// Gets the number of results evicted before they were joined.
+    pub fn get_evicted_count(&self) -> u64 {
+        self.store.get_evicted_count()
+    }
+
+    /// Set the time after which a waiting task is promoted by one priority.
+    /// `aging_time` has `ns` units, `0` means never promote.
+    pub fn set_aging_time(&self, aging_time: u64) {
+        self.aging_time.store(aging_time, Ordering::Release);
+    }
+
+    /// Get the time after which a waiting task is promoted by one priority.
+    /// Returns in `ns` units.
+    pub fn get_aging_time(&self) -> u64 {
+        self.aging_time.load(Ordering::Acquire)
+    }
+
+    /// Set the autoscaler of this pool, `None` means disable autoscaling.
+    pub fn set_autoscaler(&self, autoscaler: Option<Autoscaler>) {
+        self.autoscaler.set(autoscaler);
+    }
+
+    /// Get the autoscaler of this pool.
+    pub fn get_autoscaler(&self) -> Option<Autoscaler> {
+        self.autoscaler.get()
+    }
+
+    /// Add a listener to observe the decisions of the autoscaler.
+    pub fn add_scale_listener(&self, listener: impl ScaleListener + 'p) {
+        self.add_raw_scale_listener(Box::leak(Box::new(listener)));
+    }
+
+    /// Add a raw listener to observe the decisions of the autoscaler.
+    pub(crate) fn add_raw_scale_listener(&self, listener: &'p dyn ScaleListener) {
+        self.scale_listeners
+            .write()
+            .expect("lock failed")
+            .push(listener);
+    }
+
+    /// Add a listener to observe the lifecycle of the tasks in this pool.
+    pub fn add_task_listener(&self, listener: impl TaskListener + 'p) {
+        self.add_raw_task_listener(Box::leak(Box::new(listener)));
+    }
+
+    /// Add a raw listener to observe the lifecycle of the tasks in this pool.
+    pub(crate) fn add_raw_task_listener(&self, listener: &'p dyn TaskListener) {
+        self.task_listeners
+            .write()
+            .expect("lock failed")
+            .push(listener);
+    }
+
+    fn broadcast(&self, method: &str, f: impl Fn(&dyn TaskListener)) {
+        let listeners = self.task_listeners.read().expect("lock failed").clone();
+        for listener in listeners {
+            _ = catch!(
+                || f(listener),
+                format!("TaskListener {method} failed without message"),
+                format!("{} invoke {method}", self.name())
+            );
+        }
+    }
+
+    fn on_task_done(
+        &self,
+        name: &str,
+        wait_time: u64,
+        run_time: u64,
+        result: &Result<Option<usize>, &str>,
+    ) {
+        match result {
+            Ok(result) => self.broadcast("on_complete", |listener| {
+                listener.on_complete(name, wait_time, run_time, *result);
+            }),
+            Err(_) if is_cancelled(result) => self.broadcast("on_cancel", |listener| {
+                listener.on_cancel(name, wait_time, run_time);
+            }),
+            Err(message) => self.broadcast("on_error", |listener| {
+                listener.on_error(name, wait_time, run_time, message);
+            }),
+        }
+    }
+
+    /// Returns the current load of this pool.
+    pub fn load(&self) -> PoolLoad {
+        PoolLoad {
+            queue_depth: self.task_queue.len(),
+            wait_time: self.wait_time.load(Ordering::Acquire),
+            workers: self.get_running_size(),
+            syscall_workers: self.syscall_size(),
+        }
+    }
+
+    /// Grow the workers according to the autoscaler.
+    fn autoscale(&self) {
+        let Some(autoscaler) = self.get_autoscaler() else {
+            return;
+        };
+        let load = self.load();
+        let mut count = 0;
+        for _ in 0..autoscaler.grow_count(&load, self.get_max_size()) {
+            if self.try_grow().is_err() {
+                break;
+            }
+            count += 1;
+        }
+        if count > 0 {
+            self.on_scale(ScaleEvent::Grow { count, load });
+        }
+    }
+
+    /// Returns `true` if the idle worker should exit before its keep alive time.
+    fn try_shrink(&self) -> bool {
+        if !self.task_queue.is_empty() || self.get_running_size() <= self.get_min_size() {
+            return false;
+        }
+        self.on_scale(ScaleEvent::Shrink { load: self.load() });
+        true
+    }
+
+    fn on_scale(&self, event: ScaleEvent) {
+        trace!("The coroutine pool:{} {:?}", self.name(), event);
+        let listeners = self.scale_listeners.read().expect("lock failed").clone();
+        for listener in listeners {
+            listener.on_scale(self.name(), event);
+        }
+    }
+
     /// Returns `true` if the task queue is empty.
     pub fn is_empty(&self) -> bool {
         self.size() == 0
@@ -187,6 +481,18 @@ impl<'p> CoroutinePool<'p> {
         self.task_queue.len()
     }
 
+    /// Returns how much work this pool has, that is the queued tasks, the coroutines
+    /// ready to run and the workers which are not idle.
+    pub(crate) fn busyness(&self) -> usize {
+        let idle = {
+            let _guard = PreemptionGuard::new();
+            self.idle.lock().expect("lock failed").len()
+        };
+        self.size()
+            .saturating_add(self.ready_size())
+            .saturating_add(self.get_running_size().saturating_sub(idle))
+    }
+
     /// Stop this coroutine pool.
     pub fn stop(&mut self, dur: Duration) -> std::io::Result<()> {
         match self.state() {
@@ -201,16 +507,284 @@ impl<'p> CoroutinePool<'p> {
         }
     }
 
-    /// Submit a new task to this pool.
+    /// Submit a new task to this pool, the task with higher `priority` is executed first,
+    /// `None` means [`TaskPriority::Normal`].
     ///
     /// Allow multiple threads to concurrently submit task to the pool,
     /// but only allow one thread to execute scheduling.
+    ///
+    /// Returns the id of the new task.
+    ///
+    /// If the task queue is full, the task is handled according to the [`RejectPolicy`].
+    /// Note that [`RejectPolicy::Block`] blocks the submitting thread, so never use it
+    /// on the thread which schedules this pool.
+    ///
+    /// # Errors
+    /// if the pool is stopping, or a task with the same `name` has not finished yet,
+    /// or the task was rejected.
     pub fn submit_task(
         &self,
         name: Option<String>,
         func: impl FnOnce(Option<usize>) -> Option<usize> + 'p,
         param: Option<usize>,
-    ) -> std::io::Result<String> {
+        priority: Option<TaskPriority>,
+    ) -> std::io::Result<TaskId> {
+        let task = self.new_task(name, |name| {
+            Task::new(name, func, param, priority.unwrap_or_default())
+        })?;
+        self.enqueue_task(task)
+    }
+
+    /// Submit a new task to this pool like [`CoroutinePool::submit_task`], but the result
+    /// of the task is never saved, so nobody can join it.
+    ///
+    /// # Errors
+    /// see [`CoroutinePool::submit_task`].
+    pub fn submit_detached_task(
+        &self,
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + 'p,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+    ) -> std::io::Result<TaskId> {
+        let mut task = self.new_task(name, |name| {
+            Task::new(name, func, param, priority.unwrap_or_default())
+        })?;
+        task.detach();
+        self.enqueue_task(task)
+    }
+
+    /// Submit a new task to this pool, and run it again according to the `policy` if it
+    /// fails by returning an error or panicking. The retries wait in the timers of this
+    /// pool instead of occupying a worker, and only the result of the last run is saved,
+    /// see [`CoroutinePool::try_get_task_attempts`] for all the runs.
+    ///
+    /// # Errors
+    /// see [`CoroutinePool::submit_task`].
+    //各次执行可能被其他线程池窃取
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn submit_task_with_retry(
+        &self,
+        name: Option<String>,
+        func: impl Fn(Option<usize>) -> Result<Option<usize>, &'p str> + 'p,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+        policy: RetryPolicy,
+    ) -> std::io::Result<TaskId> {
+        let task = self.new_task(name, |name| {
+            let retry = Retry::new(name, func, param, priority.unwrap_or_default(), policy);
+            Arc::new(retry).task(1)
+        })?;
+        self.enqueue_task(task)
+    }
+
+    /// Submit a new task to this pool like [`CoroutinePool::submit_task`], but the tasks
+    /// with the same `key` run strictly one after another in the order they were
+    /// submitted, even if one of them suspends, while the tasks with different keys
+    /// run concurrently. The keyed tasks are never stolen by other pools.
+    ///
+    /// # Errors
+    /// see [`CoroutinePool::submit_task`].
+    pub fn submit_keyed_task(
+        &self,
+        key: &(impl Hash + ?Sized),
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + 'p,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+    ) -> std::io::Result<TaskId> {
+        let mut task = self.new_task(name, |name| {
+            Task::new(name, func, param, priority.unwrap_or_default())
+        })?;
+        task.set_key(hash(key));
+        self.enqueue_task(task)
+    }
+
+    /// Set how many tasks of the `group` can be queued in this pool or running at the
+    /// same time, and how many excess tasks can wait in the group, `capacity` `0` means
+    /// unbounded. The group is created if it doesn't exist.
+    pub fn set_group_limit(&self, group: &str, limit: usize, capacity: usize) {
+        assert!(limit > 0, "limit must be greater than 0");
+        let _guard = PreemptionGuard::new();
+        let ready = match self.groups.entry(group.to_string()) {
+            Entry::Occupied(mut entry) => entry.get_mut().set_limit(limit, capacity),
+            Entry::Vacant(entry) => {
+                _ = entry.insert(TaskGroup::new(limit, capacity));
+                Vec::new()
+            }
+        };
+        for task in ready {
+            self.push_task(task);
+        }
+    }
+
+    /// Get the metrics of the `group`, `None` means the group doesn't exist.
+    pub fn get_group_metrics(&self, group: &str) -> Option<GroupMetrics> {
+        let _guard = PreemptionGuard::new();
+        self.groups.get(group).map(|group| group.metrics())
+    }
+
+    /// Submit a new task to this pool like [`CoroutinePool::submit_task`], but the task
+    /// waits in the `group` instead of taking a place in the task queue or a worker if
+    /// the group already has as many tasks in flight as its limit. The grouped tasks
+    /// are never stolen by other pools.
+    ///
+    /// # Errors
+    /// see [`CoroutinePool::submit_task`], or the group doesn't exist, or too many tasks
+    /// are waiting in the group.
+    pub fn submit_group_task(
+        &self,
+        group: &str,
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + 'p,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+    ) -> std::io::Result<TaskId> {
+        if self.get_group_metrics(group).is_none() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("The task group {group} does not exist !"),
+            ));
+        }
+        let mut task = self.new_task(name, |name| {
+            Task::new(name, func, param, priority.unwrap_or_default())
+        })?;
+        task.set_group(group.to_string());
+        self.enqueue_task(task)
+    }
+
+    /// Put the task into the task queue, or handle it according to the [`RejectPolicy`]
+    /// if the task queue is full.
+    fn enqueue_task(&self, task: Task<'p>) -> std::io::Result<TaskId> {
+        let task_id = task.id();
+        self.broadcast("on_submit", |listener| listener.on_submit(task.name()));
+        //分组的并发数已满时，任务在分组内等待
+        let Some(mut task) = self.enter_group(task)? else {
+            return Ok(task_id);
+        };
+        let task = if let Some(permit) = self.try_acquire() {
+            task.set_permit(permit);
+            task
+        } else {
+            match self.reject(task)? {
+                Some(task) => task,
+                None => return Ok(task_id),
+            }
+        };
+        {
+            let _guard = PreemptionGuard::new();
+            self.mark_queued(&task);
+        }
+        self.submit_raw_task(task);
+        Ok(task_id)
+    }
+
+    /// Submit a new task to this pool which is executed at the `timestamp` in ns,
+    /// the task doesn't take a place in the bounded queue until it's due.
+    ///
+    /// # Errors
+    /// if the pool is stopping, or a task with the same `name` has not finished yet.
+    pub fn submit_task_at(
+        &self,
+        timestamp: u64,
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + 'p,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+    ) -> std::io::Result<TaskId> {
+        let task = self.new_task(name, |name| {
+            Task::new(name, func, param, priority.unwrap_or_default())
+        })?;
+        Ok(self.delay_task(timestamp, task))
+    }
+
+    /// Submit a periodic task to this pool which first runs at the `timestamp` in ns,
+    /// and then at `timestamp + n * period`. The runs missed by an overrun are skipped.
+    ///
+    /// It stops when it's cancelled or a run fails, and the result of the last run is
+    /// saved, the results of the other runs are discarded.
+    ///
+    /// # Errors
+    /// if the pool is stopping, or a task with the same `name` has not finished yet,
+    /// or the `period` is zero.
+    pub fn schedule_at_fixed_rate(
+        &self,
+        timestamp: u64,
+        name: Option<String>,
+        func: impl Fn(Option<usize>) -> Option<usize> + 'p,
+        param: Option<usize>,
+        period: Duration,
+        priority: Option<TaskPriority>,
+    ) -> std::io::Result<TaskId> {
+        let period = Self::period(period)?;
+        self.schedule(timestamp, name, |name| {
+            Periodic::new(
+                name,
+                func,
+                param,
+                priority.unwrap_or_default(),
+                period,
+                true,
+            )
+        })
+    }
+
+    /// Like [`CoroutinePool::schedule_at_fixed_rate`], but the next run starts
+    /// `delay` after the previous run finishes.
+    ///
+    /// # Errors
+    /// if the pool is stopping, or a task with the same `name` has not finished yet,
+    /// or the `delay` is zero.
+    pub fn schedule_with_fixed_delay(
+        &self,
+        timestamp: u64,
+        name: Option<String>,
+        func: impl Fn(Option<usize>) -> Option<usize> + 'p,
+        param: Option<usize>,
+        delay: Duration,
+        priority: Option<TaskPriority>,
+    ) -> std::io::Result<TaskId> {
+        let delay = Self::period(delay)?;
+        self.schedule(timestamp, name, |name| {
+            Periodic::new(
+                name,
+                func,
+                param,
+                priority.unwrap_or_default(),
+                delay,
+                false,
+            )
+        })
+    }
+
+    fn period(period: Duration) -> std::io::Result<u64> {
+        match u64::try_from(period.as_nanos()).unwrap_or(u64::MAX) {
+            0 => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "The period must be greater than zero !",
+            )),
+            period => Ok(period),
+        }
+    }
+
+    //各次执行可能被其他线程池窃取
+    #[allow(clippy::arc_with_non_send_sync)]
+    fn schedule(
+        &self,
+        timestamp: u64,
+        name: Option<String>,
+        periodic: impl FnOnce(String) -> Periodic<'p>,
+    ) -> std::io::Result<TaskId> {
+        let task = self.new_task(name, |name| Arc::new(periodic(name)).task(timestamp))?;
+        Ok(self.delay_task(timestamp, task))
+    }
+
+    /// Check the state of this pool and register the task name.
+    fn new_task(
+        &self,
+        name: Option<String>,
+        create: impl FnOnce(String) -> Task<'p>,
+    ) -> std::io::Result<Task<'p>> {
         match self.state() {
             PoolState::Running => {}
             PoolState::Stopping | PoolState::Stopped => {
@@ -220,9 +794,293 @@ impl<'p> CoroutinePool<'p> {
                 ))
             }
         }
-        let name = name.unwrap_or(format!("{}@{}", self.name(), uuid::Uuid::new_v4()));
-        self.submit_raw_task(Task::new(name.clone(), func, param));
-        Ok(name)
+        let named = name.is_some();
+        let mut task =
+            create(name.unwrap_or_else(|| format!("{}@{}", self.name(), uuid::Uuid::new_v4())));
+        task.set_store(Arc::downgrade(&self.store));
+        if named {
+            let _guard = PreemptionGuard::new();
+            match self.names.entry(task.name().to_string()) {
+                Entry::Occupied(_) => {
+                    return Err(Error::new(
+                        ErrorKind::AlreadyExists,
+                        format!("The task {} already exists !", task.name()),
+                    ));
+                }
+                Entry::Vacant(entry) => _ = entry.insert(task.id()),
+            }
+        }
+        Ok(task)
+    }
+
+    fn delay_task(&self, timestamp: u64, task: Task<'p>) -> TaskId {
+        let task_id = task.id();
+        self.broadcast("on_submit", |listener| listener.on_submit(task.name()));
+        let _guard = PreemptionGuard::new();
+        self.mark_queued(&task);
+        _ = self.timestamps.insert(task_id, timestamp);
+        self.delayed
+            .lock()
+            .expect("lock failed")
+            .insert(timestamp, task);
+        task_id
+    }
+
+    /// Move the due tasks to the task queue.
+    fn submit_due_tasks(&self) {
+        let _guard = PreemptionGuard::new();
+        let mut delayed = self.delayed.lock().expect("lock failed");
+        let now = now();
+        while delayed
+            .front()
+            .is_some_and(|(timestamp, _)| *timestamp <= now)
+        {
+            if let Some((_, mut entry)) = delayed.pop_front() {
+                while let Some(task) = entry.pop_front() {
+                    _ = self.timestamps.remove(&task.id());
+                    self.submit_raw_task(task);
+                }
+            }
+        }
+    }
+
+    /// Returns the timestamp when this pool has coroutines to resume or tasks due next,
+    /// `u64::MAX` means it's idle until someone submits a task or wakes up a coroutine.
+    pub(crate) fn next_schedule_time(&self) -> u64 {
+        let delayed = self
+            .delayed
+            .lock()
+            .expect("lock failed")
+            .front()
+            .map_or(u64::MAX, |(timestamp, _)| *timestamp);
+        self.workers.next_schedule_time().min(delayed)
+    }
+
+    /// Submit `func` as a new task once the task with `task_id` completes, `func` receives
+    /// its result instead of the joiners, so nothing blocks in between. The new task is
+    /// submitted by the pool which runs the task with `task_id`.
+    ///
+    /// The result of the new task is not saved if `detached` is `true`.
+    ///
+    /// Returns the id of the new task.
+    ///
+    /// # Errors
+    /// if the result of the task with `task_id` has been joined, or it will never be saved.
+    pub fn submit_continuation(
+        &self,
+        task_id: TaskId,
+        func: impl FnOnce(Result<Option<usize>, &'p str>) -> Result<Option<usize>, &'p str> + 'p,
+        detached: bool,
+    ) -> std::io::Result<TaskId> {
+        let previous = ResultSlot::default();
+        let slot = previous.clone();
+        let mut task = Task::fallible(
+            format!("{}@{}", self.name(), uuid::Uuid::new_v4()),
+            move |_| func(slot.take().expect("no previous result")),
+            None,
+            TaskPriority::default(),
+        );
+        task.set_store(Arc::downgrade(&self.store));
+        if detached {
+            task.detach();
+        }
+        let continuation_id = task.id();
+        let name = task.name().to_string();
+        self.broadcast("on_submit", |listener| listener.on_submit(&name));
+        let guard = PreemptionGuard::new();
+        self.mark_queued(&task);
+        let task = match self.store.add_continuation(task_id, task, previous) {
+            Ok(Some(task)) => task,
+            Ok(None) => return Ok(continuation_id),
+            Err(e) => {
+                //前置任务的结果已被取走或者不会保存，后续任务永远不会执行
+                _ = TASK_STATES.remove(&continuation_id);
+                _ = self.cancellers.remove(&continuation_id);
+                self.store.forget(continuation_id);
+                drop(guard);
+                self.broadcast("on_reject", |listener| listener.on_reject(&name));
+                return Err(e);
+            }
+        };
+        drop(guard);
+        self.submit_raw_task(task);
+        Ok(continuation_id)
+    }
+
+    /// Cancel the task with the given `task_id`.
+    ///
+    /// A queued task will never run, its place in the bounded queue and its closure are
+    /// given up and its result is saved immediately, and a delayed task is removed from
+    /// the timers. A running task is
+    /// notified by its next hooked syscall, which fails with `ECANCELED`, and its result
+    /// is saved when it finishes. Either way, the result is [`TASK_CANCELLED`].
+    ///
+    /// Returns `false` if the task has finished or been cancelled.
+    pub fn cancel_task(&self, task_id: TaskId) -> bool {
+        let guard = PreemptionGuard::new();
+        let queued = match TASK_STATES.entry(task_id) {
+            Entry::Occupied(mut entry) => match entry.insert(TaskState::Cancelled) {
+                TaskState::Queued => true,
+                TaskState::Running => false,
+                TaskState::Cancelled => return false,
+            },
+            Entry::Vacant(_) => return false,
+        };
+        if queued {
+            //立即释放排队任务的名额和闭包，任务本身在出队时丢弃
+            if let Some((_, canceller)) = self.cancellers.remove(&task_id) {
+                canceller.cancel();
+                self.forget_name(task_id, canceller.name());
+            }
+            self.save_task_result(&self.store, task_id, Err(TASK_CANCELLED));
+            //延迟执行的任务不必等到期，直接从定时器中移除
+            let delayed = self.timestamps.remove(&task_id).and_then(|(_, timestamp)| {
+                self.delayed
+                    .lock()
+                    .expect("lock failed")
+                    .remove_first(&timestamp, |task| task.id() == task_id)
+            });
+            if let Some(task) = delayed {
+                _ = TASK_STATES.remove(&task_id);
+                drop(guard);
+                let wait_time = now().saturating_sub(task.create_time());
+                self.broadcast("on_cancel", |listener| {
+                    listener.on_cancel(task.name(), wait_time, 0);
+                });
+            }
+        }
+        true
+    }
+
+    /// Cancel the unfinished task with the given `name`, see [`CoroutinePool::cancel_task`].
+    pub fn cancel_task_by_name(&self, name: &str) -> bool {
+        let task_id = {
+            let _guard = PreemptionGuard::new();
+            self.names.get(name).map(|id| *id)
+        };
+        task_id.is_some_and(|task_id| self.cancel_task(task_id))
+    }
+
+    /// Returns `true` if the task running in the current coroutine has been cancelled.
+    #[must_use]
+    pub(crate) fn current_task_cancelled() -> bool {
+        let Some(task_id) =
+            SchedulableCoroutine::current().and_then(|co| co.get::<TaskId>(CURRENT_TASK).copied())
+        else {
+            return false;
+        };
+        let _guard = PreemptionGuard::new();
+        TASK_STATES
+            .get(&task_id)
+            .is_some_and(|state| TaskState::Cancelled == *state)
+    }
+
+    fn try_acquire(&self) -> Option<QueuePermit> {
+        QueuePermit::try_acquire(&self.slots, self.get_queue_capacity())
+    }
+
+    /// Handle the task which can't be put into the full task queue.
+    ///
+    /// Returns the task if it still should be submitted.
+    fn reject(&self, mut task: Task<'p>) -> std::io::Result<Option<Task<'p>>> {
+        let mut policy = self.get_reject_policy();
+        //本池的协程或调度线程等待名额时，可能占住了腾出名额所需的worker，改为由调用者直接执行
+        if RejectPolicy::Block == policy
+            && Self::current().is_some_and(|current| {
+                std::ptr::eq(
+                    std::ptr::from_ref(current).cast::<()>(),
+                    std::ptr::from_ref(self).cast(),
+                )
+            })
+        {
+            policy = RejectPolicy::CallerRuns;
+        }
+        match policy {
+            RejectPolicy::Abort => {}
+            //同一key的前一个任务未完成时，不能由调用者直接执行
+            RejectPolicy::CallerRuns => {
+                if self.try_claim_key(&task) {
+                    {
+                        let _guard = PreemptionGuard::new();
+                        _ = TASK_STATES.insert(task.id(), TaskState::Running);
+                        self.expect_result(&task);
+                    }
+                    self.run_task(task);
+                    return Ok(None);
+                }
+            }
+            RejectPolicy::DiscardOldest => loop {
+                if let Some(permit) = self.try_acquire() {
+                    task.set_permit(permit);
+                    return Ok(Some(task));
+                }
+                let oldest = {
+                    let _guard = PreemptionGuard::new();
+                    //只丢弃本线程池排队的任务，不从其他队列窃取
+                    self.task_queue.pop_oldest()
+                };
+                let Some(mut oldest) = oldest else {
+                    //占用名额的任务已被其他线程池窃取，无任务可丢弃
+                    break;
+                };
+                let task_id = oldest.id();
+                let guard = PreemptionGuard::new();
+                _ = self.cancellers.remove(&task_id);
+                self.forget_name(task_id, oldest.name());
+                //已取消的任务在取消时已经保存了结果
+                let discarded = TASK_STATES
+                    .remove(&task_id)
+                    .is_some_and(|(_, state)| TaskState::Cancelled != state);
+                drop(guard);
+                let key = oldest.key();
+                let group = oldest.take_group();
+                if discarded {
+                    _ = self.rejected.fetch_add(1, Ordering::Release);
+                    self.broadcast("on_reject", |listener| listener.on_reject(oldest.name()));
+                    let store = self.store_of(&oldest);
+                    drop(oldest);
+                    if let Some(store) = store {
+                        self.save_task_result(&store, task_id, Err("The task was discarded !"));
+                    }
+                }
+                self.release_key(key);
+                self.leave_group(group.as_deref());
+            },
+            RejectPolicy::Block => {
+                let waiter = Waiter::current();
+                let timeout_time = get_timeout_time(Duration::from_nanos(self.get_block_timeout()));
+                let permit = loop {
+                    //先登记再申请名额，避免错过登记前归还的名额
+                    self.slots.add_waiter(&waiter);
+                    if let Some(permit) = self.try_acquire() {
+                        break Ok(Some(permit));
+                    }
+                    if PoolState::Running != self.state() || now() >= timeout_time {
+                        break Ok(None);
+                    }
+                    //等待任务出队归还名额、线程池停止或者超时
+                    if let Err(e) = waiter.wait(timeout_time) {
+                        break Err(e);
+                    }
+                };
+                self.slots.remove_waiter(&waiter);
+                if let Some(permit) = permit? {
+                    task.set_permit(permit);
+                    return Ok(Some(task));
+                }
+            }
+        }
+        _ = self.rejected.fetch_add(1, Ordering::Release);
+        {
+            let _guard = PreemptionGuard::new();
+            self.forget_name(task.id(), task.name());
+        }
+        self.broadcast("on_reject", |listener| listener.on_reject(task.name()));
+        self.leave_group(task.group());
+        Err(Error::new(
+            ErrorKind::Other,
+            format!("The task {} was rejected !", task.name()),
+        ))
     }
 
     /// Submit new task to this pool.
@@ -230,66 +1088,213 @@ impl<'p> CoroutinePool<'p> {
     /// Allow multiple threads to concurrently submit task to the pool,
     /// but only allow one thread to execute scheduling.
     pub(crate) fn submit_raw_task(&self, task: Task<'p>) {
-        self.task_queue.push_back(task);
-        self.blocker.notify();
+        let _guard = PreemptionGuard::new();
+        if let Some(key) = task.key() {
+            match self.keys.entry(key) {
+                //同一key的前一个任务未完成，排在它后面
+                Entry::Occupied(mut entry) => return entry.get_mut().push_back(task),
+                Entry::Vacant(entry) => _ = entry.insert(VecDeque::new()),
+            }
+        }
+        self.push_task(task);
+    }
+
+    /// Returns `true` if the task is not keyed, or no task with the same key is
+    /// unfinished, then the key is occupied until the task is finished.
+    fn try_claim_key(&self, task: &Task<'p>) -> bool {
+        let Some(key) = task.key() else {
+            return true;
+        };
+        let _guard = PreemptionGuard::new();
+        match self.keys.entry(key) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                _ = entry.insert(VecDeque::new());
+                true
+            }
+        }
+    }
+
+    /// The task with the `key` is finished, run the next one with the same key.
+    fn release_key(&self, key: Option<u64>) {
+        let Some(key) = key else {
+            return;
+        };
+        let _guard = PreemptionGuard::new();
+        let Entry::Occupied(mut entry) = self.keys.entry(key) else {
+            return;
+        };
+        if let Some(next) = entry.get_mut().pop_front() {
+            drop(entry);
+            self.push_task(next);
+        } else {
+            _ = entry.remove();
+        }
+    }
+
+    /// Returns the task if it's not grouped, or its group has room for it to run now.
+    fn enter_group(&self, task: Task<'p>) -> std::io::Result<Option<Task<'p>>> {
+        let Some(group) = task.group() else {
+            return Ok(Some(task));
+        };
+        let task_id = task.id();
+        let guard = PreemptionGuard::new();
+        let Some(mut group) = self.groups.get_mut(group) else {
+            return Ok(Some(task));
+        };
+        if !group.is_full() {
+            let canceller = task.canceller();
+            let detached = task.is_detached();
+            let attempts = task.attempts().cloned();
+            let task = group.enter(task);
+            if task.is_none() {
+                _ = TASK_STATES.insert(task_id, TaskState::Queued);
+                _ = self.cancellers.insert(task_id, canceller);
+                if !detached {
+                    self.store.expect(task_id, attempts.as_ref());
+                }
+            }
+            return Ok(task);
+        }
+        drop(group);
+        self.forget_name(task_id, task.name());
+        drop(guard);
+        _ = self.rejected.fetch_add(1, Ordering::Release);
+        self.broadcast("on_reject", |listener| listener.on_reject(task.name()));
+        Err(Error::new(
+            ErrorKind::Other,
+            format!(
+                "The task {} was rejected by the full group {} !",
+                task.name(),
+                task.group().unwrap_or_default()
+            ),
+        ))
+    }
+
+    /// A task of the `group` is finished, run the waiting tasks of the group.
+    fn leave_group(&self, group: Option<&str>) {
+        let Some(group) = group else {
+            return;
+        };
+        let _guard = PreemptionGuard::new();
+        let ready = match self.groups.get_mut(group) {
+            Some(mut group) => group.leave(),
+            None => return,
+        };
+        for task in ready {
+            self.push_task(task);
+        }
+    }
+
+    fn push_task(&self, task: Task<'p>) {
+        let _guard = PreemptionGuard::new();
+        //按key串行执行或者分组的任务不能被其他线程窃取
+        if task.key().is_some() || task.group().is_some() {
+            self.task_queue.push_pinned(task);
+        } else {
+            self.task_queue.push_back(task);
+        }
+        //直接唤醒一个空闲的worker协程
+        let co_id = self.idle.lock().expect("lock failed").pop_front();
+        if let Some(co_id) = co_id {
+            self.unpark(co_id);
+        }
+    }
+
+    /// Steal the queued tasks from the `victim` pool, and wake up an idle worker to run
+    /// them. Returns `true` if any task is stolen.
+    pub(crate) fn steal_tasks_from(&self, victim: &CoroutinePool<'p>) -> bool {
+        let _guard = PreemptionGuard::new();
+        if !self.task_queue.steal_from(&victim.task_queue) {
+            return false;
+        }
+        let co_id = self.idle.lock().expect("lock failed").pop_front();
+        if let Some(co_id) = co_id {
+            self.unpark(co_id);
+        }
+        true
     }
 
-    /// Attempt to obtain task results with the given `task_name`.
-    pub fn try_get_task_result(&self, task_name: &str) -> Option<Result<Option<usize>, &'p str>> {
-        self.results.remove(task_name).map(|(_, r)| r)
+    /// Returns `true` if the other pools or the global queue have tasks to steal.
+    pub(crate) fn can_steal_tasks(&self) -> bool {
+        self.task_queue.can_steal()
     }
 
-    /// Use the given `task_name` to obtain task results, and if no results are found,
+    /// Attempt to obtain task results with the given `task_id`.
+    pub fn try_get_task_result(&self, task_id: TaskId) -> Option<Result<Option<usize>, &'p str>> {
+        self.try_get_task_attempts(task_id).map(|(r, _)| r)
+    }
+
+    /// Like [`CoroutinePool::try_get_task_result`], but also returns the runs of the task
+    /// submitted by [`CoroutinePool::submit_task_with_retry`], which are empty for the
+    /// other tasks.
+    #[allow(clippy::type_complexity)]
+    pub fn try_get_task_attempts(
+        &self,
+        task_id: TaskId,
+    ) -> Option<(Result<Option<usize>, &'p str>, Vec<Attempt<'p>>)> {
+        self.store.take(task_id)
+    }
+
+    /// Like [`CoroutinePool::try_get_task_attempts`], but fails if the result will never
+    /// be available, for example it has been joined, evicted or detached.
+    pub(crate) fn try_take_task_result(
+        &self,
+        task_id: TaskId,
+    ) -> std::io::Result<Option<TaskResult<'p>>> {
+        self.store.try_take(task_id)
+    }
+
+    /// Put back the result taken by [`CoroutinePool::try_get_task_attempts`], so it can
+    /// be joined again.
+    pub(crate) fn put_back_task_result(&self, task_id: TaskId, result: TaskResult<'p>) {
+        self.store.put_back(task_id, result);
+    }
+
+    /// Nobody cares about the result of the task with the given `task_id` any more,
+    /// drop its result if it has finished, otherwise its result will not be saved.
+    pub fn detach_task(&self, task_id: TaskId) {
+        self.store.detach(task_id);
+    }
+
+    /// Use the given `task_id` to obtain task results, and if no results are found,
     /// block the current thread for `wait_time`.
     ///
+    /// Inside a coroutine, the current coroutine is suspended instead of the thread,
+    /// and it's woken up by the pool once the result is saved, or by the scheduler
+    /// once `wait_time` passes.
+    ///
     /// # Errors
-    /// if timeout
+    /// if timeout, or the result has been joined, evicted or detached.
     pub fn wait_task_result(
         &self,
-        task_name: &str,
+        task_id: TaskId,
         wait_time: Duration,
     ) -> std::io::Result<Result<Option<usize>, &str>> {
-        let key = Box::leak(Box::from(task_name));
-        if let Some(r) = self.try_get_task_result(key) {
-            self.notify(key);
-            drop(self.waits.remove(key));
-            return Ok(r);
-        }
-        if SchedulableCoroutine::current().is_some() {
-            let timeout_time = get_timeout_time(wait_time);
-            loop {
-                _ = self.try_run();
-                if let Some(r) = self.try_get_task_result(key) {
-                    return Ok(r);
-                }
-                if timeout_time.saturating_sub(now()) == 0 {
-                    return Err(Error::new(ErrorKind::TimedOut, "wait timeout"));
-                }
-            }
-        }
-        let arc = if let Some(arc) = self.waits.get(key) {
-            arc.clone()
-        } else {
-            let arc = Arc::new((Mutex::new(true), Condvar::new()));
-            assert!(self.waits.insert(key, arc.clone()).is_none());
-            arc
-        };
-        let (lock, cvar) = &*arc;
-        drop(
-            cvar.wait_timeout_while(
-                lock.lock()
-                    .map_err(|e| Error::new(ErrorKind::Other, format!("{e}")))?,
-                wait_time,
-                |&mut pending| pending,
-            )
-            .map_err(|e| Error::new(ErrorKind::Other, format!("{e}")))?,
-        );
-        if let Some(r) = self.try_get_task_result(key) {
-            self.notify(key);
-            assert!(self.waits.remove(key).is_some());
+        self.wait_task_attempts(task_id, wait_time)
+            .map(|(result, _)| result)
+    }
+
+    /// Like [`CoroutinePool::wait_task_result`], but also returns the runs of the task,
+    /// see [`CoroutinePool::try_get_task_attempts`].
+    ///
+    /// # Errors
+    /// see [`CoroutinePool::wait_task_result`].
+    #[allow(clippy::type_complexity)]
+    pub fn wait_task_attempts(
+        &self,
+        task_id: TaskId,
+        wait_time: Duration,
+    ) -> std::io::Result<(Result<Option<usize>, &'p str>, Vec<Attempt<'p>>)> {
+        if let Some(r) = self.try_get_task_attempts(task_id) {
             return Ok(r);
         }
-        Err(Error::new(ErrorKind::TimedOut, "wait timeout"))
+        let (mut results, _) =
+            wait_results(&[(self, task_id)], get_timeout_time(wait_time), |_| true)?;
+        Ok(results
+            .pop()
+            .flatten()
+            .expect("the result should be collected"))
     }
 
     fn can_recycle(&self) -> bool {
@@ -310,46 +1315,56 @@ impl<'p> CoroutinePool<'p> {
             return Ok(());
         }
         let create_time = now();
-        self.submit_co(
+        let co_id = self.submit_co(
             move |suspender, ()| {
                 loop {
                     let pool = Self::current().expect("current pool not found");
                     if pool.try_run().is_some() {
-                        pool.reset_pop_fail_times();
                         continue;
                     }
-                    let running = pool.get_running_size();
-                    if now().saturating_sub(create_time) >= pool.get_keep_alive_time()
-                        && running > pool.get_min_size()
-                        || pool.can_recycle()
-                    {
+                    let keep_alive = pool.get_running_size() > pool.get_min_size();
+                    let timeout_time = create_time.saturating_add(pool.get_keep_alive_time());
+                    if keep_alive && now() >= timeout_time || pool.can_recycle() {
                         return None;
                     }
-                    _ = pool.pop_fail_times.fetch_add(1, Ordering::Release);
-                    match pool.pop_fail_times.load(Ordering::Acquire).cmp(&running) {
-                        //让出CPU给下一个协程
-                        std::cmp::Ordering::Less => suspender.suspend(),
-                        //减少CPU在N个无任务的协程中空轮询
-                        std::cmp::Ordering::Equal | std::cmp::Ordering::Greater => {
-                            pool.blocker.clone().block(Duration::from_millis(1));
-                            pool.reset_pop_fail_times();
-                        }
+                    //开启自动扩缩容时，非核心协程空闲一段时间后提前退出
+                    let idle_time = pool.get_autoscaler().map_or(u64::MAX, |autoscaler| {
+                        now().saturating_add(autoscaler.idle_time())
+                    });
+                    //非核心协程最多停车到保活时间结束
+                    let park_time = if keep_alive {
+                        timeout_time.min(idle_time)
+                    } else {
+                        u64::MAX
+                    };
+                    pool.park_idle(suspender, park_time);
+                    if keep_alive
+                        && park_time < timeout_time
+                        && now() >= park_time
+                        && pool.try_shrink()
+                    {
+                        return None;
                     }
                 }
             },
             None,
-        )
+        )?;
+        //worker协程的结果没有人关心
+        self.detach(co_id);
+        Ok(())
     }
 
     /// Try to create a coroutine in this pool.
     ///
+    /// Returns the id of the new coroutine.
+    ///
     /// # Errors
     /// if create failed.
     pub fn submit_co(
         &self,
         f: impl FnOnce(&Suspender<(), ()>, ()) -> Option<usize> + 'static,
         stack_size: Option<usize>,
-    ) -> std::io::Result<()> {
+    ) -> std::io::Result<CoroutineId> {
         if self.get_running_size() >= self.get_max_size() {
             trace!(
                 "The coroutine pool:{} has reached its maximum size !",
@@ -360,32 +1375,223 @@ impl<'p> CoroutinePool<'p> {
                 "The coroutine pool has reached its maximum size !",
             ));
         }
-        self.deref().submit_co(f, stack_size).map(|()| {
+        self.deref().submit_co(f, stack_size).inspect(|_| {
             _ = self.running.fetch_add(1, Ordering::Release);
         })
     }
 
-    fn reset_pop_fail_times(&self) {
-        self.pop_fail_times.store(0, Ordering::Release);
+    /// Park the current coroutine which waits for task results until it's woken up or
+    /// `timeout_time` is reached, it doesn't count in the running size meanwhile, so
+    /// other workers can be created to run the tasks it waits for.
+    pub(crate) fn park_joiner(&self, suspender: &SchedulableSuspender, timeout_time: u64) {
+        //挂起时会尝试扩容，先让出名额，否则达到最大协程数后无人执行被等待的任务
+        _ = self.running.fetch_sub(1, Ordering::Release);
+        self.park(suspender, timeout_time);
+        _ = self.running.fetch_add(1, Ordering::Release);
+    }
+
+    /// Park the current worker until a new task is submitted or `timeout_time` is reached.
+    fn park_idle(&self, suspender: &SchedulableSuspender, timeout_time: u64) {
+        let Some(co_id) = SchedulableCoroutine::current().map(SchedulableCoroutine::id) else {
+            return;
+        };
+        {
+            let _guard = PreemptionGuard::new();
+            self.idle.lock().expect("lock failed").push_back(co_id);
+        }
+        //登记后再检查一次，避免错过登记前提交的任务
+        if self.task_queue.is_empty() && !self.can_recycle() {
+            self.park(suspender, timeout_time);
+        }
+        let _guard = PreemptionGuard::new();
+        self.idle
+            .lock()
+            .expect("lock failed")
+            .retain(|id| *id != co_id);
+    }
+
+    /// Wake up all the idle workers, it's used when stopping this pool.
+    pub(crate) fn wake_idle_workers(&self) {
+        let _guard = PreemptionGuard::new();
+        let idle: Vec<CoroutineId> = self.idle.lock().expect("lock failed").drain(..).collect();
+        for co_id in idle {
+            self.unpark(co_id);
+        }
     }
 
     fn try_run(&self) -> Option<()> {
-        self.task_queue.pop_front().map(|task| {
-            let (task_name, result) = task.run();
-            assert!(
-                self.results.insert(task_name.clone(), result).is_none(),
-                "The previous result was not retrieved in a timely manner"
-            );
-            self.notify(&task_name);
-        })
+        loop {
+            let task = {
+                let _guard = PreemptionGuard::new();
+                self.task_queue.pop_front(self.get_aging_time())
+            }?;
+            let guard = PreemptionGuard::new();
+            _ = self.cancellers.remove(&task.id());
+            let cancelled = match TASK_STATES.entry(task.id()) {
+                Entry::Occupied(mut entry) => {
+                    if TaskState::Cancelled == *entry.get() {
+                        _ = entry.remove();
+                        true
+                    } else {
+                        _ = entry.insert(TaskState::Running);
+                        false
+                    }
+                }
+                Entry::Vacant(_) => false,
+            };
+            drop(guard);
+            let wait_time = now().saturating_sub(task.create_time());
+            if !cancelled {
+                self.wait_time.store(wait_time, Ordering::Release);
+                self.run_task(task);
+                return Some(());
+            }
+            //已取消的任务直接丢弃，结果在取消时已经保存
+            self.forget_name(task.id(), task.name());
+            self.broadcast("on_cancel", |listener| {
+                listener.on_cancel(task.name(), wait_time, 0);
+            });
+            self.release_key(task.key());
+            self.leave_group(task.group());
+        }
+    }
+
+    fn run_task(&self, mut task: Task<'p>) {
+        let task_id = task.id();
+        let detached = task.is_detached();
+        let key = task.key();
+        let group = task.take_group();
+        let repeat = task.take_repeat();
+        let retry = task.take_retry();
+        //重试任务的执行记录保存在任务自身，无论在哪个线程池执行
+        let attempts = task.attempts().cloned();
+        //任务可能是从其他线程池窃取的，结果保存到提交任务的线程池
+        let owner = task.store().cloned();
+        let store = self.store_of(&task);
+        //记录当前协程正在执行的任务，系统调用据此检查任务是否已被取消
+        let previous = SchedulableCoroutine::current().and_then(|co| co.put(CURRENT_TASK, task_id));
+        let start = now();
+        let wait_time = start.saturating_sub(task.create_time());
+        self.broadcast("on_start", |listener| {
+            listener.on_start(task.name(), wait_time);
+        });
+        let (task_name, result) = task.run();
+        let run_time = now().saturating_sub(start);
+        //执行任务期间协程可能已被移动，需要重新获取
+        if let Some(co) = SchedulableCoroutine::current() {
+            if let Some(previous) = previous {
+                _ = co.put(CURRENT_TASK, previous);
+            } else {
+                _ = co.remove::<TaskId>(CURRENT_TASK);
+            }
+        }
+        if let Some(attempts) = attempts {
+            attempts.lock().expect("lock failed").push(Attempt {
+                start_time: start,
+                run_time,
+                result,
+            });
+        }
+        //周期任务执行成功或重试任务执行失败时，安排下一次执行
+        let next = match &result {
+            Ok(_) => repeat.map(|repeat| repeat()),
+            Err(error) => retry.and_then(|retry| retry(error)),
+        };
+        let guard = PreemptionGuard::new();
+        if let Some((timestamp, mut next)) = next {
+            if let Some(owner) = owner {
+                next.set_store(owner);
+            }
+            if let Some(mut state) = TASK_STATES.get_mut(&task_id) {
+                if TaskState::Running == *state {
+                    *state = TaskState::Queued;
+                    drop(state);
+                    drop(guard);
+                    self.on_task_done(&task_name, wait_time, run_time, &result);
+                    _ = self.delay_task(timestamp, next);
+                    return;
+                }
+            }
+        }
+        self.forget_name(task_id, &task_name);
+        let result = match TASK_STATES.remove(&task_id) {
+            Some((_, TaskState::Cancelled)) => Err(TASK_CANCELLED),
+            _ => result,
+        };
+        drop(guard);
+        self.on_task_done(&task_name, wait_time, run_time, &result);
+        if let (false, Some(store)) = (detached, store) {
+            self.save_task_result(&store, task_id, result);
+        }
+        self.release_key(key);
+        self.leave_group(group.as_deref());
+    }
+
+    fn forget_name(&self, task_id: TaskId, task_name: &str) {
+        _ = self.names.remove_if(task_name, |_, id| *id == task_id);
+    }
+
+    /// The task is queued until it runs, it can be cancelled in the meantime.
+    fn mark_queued(&self, task: &Task<'p>) {
+        _ = TASK_STATES.insert(task.id(), TaskState::Queued);
+        _ = self.cancellers.insert(task.id(), task.canceller());
+        self.expect_result(task);
+    }
+
+    /// The result of the task will be saved, unless it's detached.
+    fn expect_result(&self, task: &Task<'p>) {
+        if task.is_detached() {
+            return;
+        }
+        if let Some(store) = self.store_of(task) {
+            store.expect(task.id(), task.attempts());
+        }
+    }
+
+    /// Get where the result of the task is saved, `None` means the pool which the task
+    /// was submitted to has been dropped.
+    fn store_of(&self, task: &Task<'p>) -> Option<Arc<TaskStore<'p>>> {
+        task.store()
+            .map_or_else(|| Some(self.store.clone()), Weak::upgrade)
+    }
+
+    /// Save the result into the `store`, the continuation of the task is submitted to
+    /// this pool.
+    fn save_task_result(
+        &self,
+        store: &TaskStore<'p>,
+        task_id: TaskId,
+        result: Result<Option<usize>, &'p str>,
+    ) {
+        if let Some(continuation) = store.save(task_id, result) {
+            self.submit_raw_task(continuation);
+        }
+    }
+
+    /// Register the `waiter` which is woken up after the result of the task is saved.
+    pub(crate) fn add_waiter(&self, task_id: TaskId, waiter: &Waiter) {
+        self.store.add_waiter(task_id, waiter);
+    }
+
+    /// Remove the `waiter` registered by [`CoroutinePool::add_waiter`].
+    pub(crate) fn remove_waiter(&self, task_id: TaskId, waiter: &Waiter) {
+        self.store.remove_waiter(task_id, waiter);
+    }
+
+    /// Set how to wake up the thread which schedules this pool, it's called after
+    /// a coroutine of this pool is woken up by other threads.
+    pub(crate) fn set_waker(&self, waker: impl Fn() + 'p) {
+        assert!(
+            self.waker.set(Box::new(waker)).is_ok(),
+            "The waker is already set !"
+        );
     }
 
-    fn notify(&self, task_name: &str) {
-        if let Some(arc) = self.waits.get(task_name) {
-            let (lock, cvar) = &**arc;
-            let mut pending = lock.lock().expect("notify task failed");
-            *pending = false;
-            cvar.notify_one();
+    /// Wake up the coroutine which is waiting for task results.
+    pub(crate) fn wake(&self, co_id: CoroutineId) {
+        self.unpark(co_id);
+        if let Some(waker) = self.waker.get() {
+            waker();
         }
     }
 
@@ -432,6 +1638,9 @@ impl<'p> CoroutinePool<'p> {
                 ))
             }
         }
+        self.submit_due_tasks();
+        self.store.evict();
+        self.autoscale();
         Self::init_current(self);
         let left_time = self.try_timeout_schedule(timeout_time);
         Self::clean_current();
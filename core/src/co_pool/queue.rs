@@ -0,0 +1,316 @@
+use crate::co_pool::task::{Task, TaskPriority};
+use crate::co_pool::waiter::Waiter;
+use crate::common::beans::BeanFactory;
+use crate::common::now;
+use crate::common::work_steal::{LocalQueue, WorkStealQueue};
+use crate::preempt::PreemptionGuard;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The places of a bounded task queue, the submitters blocked by the full queue
+/// wait here until a place is given back.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub(crate) struct QueueSlots {
+    queued: AtomicUsize,
+    waiters: Mutex<VecDeque<Waiter>>,
+}
+
+impl QueueSlots {
+    /// Register the waiter which is woken up when a place is given back.
+    pub(crate) fn add_waiter(&self, waiter: &Waiter) {
+        let _guard = PreemptionGuard::new();
+        let mut waiters = self.waiters.lock().expect("lock failed");
+        if !waiters.iter().any(|w| w.is(waiter)) {
+            waiters.push_back(waiter.clone());
+        }
+    }
+
+    pub(crate) fn remove_waiter(&self, waiter: &Waiter) {
+        let _guard = PreemptionGuard::new();
+        self.waiters
+            .lock()
+            .expect("lock failed")
+            .retain(|w| !w.is(waiter));
+    }
+
+    /// Wake up all the waiters, for example when the pool is stopping.
+    pub(crate) fn wake_all(&self) {
+        let waiters = {
+            let _guard = PreemptionGuard::new();
+            std::mem::take(&mut *self.waiters.lock().expect("lock failed"))
+        };
+        for waiter in waiters {
+            waiter.wake();
+        }
+    }
+}
+
+/// A place in a bounded task queue, it's given back when the task leaves the queue.
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct QueuePermit(Arc<QueueSlots>);
+
+impl QueuePermit {
+    /// Take a place if less than `capacity` are taken, `0` means unbounded.
+    pub(crate) fn try_acquire(slots: &Arc<QueueSlots>, capacity: usize) -> Option<Self> {
+        slots
+            .queued
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (0 == capacity || n < capacity).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| QueuePermit(slots.clone()))
+    }
+}
+
+impl Drop for QueuePermit {
+    fn drop(&mut self) {
+        _ = self.0.queued.fetch_sub(1, Ordering::AcqRel);
+        let waiter = {
+            let _guard = PreemptionGuard::new();
+            self.0.waiters.lock().expect("lock failed").pop_front()
+        };
+        if let Some(waiter) = waiter {
+            waiter.wake();
+        }
+    }
+}
+
+/// The task queues of a coroutine pool, one work stealing queue per priority.
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct PriorityQueue<'q> {
+    queues: [LocalQueue<'q, Task<'q>>; TaskPriority::COUNT],
+    //各优先级队列的队首，用于计算等待时间
+    heads: Mutex<[Option<Task<'q>>; TaskPriority::COUNT]>,
+    //不会被其他队列窃取的任务，比如按key串行执行的任务
+    pinned: Mutex<VecDeque<Task<'q>>>,
+}
+
+impl<'q> PriorityQueue<'q> {
+    pub(crate) fn new() -> Self {
+        Self::with_shared(BeanFactory::get_or_default(
+            crate::common::constants::TASK_GLOBAL_QUEUE_BEAN,
+        ))
+    }
+
+    fn with_shared(shared: &'q [WorkStealQueue<Task<'q>>; TaskPriority::COUNT]) -> Self {
+        PriorityQueue {
+            queues: std::array::from_fn(|index| shared[index].local_queue()),
+            heads: Mutex::default(),
+            pinned: Mutex::default(),
+        }
+    }
+
+    /// Returns `true` if there is no task in the local queues.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of tasks in the local queues.
+    pub(crate) fn len(&self) -> usize {
+        let heads = self
+            .heads
+            .lock()
+            .expect("lock failed")
+            .iter()
+            .filter(|head| head.is_some())
+            .count();
+        let pinned = self.pinned.lock().expect("lock failed").len();
+        self.queues.iter().map(LocalQueue::len).sum::<usize>() + heads + pinned
+    }
+
+    pub(crate) fn push_back(&self, task: Task<'q>) {
+        self.queues[task.priority() as usize].push_back(task);
+    }
+
+    /// Push a task which can't be stolen by other queues.
+    pub(crate) fn push_pinned(&self, task: Task<'q>) {
+        self.pinned.lock().expect("lock failed").push_back(task);
+    }
+
+    /// Steal the tasks of every priority from the `other` queue, the pinned tasks are
+    /// never stolen. Returns `true` if any task is stolen.
+    pub(crate) fn steal_from(&self, other: &PriorityQueue<'q>) -> bool {
+        let mut stolen = false;
+        for (queue, other) in self.queues.iter().zip(&other.queues) {
+            stolen |= queue.steal_from(other);
+        }
+        stolen
+    }
+
+    /// Returns `true` if other queues have tasks of any priority to steal.
+    pub(crate) fn can_steal(&self) -> bool {
+        self.queues.iter().any(LocalQueue::can_steal)
+    }
+
+    /// Pop the oldest task in the local queues, it never steals from other queues.
+    pub(crate) fn pop_oldest(&self) -> Option<Task<'q>> {
+        let mut heads = self.heads.lock().expect("lock failed");
+        for (head, queue) in heads.iter_mut().zip(&self.queues) {
+            if head.is_none() {
+                *head = queue.pop_local();
+            }
+        }
+        let head = heads
+            .iter()
+            .enumerate()
+            .filter_map(|(index, head)| head.as_ref().map(|task| (task.create_time(), index)))
+            .min();
+        let mut pinned = self.pinned.lock().expect("lock failed");
+        let pinned_head = pinned.front().map(Task::create_time);
+        let task = match (head, pinned_head) {
+            (Some((head, _)), Some(pinned_head)) if pinned_head < head => pinned.pop_front(),
+            (None, Some(_)) => pinned.pop_front(),
+            (Some((_, index)), _) => heads[index].take(),
+            (None, None) => None,
+        };
+        task.map(|mut task| {
+            task.release();
+            task
+        })
+    }
+
+    /// Pop the task with the highest priority, a task is promoted by one priority
+    /// every `aging_time` ns it waits, so the low priority tasks will not starve.
+    /// `0` means never promote.
+    ///
+    /// Only when there is no task with higher priority, steal the lower ones from
+    /// other queues.
+    pub(crate) fn pop_front(&self, aging_time: u64) -> Option<Task<'q>> {
+        let mut heads = self.heads.lock().expect("lock failed");
+        let mut higher = false;
+        for (head, queue) in heads.iter_mut().zip(&self.queues) {
+            if head.is_none() && (!higher || !queue.is_empty()) {
+                *head = queue.pop_front();
+            }
+            higher |= head.is_some();
+        }
+        let now = now();
+        let level = |index: usize, task: &Task<'q>| {
+            let waited = now.saturating_sub(task.create_time());
+            let promoted = waited.checked_div(aging_time).unwrap_or(0);
+            let level = u64::try_from(index)
+                .expect("overflow")
+                .saturating_sub(promoted);
+            (level, task.create_time())
+        };
+        let head = heads
+            .iter()
+            .enumerate()
+            .filter_map(|(index, head)| head.as_ref().map(|task| (level(index, task), index)))
+            .min();
+        let mut pinned = self.pinned.lock().expect("lock failed");
+        let pinned_head = pinned
+            .front()
+            .map(|task| level(task.priority() as usize, task));
+        let task = match (head, pinned_head) {
+            (Some((head, _)), Some(pinned_head)) if pinned_head < head => pinned.pop_front(),
+            (None, Some(_)) => pinned.pop_front(),
+            (Some((_, index)), _) => heads[index].take(),
+            (None, None) => None,
+        };
+        task.map(|mut task| {
+            task.release();
+            task
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_priority_and_aging() {
+        let queue = PriorityQueue::with_shared(Box::leak(Box::default()));
+        let task = |name: &str, priority| Task::new(String::from(name), |p| p, None, priority);
+        queue.push_back(task("low", TaskPriority::Low));
+        queue.push_back(task("normal", TaskPriority::Normal));
+        queue.push_back(task("high", TaskPriority::High));
+        assert_eq!(3, queue.len());
+        assert_eq!("high", queue.pop_front(0).expect("no task").name());
+        assert_eq!("normal", queue.pop_front(0).expect("no task").name());
+        queue.push_back(task("high", TaskPriority::High));
+        assert_eq!("high", queue.pop_front(0).expect("no task").name());
+        assert_eq!("low", queue.pop_front(0).expect("no task").name());
+        assert!(queue.is_empty());
+
+        // the low priority task waits long enough to overtake the newer ones
+        let aging_time = 10_000_000;
+        queue.push_back(task("low", TaskPriority::Low));
+        std::thread::sleep(Duration::from_millis(30));
+        queue.push_back(task("high", TaskPriority::High));
+        queue.push_back(task("normal", TaskPriority::Normal));
+        assert_eq!("low", queue.pop_front(aging_time).expect("no task").name());
+        assert_eq!("high", queue.pop_front(aging_time).expect("no task").name());
+        assert_eq!(
+            "normal",
+            queue.pop_front(aging_time).expect("no task").name()
+        );
+        assert_eq!(None, queue.pop_front(aging_time).map(|task| task.id()));
+    }
+
+    #[test]
+    fn test_pinned() {
+        let shared = Box::leak(Box::default());
+        let queue = PriorityQueue::with_shared(shared);
+        let other = PriorityQueue::with_shared(shared);
+        let task = |name: &str, priority| Task::new(String::from(name), |p| p, None, priority);
+        queue.push_back(task("low", TaskPriority::Low));
+        queue.push_pinned(task("pinned", TaskPriority::Normal));
+        assert_eq!(2, queue.len());
+        // the pinned task is never stolen
+        assert_eq!("low", other.pop_front(0).expect("no task").name());
+        assert_eq!(None, other.pop_front(0).map(|task| task.id()));
+        assert_eq!("pinned", queue.pop_front(0).expect("no task").name());
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_steal_from() {
+        let shared = Box::leak(Box::new(std::array::from_fn(|_| {
+            WorkStealQueue::new(3, 64)
+        })));
+        let queue = PriorityQueue::with_shared(shared);
+        let busy = PriorityQueue::with_shared(shared);
+        let other = PriorityQueue::with_shared(shared);
+        let task = |name: &str, priority| Task::new(String::from(name), |p| p, None, priority);
+        other.push_back(task("other", TaskPriority::Low));
+        busy.push_back(task("high", TaskPriority::High));
+        busy.push_back(task("low", TaskPriority::Low));
+        busy.push_pinned(task("pinned", TaskPriority::Normal));
+        // only steal from the busy queue, and never the pinned tasks
+        assert!(queue.steal_from(&busy));
+        assert_eq!(2, queue.len());
+        assert_eq!(1, busy.len());
+        assert_eq!(1, other.len());
+        assert!(!queue.steal_from(&busy));
+        assert_eq!("high", queue.pop_oldest().expect("no task").name());
+        assert_eq!("low", queue.pop_oldest().expect("no task").name());
+        assert_eq!("pinned", busy.pop_oldest().expect("no task").name());
+        assert_eq!("other", other.pop_oldest().expect("no task").name());
+    }
+
+    #[test]
+    fn test_pop_oldest() {
+        let shared = Box::leak(Box::new(std::array::from_fn(|_| {
+            WorkStealQueue::new(2, 64)
+        })));
+        let queue = PriorityQueue::with_shared(shared);
+        let other = PriorityQueue::with_shared(shared);
+        let task = |name: &str, priority| Task::new(String::from(name), |p| p, None, priority);
+        other.push_back(task("other", TaskPriority::Low));
+        // never steal from other queues
+        assert_eq!(None, queue.pop_oldest().map(|task| task.id()));
+        queue.push_back(task("low", TaskPriority::Low));
+        queue.push_back(task("high", TaskPriority::High));
+        assert_eq!("low", queue.pop_oldest().expect("no task").name());
+        assert_eq!("high", queue.pop_oldest().expect("no task").name());
+        assert_eq!(None, queue.pop_oldest().map(|task| task.id()));
+        assert_eq!("other", other.pop_oldest().expect("no task").name());
+    }
+}
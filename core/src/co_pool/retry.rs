@@ -0,0 +1,227 @@
+use crate::co_pool::task::{Task, TaskId, TaskPriority};
+use crate::common::now;
+use derivative::Derivative;
+use rand::Rng;
+use std::sync::{Arc, Mutex};
+
+/// One run of a task submitted with a [`RetryPolicy`].
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Attempt<'a> {
+    /// When the run started, in ns.
+    pub start_time: u64,
+    /// How long the run took, in ns.
+    pub run_time: u64,
+    /// The result of the run.
+    pub result: Result<Option<usize>, &'a str>,
+}
+
+/// The runs of a task submitted with a [`RetryPolicy`], shared by all its runs wherever
+/// they run.
+pub(crate) type Attempts<'a> = Arc<Mutex<Vec<Attempt<'a>>>>;
+
+/// Decide whether and when a failed task runs again.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct RetryPolicy {
+    //最多执行的次数，包括第一次
+    max_attempts: usize,
+    //第一次重试前的等待时间，单位ns
+    backoff: u64,
+    //每次重试后等待时间的倍数
+    multiplier: u32,
+    //等待时间的上限，单位ns
+    max_backoff: u64,
+    //等待时间随机减少的比例，单位%
+    jitter: u64,
+    //返回true的错误才重试
+    retryable: fn(&str) -> bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            backoff: 10_000_000,
+            multiplier: 2,
+            max_backoff: 1_000_000_000,
+            jitter: 20,
+            retryable: |_| true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Get the maximum number of runs, including the first one.
+    #[must_use]
+    pub fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    /// Get the delay in ns before the first retry.
+    #[must_use]
+    pub fn backoff(&self) -> u64 {
+        self.backoff
+    }
+
+    /// Get the factor by which the delay grows after each retry.
+    #[must_use]
+    pub fn multiplier(&self) -> u32 {
+        self.multiplier
+    }
+
+    /// Get the upper limit of the delay in ns.
+    #[must_use]
+    pub fn max_backoff(&self) -> u64 {
+        self.max_backoff
+    }
+
+    /// Get the percentage by which the delay is randomly reduced.
+    #[must_use]
+    pub fn jitter(&self) -> u64 {
+        self.jitter
+    }
+
+    /// Set the maximum number of runs, including the first one.
+    pub fn set_max_attempts(&mut self, max_attempts: usize) -> &mut Self {
+        assert!(max_attempts > 0, "max_attempts must be greater than 0");
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Set the delay before the first retry, `backoff` has `ns` units.
+    pub fn set_backoff(&mut self, backoff: u64) -> &mut Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Set the factor by which the delay grows after each retry, `1` means a fixed delay.
+    pub fn set_multiplier(&mut self, multiplier: u32) -> &mut Self {
+        assert!(multiplier > 0, "multiplier must be greater than 0");
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Set the upper limit of the delay, `max_backoff` has `ns` units.
+    pub fn set_max_backoff(&mut self, max_backoff: u64) -> &mut Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Set the percentage by which the delay is randomly reduced, so the failed
+    /// tasks don't retry at the same time.
+    pub fn set_jitter(&mut self, jitter: u64) -> &mut Self {
+        assert!(jitter <= 100, "jitter must be less than or equal to 100");
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the predicate on the error message, only the errors it accepts are retried.
+    pub fn set_retryable(&mut self, retryable: fn(&str) -> bool) -> &mut Self {
+        self.retryable = retryable;
+        self
+    }
+
+    /// Returns the delay in ns before the next run if the `attempt`th run failed
+    /// with the `error` should be retried.
+    pub(crate) fn next_delay(&self, attempt: usize, error: &str) -> Option<u64> {
+        if attempt >= self.max_attempts || !(self.retryable)(error) {
+            return None;
+        }
+        let exponent = u32::try_from(attempt - 1).unwrap_or(u32::MAX);
+        let delay = u64::from(self.multiplier)
+            .checked_pow(exponent)
+            .map_or(self.max_backoff, |factor| {
+                self.backoff.saturating_mul(factor).min(self.max_backoff)
+            });
+        let jitter = rand::thread_rng().gen_range(0..=self.jitter);
+        Some(delay - delay / 100 * jitter)
+    }
+}
+
+/// The runs of a task with a [`RetryPolicy`], every run is a new task with the same id,
+/// and they may be stolen by other pools.
+#[repr(C)]
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub(crate) struct Retry<'p> {
+    id: TaskId,
+    name: String,
+    #[allow(clippy::type_complexity)]
+    #[derivative(Debug = "ignore")]
+    func: Arc<dyn Fn(Option<usize>) -> Result<Option<usize>, &'p str> + 'p>,
+    param: Option<usize>,
+    priority: TaskPriority,
+    policy: RetryPolicy,
+    //已经执行的记录，随结果一起保存
+    #[derivative(Debug = "ignore")]
+    attempts: Attempts<'p>,
+}
+
+impl<'p> Retry<'p> {
+    //各次执行可能被其他线程池窃取
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub(crate) fn new(
+        name: String,
+        func: impl Fn(Option<usize>) -> Result<Option<usize>, &'p str> + 'p,
+        param: Option<usize>,
+        priority: TaskPriority,
+        policy: RetryPolicy,
+    ) -> Self {
+        Retry {
+            id: TaskId::next(),
+            name,
+            func: Arc::new(func),
+            param,
+            priority,
+            policy,
+            attempts: Arc::default(),
+        }
+    }
+
+    /// Create the `attempt`th run, the next run is only created after this one fails.
+    pub(crate) fn task(self: Arc<Self>, attempt: usize) -> Task<'p> {
+        let func = self.func.clone();
+        let mut task = Task::fallible(
+            self.name.clone(),
+            move |param| func(param),
+            self.param,
+            self.priority,
+        )
+        .with_id(self.id);
+        task.set_attempts(self.attempts.clone());
+        task.set_retry(move |error| {
+            let delay = self.policy.next_delay(attempt, error)?;
+            Some((now().saturating_add(delay), self.task(attempt + 1)))
+        });
+        task
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_delay() {
+        let mut policy = RetryPolicy::default();
+        _ = policy
+            .set_max_attempts(4)
+            .set_backoff(10)
+            .set_max_backoff(25)
+            .set_jitter(0);
+        assert_eq!(Some(10), policy.next_delay(1, "error"));
+        assert_eq!(Some(20), policy.next_delay(2, "error"));
+        assert_eq!(Some(25), policy.next_delay(3, "error"));
+        assert_eq!(None, policy.next_delay(4, "error"));
+        _ = policy.set_retryable(|error| error != "fatal");
+        assert_eq!(None, policy.next_delay(1, "fatal"));
+        // the delay is randomly reduced
+        _ = policy
+            .set_max_backoff(1000)
+            .set_backoff(1000)
+            .set_jitter(50);
+        let delay = policy.next_delay(1, "error").expect("should retry");
+        assert!((500..=1000).contains(&delay), "unexpected delay {delay}");
+    }
+}
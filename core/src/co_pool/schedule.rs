@@ -0,0 +1,93 @@
+use crate::co_pool::task::{Task, TaskId, TaskPriority};
+use crate::common::now;
+use derivative::Derivative;
+use std::sync::Arc;
+
+/// The schedule of a periodic task, every run is a new task with the same id.
+#[repr(C)]
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub(crate) struct Periodic<'p> {
+    id: TaskId,
+    name: String,
+    //各次执行可能在不同的线程上
+    #[derivative(Debug = "ignore")]
+    func: Arc<dyn Fn(Option<usize>) -> Option<usize> + 'p>,
+    param: Option<usize>,
+    priority: TaskPriority,
+    //周期，单位ns
+    period: u64,
+    //true表示固定频率，false表示固定间隔
+    fixed_rate: bool,
+}
+
+impl<'p> Periodic<'p> {
+    pub(crate) fn new(
+        name: String,
+        func: impl Fn(Option<usize>) -> Option<usize> + 'p,
+        param: Option<usize>,
+        priority: TaskPriority,
+        period: u64,
+        fixed_rate: bool,
+    ) -> Self {
+        Periodic {
+            id: TaskId::next(),
+            name,
+            func: Arc::new(func),
+            param,
+            priority,
+            period,
+            fixed_rate,
+        }
+    }
+
+    /// Create the run at the `scheduled` timestamp, the next run is only created
+    /// after this one succeeds, so the runs never overlap.
+    pub(crate) fn task(self: Arc<Self>, scheduled: u64) -> Task<'p> {
+        let func = self.func.clone();
+        let mut task = Task::new(
+            self.name.clone(),
+            move |param| func(param),
+            self.param,
+            self.priority,
+        )
+        .with_id(self.id);
+        task.set_repeat(move || {
+            let next = self.next_time(scheduled);
+            (next, self.task(next))
+        });
+        task
+    }
+
+    /// The timestamp of the next run, the periods missed by an overrun are skipped
+    /// instead of piling up.
+    fn next_time(&self, scheduled: u64) -> u64 {
+        let now = now();
+        if !self.fixed_rate {
+            return now.saturating_add(self.period);
+        }
+        let periods = now.saturating_sub(scheduled) / self.period + 1;
+        scheduled.saturating_add(periods.saturating_mul(self.period))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_time() {
+        let periodic = Periodic::new(
+            String::from("test"),
+            |p| p,
+            None,
+            TaskPriority::default(),
+            1_000_000_000,
+            true,
+        );
+        let now = now();
+        assert_eq!(now + 1_000_000_000, periodic.next_time(now));
+        // the overrun skips the missed periods
+        assert_eq!(now + 1_000_000_000, periodic.next_time(now - 2_000_000_000));
+    }
+}
@@ -9,6 +9,11 @@ impl CoroutinePool<'_> {
     /// if change state fails.
     pub(crate) fn stopping(&self) -> std::io::Result<PoolState> {
         self.change_state(PoolState::Running, PoolState::Stopping)
+            .inspect(|_| {
+                self.wake_idle_workers();
+                //唤醒等待任务队列名额的提交者
+                self.slots.wake_all();
+            })
     }
 
     /// stopping -> stopped
@@ -0,0 +1,295 @@
+use crate::co_pool::retry::{Attempt, Attempts};
+use crate::co_pool::task::{ResultSlot, Task, TaskId};
+use crate::co_pool::waiter::{TaskResult, Waiter};
+use crate::common::now;
+use crate::preempt::PreemptionGuard;
+use dashmap::mapref::entry::Entry;
+use dashmap::{DashMap, DashSet};
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// The result of a task, with the time it's saved and the runs of the task.
+type Saved<'s> = (u64, Result<Option<usize>, &'s str>, Vec<Attempt<'s>>);
+
+/// The continuation of a task, and where it receives the result of the task.
+type Continuation<'s> = (Task<'s>, ResultSlot<Result<Option<usize>, &'s str>>);
+
+/// The results of the tasks submitted to a pool.
+///
+/// The tasks may be stolen and run by other pools, so they save their results here
+/// instead of in the pool which runs them, and the joiners of the owner pool are
+/// notified wherever the tasks run.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub(crate) struct TaskStore<'s> {
+    //尚未保存结果的任务，不包括不保存结果和已分离的任务，无论任务在哪个线程池执行
+    pending: DashSet<TaskId>,
+    //正在等待结果的
+    waits: DashMap<TaskId, Vec<Waiter>>,
+    //任务执行结果及其保存时间，以及重试任务每次执行的记录
+    results: DashMap<TaskId, Saved<'s>>,
+    //重试任务执行的记录，由各次执行共用
+    attempts: DashMap<TaskId, Attempts<'s>>,
+    //未取走的结果的保留时间，单位ns，0表示一直保留
+    result_ttl: AtomicU64,
+    //最多保留多少个未取走的结果，0表示不限制
+    result_capacity: AtomicUsize,
+    //按保存顺序记录的结果，用于淘汰
+    saved: Mutex<VecDeque<(u64, TaskId)>>,
+    //被淘汰的结果数
+    evicted: AtomicU64,
+    //等待前置任务结果的后续任务
+    continuations: DashMap<TaskId, Continuation<'s>>,
+}
+
+impl<'s> TaskStore<'s> {
+    pub(crate) fn set_result_ttl(&self, result_ttl: u64) {
+        self.result_ttl.store(result_ttl, Ordering::Release);
+    }
+
+    pub(crate) fn get_result_ttl(&self) -> u64 {
+        self.result_ttl.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_result_capacity(&self, result_capacity: usize) {
+        self.result_capacity
+            .store(result_capacity, Ordering::Release);
+    }
+
+    pub(crate) fn get_result_capacity(&self) -> usize {
+        self.result_capacity.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn get_evicted_count(&self) -> u64 {
+        self.evicted.load(Ordering::Acquire)
+    }
+
+    /// The result of the task will be saved here, along with the runs recorded in
+    /// `attempts` if any.
+    pub(crate) fn expect(&self, task_id: TaskId, attempts: Option<&Attempts<'s>>) {
+        let _guard = PreemptionGuard::new();
+        _ = self.pending.insert(task_id);
+        if let Some(attempts) = attempts {
+            _ = self.attempts.insert(task_id, attempts.clone());
+        }
+    }
+
+    /// The result of the task will never be saved.
+    pub(crate) fn forget(&self, task_id: TaskId) {
+        let _guard = PreemptionGuard::new();
+        _ = self.pending.remove(&task_id);
+        _ = self.attempts.remove(&task_id);
+    }
+
+    /// Save the result of the task and wake up its joiners, the result is dropped if it's
+    /// not expected, for example the task is detached.
+    ///
+    /// Returns the continuation of the task which has received the result, the caller
+    /// should submit it.
+    pub(crate) fn save(
+        &self,
+        task_id: TaskId,
+        result: Result<Option<usize>, &'s str>,
+    ) -> Option<Task<'s>> {
+        let guard = PreemptionGuard::new();
+        let attempts = self
+            .attempts
+            .remove(&task_id)
+            .map(|(_, attempts)| std::mem::take(&mut *attempts.lock().expect("lock failed")))
+            .unwrap_or_default();
+        let time = now();
+        let (saved, continuation) = match self.results.entry(task_id) {
+            Entry::Occupied(_) => {
+                panic!("The previous result was not retrieved in a timely manner")
+            }
+            Entry::Vacant(entry) => {
+                //持有结果的锁，避免和登记后续任务、分离任务竞争
+                let expected = self.pending.remove(&task_id).is_some();
+                match self.continuations.remove(&task_id) {
+                    Some((_, continuation)) => (false, Some(continuation)),
+                    //不保存结果或已分离的任务，丢弃结果
+                    None if !expected => (false, None),
+                    None => {
+                        _ = entry.insert((time, result, attempts));
+                        (true, None)
+                    }
+                }
+            }
+        };
+        if saved && (self.get_result_ttl() > 0 || self.get_result_capacity() > 0) {
+            self.saved
+                .lock()
+                .expect("lock failed")
+                .push_back((time, task_id));
+            self.evict();
+        }
+        drop(guard);
+        //先唤醒等待者，再把结果交给后续任务
+        self.notify(task_id);
+        continuation.map(|(task, previous)| {
+            previous.put(result);
+            task
+        })
+    }
+
+    /// Run `task` once the result of the task with `task_id` is saved, it receives the
+    /// result through `previous` instead of the joiners.
+    ///
+    /// Returns the task if the result is already saved, the caller should submit it.
+    ///
+    /// # Errors
+    /// if the result of the task has been taken, or it's never saved, then `task` is
+    /// dropped.
+    pub(crate) fn add_continuation(
+        &self,
+        task_id: TaskId,
+        task: Task<'s>,
+        previous: ResultSlot<Result<Option<usize>, &'s str>>,
+    ) -> std::io::Result<Option<Task<'s>>> {
+        let _guard = PreemptionGuard::new();
+        match self.results.entry(task_id) {
+            Entry::Occupied(entry) => {
+                previous.put(entry.remove().1);
+                Ok(Some(task))
+            }
+            //持有结果的锁，避免和保存结果竞争
+            Entry::Vacant(_) if self.pending.contains(&task_id) => {
+                _ = self.continuations.insert(task_id, (task, previous));
+                Ok(None)
+            }
+            Entry::Vacant(_) => Err(Error::new(
+                ErrorKind::NotFound,
+                format!("The result of the task {task_id} has been joined or is never saved !"),
+            )),
+        }
+    }
+
+    /// Take the result of the task, and the runs of the task.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn take(
+        &self,
+        task_id: TaskId,
+    ) -> Option<(Result<Option<usize>, &'s str>, Vec<Attempt<'s>>)> {
+        let _guard = PreemptionGuard::new();
+        self.results
+            .remove(&task_id)
+            .map(|(_, (_, r, attempts))| (r, attempts))
+    }
+
+    /// Like [`TaskStore::take`], but fails if the result will never be available, for
+    /// example it has been taken, evicted or detached.
+    ///
+    /// # Errors
+    /// if the result is neither saved nor pending.
+    pub(crate) fn try_take(&self, task_id: TaskId) -> std::io::Result<Option<TaskResult<'s>>> {
+        let _guard = PreemptionGuard::new();
+        //先检查再取，保存结果时先移除等待记录再插入结果，二者在同一把锁内完成
+        let pending = self.pending.contains(&task_id);
+        match self.take(task_id) {
+            Some(result) => Ok(Some(result)),
+            None if pending => Ok(None),
+            None => Err(Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "The result of the task {task_id} has been joined, evicted or is never saved !"
+                ),
+            )),
+        }
+    }
+
+    /// Put back the result taken by [`TaskStore::take`], it's kept as if it's just saved.
+    pub(crate) fn put_back(&self, task_id: TaskId, (result, attempts): TaskResult<'s>) {
+        let _guard = PreemptionGuard::new();
+        let time = now();
+        _ = self.results.insert(task_id, (time, result, attempts));
+        if self.get_result_ttl() > 0 || self.get_result_capacity() > 0 {
+            self.saved
+                .lock()
+                .expect("lock failed")
+                .push_back((time, task_id));
+        }
+        self.notify(task_id);
+    }
+
+    /// Drop the result of the task if it's saved, otherwise it will not be saved, wherever
+    /// the task runs.
+    pub(crate) fn detach(&self, task_id: TaskId) {
+        let _guard = PreemptionGuard::new();
+        //持有结果的锁，避免和保存结果竞争
+        match self.results.entry(task_id) {
+            Entry::Occupied(entry) => _ = entry.remove(),
+            Entry::Vacant(_) => {
+                _ = self.pending.remove(&task_id);
+                _ = self.attempts.remove(&task_id);
+            }
+        }
+    }
+
+    /// Evict the unjoined results which are expired or beyond the capacity.
+    pub(crate) fn evict(&self) {
+        let ttl = self.get_result_ttl();
+        let capacity = self.get_result_capacity();
+        if 0 == ttl && 0 == capacity {
+            return;
+        }
+        let _guard = PreemptionGuard::new();
+        let now = now();
+        let mut saved = self.saved.lock().expect("lock failed");
+        while let Some(&(time, task_id)) = saved.front() {
+            let expired = ttl > 0 && now.saturating_sub(time) >= ttl;
+            let full = capacity > 0 && self.results.len() > capacity;
+            if !expired && !full {
+                break;
+            }
+            _ = saved.pop_front();
+            //已取走的结果只留下了记录
+            if self
+                .results
+                .remove_if(&task_id, |_, (t, _, _)| *t == time)
+                .is_some()
+            {
+                _ = self.evicted.fetch_add(1, Ordering::Release);
+            }
+        }
+        //已取走的结果留下的记录太多时清理一次
+        if saved.len() > self.results.len().saturating_mul(2).max(64) {
+            saved.retain(|(time, task_id)| {
+                self.results
+                    .get(task_id)
+                    .is_some_and(|result| result.0 == *time)
+            });
+        }
+    }
+
+    fn notify(&self, task_id: TaskId) {
+        let _guard = PreemptionGuard::new();
+        //持有锁唤醒，等待者注销后就不会再被唤醒
+        if let Some(mut waiters) = self.waits.get_mut(&task_id) {
+            for waiter in waiters.drain(..) {
+                waiter.wake();
+            }
+        }
+        _ = self
+            .waits
+            .remove_if(&task_id, |_, waiters| waiters.is_empty());
+    }
+
+    /// Register the `waiter` which is woken up after the result of the task is saved.
+    pub(crate) fn add_waiter(&self, task_id: TaskId, waiter: &Waiter) {
+        let _guard = PreemptionGuard::new();
+        self.waits.entry(task_id).or_default().push(waiter.clone());
+    }
+
+    /// Remove the `waiter` registered by [`TaskStore::add_waiter`].
+    pub(crate) fn remove_waiter(&self, task_id: TaskId, waiter: &Waiter) {
+        let _guard = PreemptionGuard::new();
+        if let Entry::Occupied(mut entry) = self.waits.entry(task_id) {
+            entry.get_mut().retain(|w| !w.is(waiter));
+            if entry.get().is_empty() {
+                _ = entry.remove();
+            }
+        }
+    }
+}
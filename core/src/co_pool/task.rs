@@ -1,18 +1,214 @@
-use crate::catch;
+use crate::co_pool::queue::QueuePermit;
+use crate::co_pool::retry::Attempts;
+use crate::co_pool::store::TaskStore;
+use crate::common::now;
+use crate::{catch, impl_display_by_debug};
 use derivative::Derivative;
+use std::ffi::{c_int, c_longlong};
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
 
 /// 做C兼容时会用到
 pub type UserTaskFunc = extern "C" fn(usize) -> usize;
 
+/// 做C兼容时会用到，释放未执行任务的参数
+pub type UserParamDrop = extern "C" fn(usize);
+
+/// 做C兼容时会用到，第二个参数是前置任务的结果，返回值小于0表示失败
+pub type UserContinuationFunc = extern "C" fn(usize, c_longlong) -> c_longlong;
+
+/// The slot shared by a task and its `JoinHandle`, like a oneshot channel,
+/// the task puts its result in and the handle takes it out.
+///
+/// The result is dropped with the last owner, whether or not it's taken. The task
+/// itself stays untyped, the pool only saves its pointer-sized status, and the typed
+/// value travels through this slot.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ResultSlot<R>(Arc<Mutex<Option<R>>>);
+
+impl<R> ResultSlot<R> {
+    /// Put the result in, the previous one is dropped.
+    pub fn put(&self, result: R) {
+        *self.0.lock().expect("lock failed") = Some(result);
+    }
+
+    /// Take the result out, returns `None` if it's not put or already taken.
+    #[must_use]
+    pub fn take(&self) -> Option<R> {
+        self.0.lock().expect("lock failed").take()
+    }
+}
+
+impl<R> Default for ResultSlot<R> {
+    fn default() -> Self {
+        ResultSlot(Arc::default())
+    }
+}
+
+impl<R> Clone for ResultSlot<R> {
+    fn clone(&self) -> Self {
+        ResultSlot(self.0.clone())
+    }
+}
+
+/// The result saved for a cancelled task, it's compared by address in [`is_cancelled`],
+/// so a task which panics with the same message is not considered cancelled.
+pub static TASK_CANCELLED: &str = "The task was cancelled !";
+
+/// Returns `true` if the task `result` means the task was cancelled.
+#[must_use]
+pub fn is_cancelled(result: &Result<Option<usize>, &str>) -> bool {
+    matches!(result, Err(message) if std::ptr::eq(*message, TASK_CANCELLED))
+}
+
+/// The error returned to the joiners of a cancelled task,
+/// `ECANCELED` on unix and `ERROR_CANCELLED` on windows.
+#[must_use]
+pub fn cancelled_error() -> std::io::Error {
+    cfg_if::cfg_if! {
+        if #[cfg(windows)] {
+            #[allow(clippy::cast_possible_wrap)]
+            std::io::Error::from_raw_os_error(
+                windows_sys::Win32::Foundation::ERROR_CANCELLED as i32,
+            )
+        } else {
+            std::io::Error::from_raw_os_error(libc::ECANCELED)
+        }
+    }
+}
+
+/// The unique id of a task, `0` is never allocated.
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct TaskId(u64);
+
+impl TaskId {
+    /// Allocate a new id.
+    pub(crate) fn next() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// The id of a task which failed to submit.
+    pub(crate) const INVALID: Self = TaskId(0);
+}
+
+impl From<TaskId> for u64 {
+    fn from(id: TaskId) -> Self {
+        id.0
+    }
+}
+
+impl Display for TaskId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+/// The priority of a task, tasks with higher priority are executed first.
+///
+/// It crosses the C ABI as a `c_int`, see [`TaskPriority::try_from`].
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum TaskPriority {
+    /// For latency sensitive tasks, such as health checks and control-plane RPCs.
+    High = 0,
+    /// The default priority.
+    #[default]
+    Normal = 1,
+    /// For bulk jobs.
+    Low = 2,
+}
+
+impl TryFrom<c_int> for TaskPriority {
+    type Error = std::io::Error;
+
+    fn try_from(value: c_int) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(TaskPriority::High),
+            1 => Ok(TaskPriority::Normal),
+            2 => Ok(TaskPriority::Low),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid task priority {value}"),
+            )),
+        }
+    }
+}
+
+impl TaskPriority {
+    /// The number of priorities.
+    pub(crate) const COUNT: usize = 3;
+}
+
+impl_display_by_debug!(TaskPriority);
+
+/// The parts of a task which are given up as soon as it's cancelled.
+#[repr(C)]
+#[derive(Derivative)]
+#[derivative(Debug)]
+struct TaskBody<'t> {
+    //在有界队列中占用的名额
+    permit: Option<QueuePermit>,
+    #[allow(clippy::type_complexity)]
+    #[derivative(Debug = "ignore")]
+    func: Box<dyn FnOnce(Option<usize>) -> Result<Option<usize>, &'t str> + 't>,
+}
+
+/// Give up the place and the closure of a queued task wherever it is queued,
+/// it does nothing if the task has been dropped.
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub(crate) struct TaskCanceller<'t>(Weak<Mutex<Option<TaskBody<'t>>>>, String);
+
+impl TaskCanceller<'_> {
+    /// Get the name of the task, so it can be forgotten without scanning all the names.
+    pub(crate) fn name(&self) -> &str {
+        &self.1
+    }
+
+    pub(crate) fn cancel(&self) {
+        if let Some(body) = self.0.upgrade() {
+            let body = body.lock().expect("lock failed").take();
+            drop(body);
+        }
+    }
+}
+
 /// The task impls.
 #[repr(C)]
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct Task<'t> {
+    id: TaskId,
     name: String,
+    priority: TaskPriority,
+    create_time: u64,
+    //取消时立即释放名额和闭包
+    body: Arc<Mutex<Option<TaskBody<'t>>>>,
+    //不保存结果
+    detached: bool,
+    //同一key的任务按提交顺序串行执行
+    key: Option<u64>,
+    //所属的分组，同一分组的任务数受并发限制
+    group: Option<String>,
+    //周期任务本次执行成功后，返回下次执行的时间和任务
+    #[allow(clippy::type_complexity)]
+    #[derivative(Debug = "ignore")]
+    repeat: Option<Box<dyn FnOnce() -> (u64, Task<'t>) + 't>>,
+    //本次执行失败后，返回重试的时间和任务
+    #[allow(clippy::type_complexity)]
+    #[derivative(Debug = "ignore")]
+    retry: Option<Box<dyn FnOnce(&str) -> Option<(u64, Task<'t>)> + 't>>,
+    //重试任务各次执行的记录，所有执行共用
     #[derivative(Debug = "ignore")]
-    func: Box<dyn FnOnce(Option<usize>) -> Option<usize> + 't>,
+    attempts: Option<Attempts<'t>>,
     param: Option<usize>,
+    //提交任务的线程池保存结果的地方，被其他线程池窃取后也保存到这里
+    #[derivative(Debug = "ignore")]
+    store: Option<Weak<TaskStore<'t>>>,
 }
 
 impl<'t> Task<'t> {
@@ -21,33 +217,187 @@ impl<'t> Task<'t> {
         name: String,
         func: impl FnOnce(Option<usize>) -> Option<usize> + 't,
         param: Option<usize>,
+        priority: TaskPriority,
+    ) -> Self {
+        Self::fallible(name, move |param| Ok(func(param)), param, priority)
+    }
+
+    /// Create a new `Task` instance which fails with the message returned by `func`.
+    //和任务本身一样，闭包可能被其他线程窃取或取消
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn fallible(
+        name: String,
+        func: impl FnOnce(Option<usize>) -> Result<Option<usize>, &'t str> + 't,
+        param: Option<usize>,
+        priority: TaskPriority,
     ) -> Self {
         Task {
+            id: TaskId::next(),
             name,
-            func: Box::new(func),
+            priority,
+            create_time: now(),
+            body: Arc::new(Mutex::new(Some(TaskBody {
+                permit: None,
+                func: Box::new(func),
+            }))),
+            detached: false,
+            key: None,
+            group: None,
+            repeat: None,
+            retry: None,
+            attempts: None,
             param,
+            store: None,
         }
     }
 
+    /// Get the id of this task.
+    #[must_use]
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Get the name of this task.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the priority of this task.
+    #[must_use]
+    pub fn priority(&self) -> TaskPriority {
+        self.priority
+    }
+
+    /// Get the create time of this task in ns.
+    #[must_use]
+    pub fn create_time(&self) -> u64 {
+        self.create_time
+    }
+
+    /// Returns `true` if the result of this task will not be saved.
+    #[must_use]
+    pub fn is_detached(&self) -> bool {
+        self.detached
+    }
+
+    pub(crate) fn detach(&mut self) {
+        self.detached = true;
+    }
+
+    /// Get the hash of the key, the tasks with the same key run one after another.
+    #[must_use]
+    pub fn key(&self) -> Option<u64> {
+        self.key
+    }
+
+    pub(crate) fn set_key(&mut self, key: u64) {
+        self.key = Some(key);
+    }
+
+    /// Get the name of the group, the tasks in the same group are limited in concurrency.
+    #[must_use]
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    pub(crate) fn set_group(&mut self, group: String) {
+        self.group = Some(group);
+    }
+
+    pub(crate) fn take_group(&mut self) -> Option<String> {
+        self.group.take()
+    }
+
+    /// Reuse the id of a previous task, such as the previous run of a periodic task.
+    pub(crate) fn with_id(mut self, id: TaskId) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub(crate) fn set_repeat(&mut self, repeat: impl FnOnce() -> (u64, Task<'t>) + 't) {
+        self.repeat = Some(Box::new(repeat));
+    }
+
+    pub(crate) fn take_repeat(&mut self) -> Option<Box<dyn FnOnce() -> (u64, Task<'t>) + 't>> {
+        self.repeat.take()
+    }
+
+    pub(crate) fn set_retry(&mut self, retry: impl FnOnce(&str) -> Option<(u64, Task<'t>)> + 't) {
+        self.retry = Some(Box::new(retry));
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn take_retry(
+        &mut self,
+    ) -> Option<Box<dyn FnOnce(&str) -> Option<(u64, Task<'t>)> + 't>> {
+        self.retry.take()
+    }
+
+    pub(crate) fn set_attempts(&mut self, attempts: Attempts<'t>) {
+        self.attempts = Some(attempts);
+    }
+
+    /// Get where the runs of this task are recorded, only the task with a retry policy
+    /// records its runs.
+    pub(crate) fn attempts(&self) -> Option<&Attempts<'t>> {
+        self.attempts.as_ref()
+    }
+
+    pub(crate) fn set_store(&mut self, store: Weak<TaskStore<'t>>) {
+        self.store = Some(store);
+    }
+
+    /// Get where the result of this task is saved, `None` means the pool which runs it.
+    pub(crate) fn store(&self) -> Option<&Weak<TaskStore<'t>>> {
+        self.store.as_ref()
+    }
+
+    pub(crate) fn set_permit(&mut self, permit: QueuePermit) {
+        if let Some(body) = self.body.lock().expect("lock failed").as_mut() {
+            body.permit = Some(permit);
+        }
+    }
+
+    /// Give back the place in the bounded queue.
+    pub(crate) fn release(&mut self) {
+        let permit = self
+            .body
+            .lock()
+            .expect("lock failed")
+            .as_mut()
+            .and_then(|body| body.permit.take());
+        drop(permit);
+    }
+
+    pub(crate) fn canceller(&self) -> TaskCanceller<'t> {
+        TaskCanceller(Arc::downgrade(&self.body), self.name.clone())
+    }
+
     /// execute the task
     ///
     /// # Errors
     /// if an exception occurred while executing this task.
-    pub fn run<'e>(self) -> (String, Result<Option<usize>, &'e str>) {
+    pub fn run(self) -> (String, Result<Option<usize>, &'t str>) {
+        let Some(body) = self.body.lock().expect("lock failed").take() else {
+            return (self.name, Err(TASK_CANCELLED));
+        };
         (
             self.name.clone(),
             catch!(
-                || (self.func)(self.param),
+                || (body.func)(self.param),
                 format!("task {} failed without message", self.name),
                 format!("task {}", self.name)
-            ),
+            )
+            .and_then(|result| result),
         )
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::co_pool::task::Task;
+    use crate::co_pool::task::{ResultSlot, Task, TaskPriority};
+    use std::rc::Rc;
 
     #[test]
     fn test() {
@@ -58,6 +408,7 @@ mod tests {
                 p
             },
             None,
+            TaskPriority::default(),
         );
         assert_eq!((String::from("test"), Ok(None)), task.run());
     }
@@ -70,10 +421,45 @@ mod tests {
                 panic!("test panic, just ignore it");
             },
             None,
+            TaskPriority::default(),
         );
         assert_eq!(
             (String::from("test"), Err("test panic, just ignore it")),
             task.run()
         );
     }
+
+    #[test]
+    fn test_result_slot() {
+        let result = Rc::new(());
+        let slot = ResultSlot::default();
+        let task_slot = slot.clone();
+        let task_result = result.clone();
+        let task = Task::new(
+            String::from("test"),
+            move |p| {
+                task_slot.put(task_result);
+                p
+            },
+            None,
+            TaskPriority::default(),
+        );
+        assert_eq!((String::from("test"), Ok(None)), task.run());
+        assert_eq!(2, Rc::strong_count(&result));
+        // the result is dropped without being taken
+        drop(slot);
+        assert_eq!(1, Rc::strong_count(&result));
+    }
+
+    #[test]
+    fn test_priority_from_c_int() {
+        for priority in [TaskPriority::High, TaskPriority::Normal, TaskPriority::Low] {
+            assert_eq!(
+                priority,
+                TaskPriority::try_from(priority as std::ffi::c_int).unwrap()
+            );
+        }
+        assert!(TaskPriority::try_from(-1).is_err());
+        assert!(TaskPriority::try_from(3).is_err());
+    }
 }
@@ -0,0 +1,148 @@
+use crate::co_pool::retry::Attempt;
+use crate::co_pool::task::TaskId;
+use crate::co_pool::CoroutinePool;
+use crate::common::now;
+use crate::coroutine::CoroutineId;
+use crate::scheduler::{SchedulableCoroutine, SchedulableSuspender};
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// Who is waiting for the results of tasks.
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub(crate) enum Waiter {
+    /// A thread blocked on the condvar until it's notified.
+    Thread(Arc<(Mutex<bool>, Condvar)>),
+    /// A coroutine parked in its pool.
+    ///
+    /// The pool outlives the registration, because the coroutine removes its
+    /// registrations before it returns, and the pool waits for its coroutines
+    /// before it's dropped.
+    Coroutine(*const CoroutinePool<'static>, CoroutineId),
+}
+
+impl Waiter {
+    /// Create the waiter for the current coroutine or thread.
+    pub(crate) fn current() -> Self {
+        if let (Some(pool), Some(co)) = (CoroutinePool::current(), SchedulableCoroutine::current())
+        {
+            return Waiter::Coroutine(std::ptr::from_ref(pool).cast(), co.id());
+        }
+        Waiter::Thread(Arc::new((Mutex::new(true), Condvar::new())))
+    }
+
+    /// Returns `true` if they are the same waiter.
+    pub(crate) fn is(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Waiter::Thread(a), Waiter::Thread(b)) => Arc::ptr_eq(a, b),
+            (Waiter::Coroutine(_, a), Waiter::Coroutine(_, b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Wake up the waiter, it can be called on any thread.
+    pub(crate) fn wake(&self) {
+        match self {
+            Waiter::Thread(arc) => {
+                let (lock, cvar) = &**arc;
+                let mut pending = lock.lock().expect("lock failed");
+                *pending = false;
+                cvar.notify_one();
+            }
+            Waiter::Coroutine(pool, co_id) => unsafe { &**pool }.wake(*co_id),
+        }
+    }
+
+    /// Block until woken up or the `timeout_time` is reached, the wakeups which
+    /// happen before this call are not lost.
+    pub(crate) fn wait(&self, timeout_time: u64) -> std::io::Result<()> {
+        match self {
+            Waiter::Thread(arc) => {
+                let (lock, cvar) = &**arc;
+                let (mut pending, _) = cvar
+                    .wait_timeout_while(
+                        lock.lock()
+                            .map_err(|e| Error::new(ErrorKind::Other, format!("{e}")))?,
+                        Duration::from_nanos(timeout_time.saturating_sub(now())),
+                        |&mut pending| pending,
+                    )
+                    .map_err(|e| Error::new(ErrorKind::Other, format!("{e}")))?;
+                *pending = true;
+            }
+            Waiter::Coroutine(pool, _) => {
+                if let Some(suspender) = SchedulableSuspender::current() {
+                    unsafe { &**pool }.park_joiner(suspender, timeout_time);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The result of a task and the runs of the task.
+pub(crate) type TaskResult<'p> = (Result<Option<usize>, &'p str>, Vec<Attempt<'p>>);
+
+/// Suspend the current coroutine or block the current thread until `done` returns
+/// `true` or the `timeout_time` is reached, `tasks` can belong to different pools.
+///
+/// The results are collected one by one, `done` is checked after each of them, so
+/// no more results are taken than needed. Returns the collected results, the
+/// pending ones are `None`, and the index of the last collected one.
+///
+/// # Errors
+/// if timeout, or the result of any task will never be available, for example it has
+/// been joined, evicted or detached. The collected results are put back, so they can
+/// still be joined.
+pub(crate) fn wait_results<'p>(
+    tasks: &[(&CoroutinePool<'p>, TaskId)],
+    timeout_time: u64,
+    done: impl Fn(&[Option<TaskResult<'p>>]) -> bool,
+) -> std::io::Result<(Vec<Option<TaskResult<'p>>>, usize)> {
+    let waiter = Waiter::current();
+    //先登记再检查结果，避免错过登记前保存的结果
+    for (pool, task_id) in tasks {
+        pool.add_waiter(*task_id, &waiter);
+    }
+    let mut results: Vec<Option<TaskResult<'p>>> = tasks.iter().map(|_| None).collect();
+    let outcome = 'wait: loop {
+        for (index, (pool, task_id)) in tasks.iter().enumerate() {
+            if results[index].is_some() {
+                continue;
+            }
+            match pool.try_take_task_result(*task_id) {
+                Ok(result) => results[index] = result,
+                Err(e) => break 'wait Err(e),
+            }
+            if results[index].is_some() && done(&results) {
+                break 'wait Ok(index);
+            }
+        }
+        if now() >= timeout_time {
+            break Err(Error::new(ErrorKind::TimedOut, "wait timeout"));
+        }
+        if let Err(e) = waiter.wait(timeout_time) {
+            break Err(e);
+        }
+    };
+    for (pool, task_id) in tasks {
+        pool.remove_waiter(*task_id, &waiter);
+    }
+    if outcome.is_err() {
+        put_back(tasks, &mut results);
+    }
+    outcome.map(|index| (results, index))
+}
+
+/// Put the collected `results` back to the pools of the `tasks`, so they can be
+/// joined again.
+pub(crate) fn put_back<'p>(
+    tasks: &[(&CoroutinePool<'p>, TaskId)],
+    results: &mut [Option<TaskResult<'p>>],
+) {
+    for ((pool, task_id), result) in tasks.iter().zip(results) {
+        if let Some(result) = result.take() {
+            pool.put_back_task_result(*task_id, result);
+        }
+    }
+}
@@ -42,6 +42,46 @@ pub enum PoolState {
 
 impl_display_by_debug!(PoolState);
 
+/// Enums used to describe what to do when a task is submitted to a full task queue.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum RejectPolicy {
+    /// Return an error to the submitter.
+    #[default]
+    Abort,
+    /// Run the task on the submitter directly.
+    CallerRuns,
+    /// Discard the oldest task queued in this pool, then submit the new one. The new one
+    /// is rejected if the tasks taking the places have been stolen by other pools.
+    DiscardOldest,
+    /// Block the submitting thread or suspend the submitting coroutine until a queued task
+    /// leaves the queue, the pool stops or the block timeout of the pool passes, then the
+    /// task is rejected. The coroutines and the scheduling thread of the pool itself run the
+    /// task directly like [`RejectPolicy::CallerRuns`] instead, waiting may hold the worker
+    /// which would give back the place.
+    Block,
+}
+
+impl_display_by_debug!(RejectPolicy);
+
+/// Enums used to describe which event-loop a task is submitted to.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum PlacementPolicy {
+    /// Submit to the event-loops in turn, regardless of their load.
+    #[default]
+    RoundRobin,
+    /// Submit to the least loaded event-loop, the load counts the queued tasks, the
+    /// ready coroutines and the busy workers.
+    LeastQueueDepth,
+    /// Pick two event-loops at random, and submit to the less loaded one.
+    PowerOfTwoChoices,
+    /// Submit to the current event-loop if the submitter runs on one, otherwise in turn.
+    CurrentFirst,
+}
+
+impl_display_by_debug!(PlacementPolicy);
+
 /// Enums used to describe syscall
 #[allow(non_camel_case_types, missing_docs)]
 #[repr(C)]
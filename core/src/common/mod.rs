@@ -1,5 +1,7 @@
+use std::collections::hash_map::DefaultHasher;
 #[cfg(target_os = "linux")]
 use std::ffi::c_int;
+use std::hash::{Hash, Hasher};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
@@ -94,6 +96,15 @@ pub fn get_timeout_time(dur: Duration) -> u64 {
         .unwrap_or(u64::MAX)
 }
 
+/// Hash the `value` with fixed keys, so the same value always has the same hash
+/// in a process.
+#[must_use]
+pub fn hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
 /// Make the total time into slices.
 #[must_use]
 pub fn get_slices(total: Duration, slice: Duration) -> Vec<Duration> {
@@ -179,6 +179,23 @@ impl<T> TimerList<T> {
         }
         None
     }
+
+    /// Removes and returns the first element at `timestamp` which `f` returns `true`
+    /// for, the elements don't need to be ordered. Returns `None` if not found.
+    pub fn remove_first(&mut self, timestamp: &u64, f: impl Fn(&T) -> bool) -> Option<T> {
+        let entry = self.inner.get_mut(timestamp)?;
+        let val = entry
+            .iter()
+            .position(f)
+            .and_then(|index| entry.inner.remove(index))
+            .inspect(|_| {
+                _ = self.total.fetch_sub(1, Ordering::Release);
+            });
+        if entry.is_empty() {
+            _ = self.remove_entry(timestamp);
+        }
+        val
+    }
 }
 
 impl_display_by_debug!(TimerList<T>);
@@ -165,6 +165,56 @@ impl<'l, T: Debug> LocalQueue<'l, T> {
         self.queue.capacity() - self.queue.spare_capacity()
     }
 
+    /// Pop an element from the local queue only, it never steals from the global
+    /// queue or the siblings.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use open_coroutine_core::common::work_steal::WorkStealQueue;
+    ///
+    /// let queue = WorkStealQueue::new(2, 64);
+    /// let local0 = queue.local_queue();
+    /// let local1 = queue.local_queue();
+    /// local0.push_back(0);
+    /// queue.push(1);
+    /// assert_eq!(local1.pop_local(), None);
+    /// assert_eq!(local0.pop_local(), Some(0));
+    /// assert_eq!(local0.pop_local(), None);
+    /// assert_eq!(queue.pop(), Some(1));
+    /// ```
+    pub fn pop_local(&self) -> Option<T> {
+        self.queue.pop()
+    }
+
+    /// Returns `true` if the global queue or any sibling has elements to steal.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use open_coroutine_core::common::work_steal::WorkStealQueue;
+    ///
+    /// let queue = WorkStealQueue::new(2, 64);
+    /// let local0 = queue.local_queue();
+    /// let local1 = queue.local_queue();
+    /// local0.push_back(0);
+    /// assert!(!local0.can_steal());
+    /// assert!(local1.can_steal());
+    /// queue.push(1);
+    /// assert!(local0.can_steal());
+    /// assert_eq!(local0.pop_front(), Some(0));
+    /// assert_eq!(local0.pop_front(), Some(1));
+    /// assert!(!local1.can_steal());
+    /// ```
+    pub fn can_steal(&self) -> bool {
+        !self.shared.is_empty()
+            || self
+                .shared
+                .local_queues
+                .iter()
+                .any(|another| !std::ptr::eq(another, self.queue) && !another.is_empty())
+    }
+
     fn try_lock(&self) -> bool {
         self.stealing
             .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
@@ -269,6 +319,41 @@ impl<'l, T: Debug> LocalQueue<'l, T> {
         if let Some(val) = self.queue.pop() {
             return Some(val);
         }
+        if self.steal() {
+            return self.queue.pop();
+        }
+        //都steal不到，只好从shared里pop
+        self.shared.pop()
+    }
+
+    /// Steal half of the elements from a sibling into the local queue, it should only
+    /// be called by the thread which owns the local queue.
+    ///
+    /// Returns `true` if any element is stolen.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use open_coroutine_core::common::work_steal::WorkStealQueue;
+    ///
+    /// let queue = WorkStealQueue::new(2, 64);
+    /// let local0 = queue.local_queue();
+    /// for i in 0..4 {
+    ///     local0.push_back(i);
+    /// }
+    /// let local1 = queue.local_queue();
+    /// assert!(local1.steal());
+    /// assert_eq!(local0.len(), 2);
+    /// assert_eq!(local1.len(), 2);
+    /// for i in 0..2 {
+    ///     assert_eq!(local1.pop_front(), Some(i));
+    /// }
+    /// for i in 2..4 {
+    ///     assert_eq!(local0.pop_front(), Some(i));
+    /// }
+    /// assert!(!local1.steal());
+    /// ```
+    pub fn steal(&self) -> bool {
         if self.try_lock() {
             //尝试从其他本地队列steal
             let local_queues = &self.shared.local_queues;
@@ -277,36 +362,76 @@ impl<'l, T: Debug> LocalQueue<'l, T> {
             for i in 0..num {
                 let i = (start + i) % num;
                 if let Some(another) = local_queues.get(i) {
-                    if std::ptr::eq(&another, &self.queue) {
-                        //不能偷自己
-                        continue;
-                    }
-                    if another.is_empty() {
-                        //其他队列为空
-                        continue;
-                    }
-                    if self.queue.spare_capacity() == 0 {
-                        //本地队列已满
-                        continue;
-                    }
-                    if another
-                        .stealer()
-                        .steal(self.queue, |n| {
-                            //可偷取的最大长度与本地队列空闲长度做比较
-                            n.min(self.queue.spare_capacity())
-                                //与其他队列当前长度的一半做比较
-                                .min(((another.capacity() - another.spare_capacity()) + 1) / 2)
-                        })
-                        .is_ok()
-                    {
+                    if self.steal_half(another) {
                         self.release_lock();
-                        return self.queue.pop();
+                        return true;
                     }
                 }
             }
             self.release_lock();
         }
-        //都steal不到，只好从shared里pop
-        self.shared.pop()
+        false
+    }
+
+    /// Like [`LocalQueue::steal`], but only steal from `another`, such as the most
+    /// loaded sibling.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use open_coroutine_core::common::work_steal::WorkStealQueue;
+    ///
+    /// let queue = WorkStealQueue::new(3, 64);
+    /// let local0 = queue.local_queue();
+    /// let local1 = queue.local_queue();
+    /// let local2 = queue.local_queue();
+    /// local0.push_back(0);
+    /// for i in 1..5 {
+    ///     local1.push_back(i);
+    /// }
+    /// assert!(local2.steal_from(&local1));
+    /// assert_eq!(local0.len(), 1);
+    /// assert_eq!(local1.len(), 2);
+    /// assert_eq!(local2.len(), 2);
+    /// assert!(!local2.steal_from(&local2));
+    /// for i in 1..3 {
+    ///     assert_eq!(local2.pop_front(), Some(i));
+    /// }
+    /// for i in 3..5 {
+    ///     assert_eq!(local1.pop_front(), Some(i));
+    /// }
+    /// assert_eq!(local0.pop_front(), Some(0));
+    /// ```
+    pub fn steal_from(&self, another: &LocalQueue<'l, T>) -> bool {
+        if !self.try_lock() {
+            return false;
+        }
+        let stolen = self.steal_half(another.queue);
+        self.release_lock();
+        stolen
+    }
+
+    fn steal_half(&self, another: &Worker<T>) -> bool {
+        if std::ptr::eq(another, self.queue) {
+            //不能偷自己
+            return false;
+        }
+        if another.is_empty() {
+            //其他队列为空
+            return false;
+        }
+        if self.queue.spare_capacity() == 0 {
+            //本地队列已满
+            return false;
+        }
+        another
+            .stealer()
+            .steal(self.queue, |n| {
+                //可偷取的最大长度与本地队列空闲长度做比较
+                n.min(self.queue.spare_capacity())
+                    //与其他队列当前长度的一半做比较
+                    .min(((another.capacity() - another.spare_capacity()) + 1) / 2)
+            })
+            .is_ok()
     }
 }
@@ -3,6 +3,7 @@ use crate::common::constants::CoroutineState;
 use crate::coroutine::listener::Listener;
 use crate::coroutine::local::CoroutineLocal;
 use crate::coroutine::suspender::Suspender;
+use crate::coroutine::CoroutineId;
 use corosensei::stack::{DefaultStack, Stack};
 use corosensei::trap::TrapHandlerRegs;
 use corosensei::CoroutineResult;
@@ -14,15 +15,44 @@ use std::io::{Error, ErrorKind};
 cfg_if::cfg_if! {
     if #[cfg(unix)] {
         use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+        use once_cell::unsync::OnceCell;
+        use std::io::Write;
+
+        thread_local! {
+            static SIGNAL_STACK: OnceCell<SignalStack> = const { OnceCell::new() };
+            //信号处理函数中不能调用std::thread::current()，提前保存线程名
+            static THREAD_NAME: Cell<([u8; 64], usize)> = const { Cell::new(([0; 64], 0)) };
+        }
+
+        type Errno = std::ffi::c_int;
+
+        fn last_errno() -> Errno {
+            nix::errno::Errno::last_raw()
+        }
+
+        fn set_errno(errno: Errno) {
+            nix::errno::Errno::set_raw(errno);
+        }
     } else if #[cfg(windows)] {
         use windows_sys::Win32::Foundation::{EXCEPTION_ACCESS_VIOLATION, EXCEPTION_STACK_OVERFLOW};
         use windows_sys::Win32::System::Diagnostics::Debug::{AddVectoredExceptionHandler, EXCEPTION_POINTERS};
+
+        type Errno = windows_sys::Win32::Foundation::WIN32_ERROR;
+
+        fn last_errno() -> Errno {
+            unsafe { windows_sys::Win32::Foundation::GetLastError() }
+        }
+
+        fn set_errno(errno: Errno) {
+            unsafe { windows_sys::Win32::Foundation::SetLastError(errno) }
+        }
     }
 }
 
 /// Use `corosensei` as the low-level coroutine.
 #[repr(C)]
 pub struct Coroutine<'c, Param, Yield, Return> {
+    pub(crate) id: CoroutineId,
     pub(crate) name: String,
     inner: corosensei::Coroutine<Param, Yield, Result<Return, &'static str>, DefaultStack>,
     pub(crate) state: Cell<CoroutineState<Yield, Return>>,
@@ -30,6 +60,94 @@ pub struct Coroutine<'c, Param, Yield, Return> {
     pub(crate) stack_bottom: RefCell<VecDeque<usize>>,
     pub(crate) listeners: VecDeque<&'c dyn Listener<Yield, Return>>,
     pub(crate) local: CoroutineLocal<'c>,
+    //errno是线程局部的，协程让出时保存，恢复执行时还原
+    errno: Cell<Errno>,
+}
+
+/// The alternate signal stack of a thread, without it the trap handler can
+/// not run when the thread stack overflows.
+#[cfg(unix)]
+pub(crate) struct SignalStack(Option<DefaultStack>);
+
+#[cfg(unix)]
+impl SignalStack {
+    const SIZE: usize = 64 * 1024;
+
+    /// Register an alternate signal stack for the current thread if it has none,
+    /// and save the thread name for the trap handler.
+    pub(crate) fn init_current() {
+        SIGNAL_STACK.with(|stack| {
+            _ = stack.get_or_init(|| {
+                Self::save_thread_name();
+                Self::new()
+            });
+        });
+    }
+
+    fn save_thread_name() {
+        let mut buf = [0u8; 64];
+        let mut len = 0;
+        if let Some(name) = std::thread::current().name() {
+            for (index, c) in name.char_indices() {
+                if index + c.len_utf8() > buf.len() {
+                    break;
+                }
+                len = index + c.len_utf8();
+            }
+            buf[..len].copy_from_slice(&name.as_bytes()[..len]);
+        }
+        THREAD_NAME.with(|name| name.set((buf, len)));
+    }
+
+    /// The thread name saved by `init_current`, it's safe to call in signal handlers.
+    fn thread_name(buf: &mut [u8; 64]) -> &str {
+        let len;
+        (*buf, len) = THREAD_NAME.with(Cell::get);
+        match std::str::from_utf8(&buf[..len]) {
+            Ok(name) if !name.is_empty() => name,
+            _ => "<unnamed>",
+        }
+    }
+
+    fn new() -> Self {
+        unsafe {
+            let mut old: libc::stack_t = std::mem::zeroed();
+            if 0 == libc::sigaltstack(std::ptr::null(), std::ptr::addr_of_mut!(old))
+                && old.ss_flags & libc::SS_DISABLE == 0
+            {
+                //已有备用信号栈，比如rust标准库创建的线程
+                return SignalStack(None);
+            }
+            let stack = match DefaultStack::new(Self::SIZE) {
+                Ok(stack) => stack,
+                Err(e) => {
+                    crate::error!("allocate signal stack failed: {e}");
+                    return SignalStack(None);
+                }
+            };
+            let mut new: libc::stack_t = std::mem::zeroed();
+            new.ss_sp = stack.limit().get() as *mut std::ffi::c_void;
+            new.ss_size = stack.base().get() - stack.limit().get();
+            if 0 != libc::sigaltstack(std::ptr::addr_of!(new), std::ptr::null_mut()) {
+                crate::error!("register signal stack failed: {}", Error::last_os_error());
+                return SignalStack(None);
+            }
+            SignalStack(Some(stack))
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SignalStack {
+    fn drop(&mut self) {
+        if self.0.is_some() {
+            unsafe {
+                let mut disable: libc::stack_t = std::mem::zeroed();
+                disable.ss_flags = libc::SS_DISABLE;
+                _ = libc::sigaltstack(std::ptr::addr_of!(disable), std::ptr::null_mut());
+            }
+        }
+    }
 }
 
 impl<'c, Param, Yield, Return> Coroutine<'c, Param, Yield, Return> {
@@ -42,8 +160,8 @@ impl<'c, Param, Yield, Return> Coroutine<'c, Param, Yield, Return> {
                 clippy::cast_possible_wrap
             )]
             extern "C" fn trap_handler(
-                _signum: libc::c_int,
-                _siginfo: *mut libc::siginfo_t,
+                signum: libc::c_int,
+                siginfo: *mut libc::siginfo_t,
                 context: *mut std::ffi::c_void,
             ) {
                 unsafe {
@@ -200,9 +318,39 @@ impl<'c, Param, Yield, Return> Coroutine<'c, Param, Yield, Return> {
                                 compile_error!("Unsupported platform");
                             }
                         }
+                    } else {
+                        Self::thread_trap(signum, siginfo, sp);
                     }
                 }
             }
+
+            /// The trap happened outside any coroutine, it can not be recovered.
+            unsafe fn thread_trap(signum: libc::c_int, siginfo: *mut libc::siginfo_t, sp: usize) {
+                cfg_if::cfg_if! {
+                    if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                        let addr = (*siginfo).si_addr() as usize;
+                    } else {
+                        let addr = (*siginfo).si_addr as usize;
+                    }
+                }
+                //访问的地址紧挨着栈顶，说明线程栈溢出
+                if addr.abs_diff(sp) <= 16 * crate::common::page_size() {
+                    let mut buf = [0u8; 256];
+                    let len = buf.len();
+                    let mut name = [0u8; 64];
+                    let mut cursor = &mut buf[..];
+                    _ = writeln!(
+                        cursor,
+                        "\nthread '{}' has overflowed its stack, fault address {addr:#x}",
+                        SignalStack::thread_name(&mut name)
+                    );
+                    let written = len - cursor.len();
+                    _ = libc::write(libc::STDERR_FILENO, buf.as_ptr().cast(), written);
+                    std::process::abort();
+                }
+                //恢复默认处理，返回后再次触发信号时由系统终止进程
+                _ = libc::signal(signum, libc::SIG_DFL);
+            }
         } else if #[cfg(windows)] {
             unsafe extern "system" fn trap_handler(exception_info: *mut EXCEPTION_POINTERS) -> i32 {
                 match (*(*exception_info).ExceptionRecord).ExceptionCode {
@@ -270,6 +418,8 @@ impl<'c, Param, Yield, Return> Coroutine<'c, Param, Yield, Return> {
     fn setup_trap_handler() {
         use std::sync::atomic::{AtomicBool, Ordering};
         static TRAP_HANDLER_INITED: AtomicBool = AtomicBool::new(false);
+        #[cfg(unix)]
+        SignalStack::init_current();
         if TRAP_HANDLER_INITED
             .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
             .is_ok()
@@ -363,7 +513,7 @@ where
         let stack_size = stack_size.max(crate::common::page_size());
         let stack = DefaultStack::new(stack_size)?;
         let stack_bottom = RefCell::new(VecDeque::from([stack.limit().get()]));
-        let co_name = name.clone().leak();
+        let co_name = name.clone();
         let inner = corosensei::Coroutine::with_stack(stack, move |y, p| {
             catch!(
                 move || {
@@ -379,6 +529,7 @@ where
         });
         #[allow(unused_mut)]
         let mut co = Coroutine {
+            id: CoroutineId::next(),
             name,
             inner,
             stack_size,
@@ -386,6 +537,7 @@ where
             state: Cell::new(CoroutineState::Ready),
             listeners: VecDeque::default(),
             local: CoroutineLocal::default(),
+            errno: Cell::new(0),
         };
         #[cfg(all(unix, feature = "preemptive"))]
         co.add_listener(crate::monitor::MonitorListener::default());
@@ -398,7 +550,12 @@ where
         arg: Param,
     ) -> std::io::Result<CoroutineState<Yield, Return>> {
         Self::setup_trap_handler();
-        match self.inner.resume(arg) {
+        let errno = last_errno();
+        set_errno(self.errno.get());
+        let result = self.inner.resume(arg);
+        self.errno.set(last_errno());
+        set_errno(errno);
+        match result {
             CoroutineResult::Yield(y) => {
                 let current = self.state();
                 match current {
@@ -1,4 +1,5 @@
 use crate::impl_display_by_debug;
+use crate::preempt::PreemptionGuard;
 use dashmap::DashMap;
 use std::ffi::c_void;
 use std::fmt::Debug;
@@ -15,6 +16,7 @@ impl<'c> CoroutineLocal<'c> {
     /// Put a value into the coroutine local.
     pub fn put<V>(&self, key: &'c str, val: V) -> Option<V> {
         let v = Box::leak(Box::new(val));
+        let _guard = PreemptionGuard::new();
         self.0
             .insert(key, std::ptr::from_mut::<V>(v) as usize)
             .map(|ptr| unsafe { *Box::from_raw((ptr as *mut c_void).cast::<V>()) })
@@ -22,6 +24,7 @@ impl<'c> CoroutineLocal<'c> {
 
     /// Get a value ref from the coroutine local.
     pub fn get<V>(&self, key: &'c str) -> Option<&V> {
+        let _guard = PreemptionGuard::new();
         self.0
             .get(key)
             .map(|ptr| unsafe { &*(*ptr as *mut c_void).cast::<V>() })
@@ -29,6 +32,7 @@ impl<'c> CoroutineLocal<'c> {
 
     /// Get a mut value ref from the coroutine local.
     pub fn get_mut<V>(&self, key: &'c str) -> Option<&mut V> {
+        let _guard = PreemptionGuard::new();
         self.0
             .get(key)
             .map(|ptr| unsafe { &mut *(*ptr as *mut c_void).cast::<V>() })
@@ -36,6 +40,7 @@ impl<'c> CoroutineLocal<'c> {
 
     /// Remove a key from the coroutine local.
     pub fn remove<V>(&self, key: &'c str) -> Option<V> {
+        let _guard = PreemptionGuard::new();
         self.0
             .remove(key)
             .map(|ptr| unsafe { *Box::from_raw((ptr.1 as *mut c_void).cast::<V>()) })
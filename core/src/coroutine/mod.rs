@@ -2,8 +2,9 @@ use crate::common::constants::CoroutineState;
 use crate::coroutine::listener::Listener;
 use crate::coroutine::local::CoroutineLocal;
 use crate::{impl_current_for, impl_display_by_debug, impl_for_named};
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 /// Coroutine suspender abstraction and impl.
 #[allow(dead_code)]
@@ -19,6 +20,8 @@ pub mod listener;
 pub use korosensei::Coroutine;
 #[cfg(feature = "korosensei")]
 mod korosensei;
+#[cfg(all(unix, feature = "korosensei"))]
+pub(crate) use korosensei::SignalStack;
 
 /// Create a new coroutine.
 #[macro_export]
@@ -44,7 +47,43 @@ macro_rules! co {
 /// Coroutine state abstraction and impl.
 mod state;
 
+/// The unique id of a coroutine, unlike the name it is cheap to copy and hash.
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct CoroutineId(u64);
+
+impl CoroutineId {
+    /// Allocate a new id.
+    pub(crate) fn next() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        CoroutineId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl From<u64> for CoroutineId {
+    fn from(id: u64) -> Self {
+        CoroutineId(id)
+    }
+}
+
+impl From<CoroutineId> for u64 {
+    fn from(id: CoroutineId) -> Self {
+        id.0
+    }
+}
+
+impl Display for CoroutineId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
 impl<'c, Param, Yield, Return> Coroutine<'c, Param, Yield, Return> {
+    /// Get the id of this coroutine.
+    pub fn id(&self) -> CoroutineId {
+        self.id
+    }
+
     /// Get the name of this coroutine.
     pub fn name(&self) -> &str {
         &self.name
@@ -143,6 +182,7 @@ where
 {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Coroutine")
+            .field("id", &self.id())
             .field("name", &self.name())
             .field("status", &self.state())
             .field("local", &self.local)
@@ -63,6 +63,9 @@ pub mod coroutine;
 #[cfg(all(unix, feature = "preemptive"))]
 mod monitor;
 
+/// Preemption control for critical sections.
+pub mod preempt;
+
 /// Scheduler impls.
 pub mod scheduler;
 
@@ -1,17 +1,28 @@
 use crate::common::beans::BeanFactory;
-use crate::common::constants::{CoroutineState, MONITOR_BEAN};
+use crate::common::constants::{CoroutineState, MONITOR_BEAN, SLICE};
 use crate::common::{get_timeout_time, now, CondvarBlocker};
 use crate::coroutine::listener::Listener;
 use crate::coroutine::local::CoroutineLocal;
+use crate::coroutine::SignalStack;
+#[cfg(target_os = "linux")]
+use crate::preempt::DeferringGuard;
+use crate::preempt::{is_non_preemptible, PreemptionGuard};
 use crate::scheduler::SchedulableSuspender;
 use crate::{catch, error, impl_current_for, impl_display_by_debug, info};
+use nix::errno::Errno;
 use nix::sys::pthread::{pthread_kill, pthread_self, Pthread};
 use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+#[cfg(target_os = "linux")]
+use once_cell::unsync::OnceCell;
+#[cfg(target_os = "linux")]
+use std::cell::RefCell;
 use std::cell::{Cell, UnsafeCell};
 use std::collections::HashSet;
+use std::ffi::c_void;
 use std::fmt::Debug;
 use std::io::{Error, ErrorKind};
 use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread::JoinHandle;
 use std::time::Duration;
@@ -65,36 +76,158 @@ impl Monitor {
         BeanFactory::get_or_default(MONITOR_BEAN)
     }
 
-    fn start(&self) -> std::io::Result<()> {
-        extern "C" fn sigurg_handler(_: libc::c_int) {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn instruction_pointer(context: &libc::ucontext_t) -> usize {
+        cfg_if::cfg_if! {
+            if #[cfg(all(
+                any(target_os = "linux", target_os = "android"),
+                target_arch = "x86_64",
+            ))] {
+                context.uc_mcontext.gregs[libc::REG_RIP as usize] as usize
+            } else if #[cfg(all(
+                any(target_os = "linux", target_os = "android"),
+                target_arch = "x86",
+            ))] {
+                context.uc_mcontext.gregs[libc::REG_EIP as usize] as usize
+            } else if #[cfg(all(target_vendor = "apple", target_arch = "x86_64"))] {
+                unsafe { (*context.uc_mcontext).__ss.__rip as usize }
+            } else if #[cfg(all(
+                any(target_os = "linux", target_os = "android"),
+                target_arch = "aarch64",
+            ))] {
+                context.uc_mcontext.pc as usize
+            } else if #[cfg(all(
+                any(target_os = "linux", target_os = "android"),
+                target_arch = "arm",
+            ))] {
+                context.uc_mcontext.arm_pc as usize
+            } else if #[cfg(all(
+                any(target_os = "linux", target_os = "android"),
+                any(target_arch = "riscv64", target_arch = "riscv32"),
+            ))] {
+                context.uc_mcontext.__gregs[libc::REG_PC] as usize
+            } else if #[cfg(all(target_vendor = "apple", target_arch = "aarch64"))] {
+                unsafe { (*context.uc_mcontext).__ss.__pc as usize }
+            } else if #[cfg(all(target_os = "linux", target_arch = "loongarch64"))] {
+                context.uc_mcontext.__pc as usize
+            } else {
+                compile_error!("Unsupported platform");
+            }
+        }
+    }
+
+    /// Register the code of libc and the dynamic loader as non-preemptible,
+    /// suspending there may leave a lock held by the suspended coroutine(such as the allocator lock).
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn register_system_regions() {
+        const PF_X: u32 = 1;
+        const SYSTEM_LIBRARIES: [&str; 7] = [
+            "libc.so",
+            "libc-",
+            "libc.musl",
+            "libpthread",
+            "libdl",
+            "ld-",
+            "linker",
+        ];
+        unsafe extern "C" fn callback(
+            info: *mut libc::dl_phdr_info,
+            _: libc::size_t,
+            _: *mut c_void,
+        ) -> libc::c_int {
+            let info = &*info;
+            if info.dlpi_name.is_null() {
+                return 0;
+            }
+            let path = std::ffi::CStr::from_ptr(info.dlpi_name).to_string_lossy();
+            let file_name = path.rsplit('/').next().unwrap_or_default();
+            if !SYSTEM_LIBRARIES
+                .iter()
+                .any(|prefix| file_name.starts_with(prefix))
+            {
+                return 0;
+            }
+            for i in 0..usize::from(info.dlpi_phnum) {
+                let phdr = &*info.dlpi_phdr.add(i);
+                if phdr.p_type != libc::PT_LOAD || phdr.p_flags & PF_X == 0 {
+                    continue;
+                }
+                #[allow(clippy::useless_conversion)]
+                let start = usize::try_from(u64::from(info.dlpi_addr) + u64::from(phdr.p_vaddr))
+                    .expect("overflow");
+                #[allow(clippy::useless_conversion)]
+                let end = start + usize::try_from(u64::from(phdr.p_memsz)).expect("overflow");
+                if crate::preempt::register_non_preemptible(start, end).is_err() {
+                    error!("register non-preemptible region for {} failed !", path);
+                }
+            }
+            0
+        }
+        _ = unsafe { libc::dl_iterate_phdr(Some(callback), std::ptr::null_mut()) };
+    }
+
+    fn init_signal_handler() -> std::io::Result<()> {
+        extern "C" fn sigurg_handler(
+            _: libc::c_int,
+            _: *mut libc::siginfo_t,
+            context: *mut c_void,
+        ) {
+            //信号处理函数可能修改errno，回到被中断的代码前需要恢复
+            let errno = Errno::last_raw();
             if let Ok(mut set) = SigSet::thread_get_mask() {
                 //删除对SIGURG信号的屏蔽，使信号处理函数即使在处理中，也可以再次进入信号处理函数
                 set.remove(Signal::SIGURG);
                 set.thread_set_mask()
                     .expect("Failed to remove SIGURG signal mask!");
-                if let Some(suspender) = SchedulableSuspender::current() {
+                let context = unsafe { &*context.cast::<libc::ucontext_t>() };
+                if PreemptionGuard::is_active()
+                    || is_non_preemptible(Monitor::instruction_pointer(context))
+                {
+                    //处于临界区或libc、动态链接器等不安全区域内，
+                    //推迟到临界区结束或下一个安全点(如hook的系统调用)再让出，
+                    //监控线程也会在1ms后重试
+                    PreemptionGuard::defer();
+                } else if let Some(suspender) = SchedulableSuspender::current() {
+                    //让出前恢复errno，使其随协程一起保存
+                    Errno::set_raw(errno);
                     suspender.suspend();
                 }
             }
+            Errno::set_raw(errno);
+        }
+        static SIGNAL_HANDLER_INITED: AtomicBool = AtomicBool::new(false);
+        if SIGNAL_HANDLER_INITED
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Ok(());
         }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        Self::register_system_regions();
+        // install SIGURG signal handler
+        let mut set = SigSet::empty();
+        set.add(Signal::SIGURG);
+        let sa = SigAction::new(
+            SigHandler::SigAction(sigurg_handler),
+            SaFlags::SA_RESTART | SaFlags::SA_SIGINFO,
+            set,
+        );
+        unsafe { _ = sigaction(Signal::SIGURG, &sa)? };
+        Ok(())
+    }
+
+    fn start(&self) -> std::io::Result<()> {
         match self.state.get() {
             MonitorState::Created => {
                 self.state.set(MonitorState::Running);
-                // install SIGURG signal handler
-                let mut set = SigSet::empty();
-                set.add(Signal::SIGURG);
-                let sa = SigAction::new(
-                    SigHandler::Handler(sigurg_handler),
-                    SaFlags::SA_RESTART,
-                    set,
-                );
-                unsafe { _ = sigaction(Signal::SIGURG, &sa)? };
+                Self::init_signal_handler()?;
                 // start the monitor thread
                 let monitor = unsafe { &mut *self.thread.get() };
                 *monitor = MaybeUninit::new(
                     std::thread::Builder::new()
                         .name("open-coroutine-monitor".to_string())
                         .spawn(|| {
+                            SignalStack::init_current();
                             info!("monitor started !");
                             if catch!(
                                 Self::monitor_thread_main,
@@ -173,12 +306,145 @@ impl Monitor {
 
 impl_current_for!(MONITOR, Monitor);
 
+/// The per-thread POSIX timer, which sends `SIGURG` to the thread when the time slice expires.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Debug)]
+struct SliceTimer(libc::timer_t);
+
+#[cfg(target_os = "linux")]
+thread_local! {
+    static SLICE_TIMER: OnceCell<Option<SliceTimer>> = const { OnceCell::new() };
+    //嵌套执行的协程各自的时间片截止时间，内层协程让出后恢复外层协程剩余的时间片
+    static SLICE_DEADLINES: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+#[cfg(target_os = "linux")]
+impl SliceTimer {
+    fn new() -> std::io::Result<Self> {
+        let mut event: libc::sigevent = unsafe { std::mem::zeroed() };
+        event.sigev_notify = libc::SIGEV_THREAD_ID;
+        event.sigev_signo = libc::SIGURG;
+        event.sigev_notify_thread_id = unsafe { libc::gettid() };
+        let mut timer = std::ptr::null_mut();
+        if unsafe {
+            libc::timer_create(
+                libc::CLOCK_MONOTONIC,
+                std::ptr::addr_of_mut!(event),
+                std::ptr::addr_of_mut!(timer),
+            )
+        } != 0
+        {
+            return Err(Error::last_os_error());
+        }
+        Ok(SliceTimer(timer))
+    }
+
+    #[allow(clippy::cast_possible_wrap, clippy::unnecessary_fallible_conversions)]
+    fn set(&self, value: Duration, interval: Duration) -> std::io::Result<()> {
+        //c_long在32位平台上是i32，纳秒部分小于10^9，不会溢出
+        let to_timespec = |dur: Duration| libc::timespec {
+            tv_sec: dur.as_secs() as libc::time_t,
+            tv_nsec: libc::c_long::try_from(dur.subsec_nanos()).unwrap_or(999_999_999),
+        };
+        let spec = libc::itimerspec {
+            it_interval: to_timespec(interval),
+            it_value: to_timespec(value),
+        };
+        if unsafe { libc::timer_settime(self.0, 0, std::ptr::addr_of!(spec), std::ptr::null_mut()) }
+            != 0
+        {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Returns how long until the timer of current thread expires, zero means disarmed.
+    #[cfg(test)]
+    fn remaining() -> Duration {
+        SLICE_TIMER.with(|timer| {
+            let Some(Some(timer)) = timer.get() else {
+                return Duration::ZERO;
+            };
+            let mut spec: libc::itimerspec = unsafe { std::mem::zeroed() };
+            assert_eq!(0, unsafe {
+                libc::timer_gettime(timer.0, std::ptr::addr_of_mut!(spec))
+            });
+            Duration::new(
+                u64::try_from(spec.it_value.tv_sec).expect("overflow"),
+                u32::try_from(spec.it_value.tv_nsec).expect("overflow"),
+            )
+        })
+    }
+
+    /// Arm the timer of current thread for a new time slice, returns `false` if the
+    /// timer is unavailable.
+    fn arm() -> bool {
+        if Monitor::init_signal_handler().is_err() {
+            return false;
+        }
+        //在状态切换中调用，退出临界区时不能让出
+        let _guard = DeferringGuard::new();
+        let armed = SLICE_TIMER.with(|timer| {
+            timer
+                .get_or_init(|| match Self::new() {
+                    Ok(timer) => Some(timer),
+                    Err(e) => {
+                        error!("create slice timer failed: {e}");
+                        None
+                    }
+                })
+                .as_ref()
+                //如果抢占调度失败，会在1ms后不断重试
+                .is_some_and(|timer| timer.set(SLICE, Duration::from_millis(1)).is_ok())
+        });
+        if armed {
+            SLICE_DEADLINES.with_borrow_mut(|deadlines| deadlines.push(get_timeout_time(SLICE)));
+        }
+        armed
+    }
+
+    /// End the time slice armed by the last [`SliceTimer::arm`], the timer of current
+    /// thread is re-armed for the rest of the outer coroutine's slice if any, otherwise
+    /// it's disarmed.
+    fn disarm() {
+        let _guard = DeferringGuard::new();
+        let outer = SLICE_DEADLINES.with_borrow_mut(|deadlines| {
+            _ = deadlines.pop();
+            deadlines.last().copied()
+        });
+        SLICE_TIMER.with(|timer| {
+            if let Some(Some(timer)) = timer.get() {
+                _ = match outer {
+                    //外层协程的时间片已用完时，尽快触发抢占
+                    Some(deadline) => timer.set(
+                        Duration::from_nanos(deadline.saturating_sub(now()).max(1)),
+                        Duration::from_millis(1),
+                    ),
+                    None => timer.set(Duration::ZERO, Duration::ZERO),
+                };
+            }
+        });
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for SliceTimer {
+    fn drop(&mut self) {
+        _ = unsafe { libc::timer_delete(self.0) };
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Default)]
 pub(crate) struct MonitorListener {}
 
 const NOTIFY_NODE: &str = "MONITOR_NODE";
 
+//本次执行是否使用了线程级的定时器
+#[cfg(target_os = "linux")]
+const SLICE_ARMED: &str = "MONITOR_SLICE_ARMED";
+
 impl<Yield, Return> Listener<Yield, Return> for MonitorListener {
     fn on_state_changed(
         &self,
@@ -192,7 +458,15 @@ impl<Yield, Return> Listener<Yield, Return> for MonitorListener {
         match new_state {
             CoroutineState::Ready => {}
             CoroutineState::Running => {
-                let timestamp = get_timeout_time(Duration::from_millis(10));
+                //优先使用线程级的定时器，不支持时退化为监控线程
+                #[cfg(target_os = "linux")]
+                {
+                    if SliceTimer::arm() {
+                        _ = local.put(SLICE_ARMED, true);
+                        return;
+                    }
+                }
+                let timestamp = get_timeout_time(SLICE);
                 if let Ok(node) = Monitor::submit(timestamp) {
                     _ = local.put(NOTIFY_NODE, node);
                 }
@@ -201,6 +475,10 @@ impl<Yield, Return> Listener<Yield, Return> for MonitorListener {
             | CoroutineState::SystemCall(_, _, _)
             | CoroutineState::Complete(_)
             | CoroutineState::Error(_) => {
+                #[cfg(target_os = "linux")]
+                if local.remove::<bool>(SLICE_ARMED).is_some() {
+                    SliceTimer::disarm();
+                }
                 if let Some(node) = local.get(NOTIFY_NODE) {
                     _ = Monitor::remove(node);
                 }
@@ -239,4 +517,55 @@ mod tests {
         assert!(SIGNALED.load(Ordering::Relaxed));
         Ok(())
     }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_nested_slice() {
+        use super::SliceTimer;
+        use std::time::Duration;
+
+        assert!(SliceTimer::arm());
+        std::thread::sleep(Duration::from_millis(5));
+        // the inner coroutine gets a whole slice
+        assert!(SliceTimer::arm());
+        SliceTimer::disarm();
+        // the outer coroutine gets the rest of its slice back
+        let remaining = SliceTimer::remaining();
+        assert!(!remaining.is_zero());
+        assert!(
+            remaining <= crate::common::constants::SLICE.saturating_sub(Duration::from_millis(5))
+        );
+        SliceTimer::disarm();
+        assert!(SliceTimer::remaining().is_zero());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_slice_timer_not_suspend() -> std::io::Result<()> {
+        use super::{Monitor, SliceTimer};
+        use crate::common::constants::CoroutineState;
+        use crate::coroutine::suspender::Suspender;
+        use crate::preempt::{DeferringGuard, PreemptionGuard};
+        use nix::sys::signal::{raise, Signal};
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        static DISARMED: AtomicBool = AtomicBool::new(false);
+        Monitor::init_signal_handler()?;
+        let mut coroutine = crate::co!(|_: &Suspender<'_, (), ()>, ()| {
+            {
+                //模拟在arm和disarm之间收到的SIGURG，它被推迟到下一个安全点
+                let _guard = DeferringGuard::new();
+                raise(Signal::SIGURG).expect("raise SIGURG failed");
+            }
+            // arm and disarm never serve the deferred preemption
+            assert!(SliceTimer::arm());
+            SliceTimer::disarm();
+            DISARMED.store(true, Ordering::Release);
+            PreemptionGuard::safepoint();
+        })?;
+        assert_eq!(CoroutineState::Suspend((), 0), coroutine.resume()?);
+        assert!(DISARMED.load(Ordering::Acquire));
+        assert_eq!(CoroutineState::Complete(()), coroutine.resume()?);
+        Ok(())
+    }
 }
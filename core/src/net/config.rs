@@ -1,4 +1,6 @@
-use crate::common::constants::{cpu_count, DEFAULT_STACK_SIZE};
+use crate::co_pool::autoscaler::Autoscaler;
+use crate::co_pool::DEFAULT_BLOCK_TIMEOUT;
+use crate::common::constants::{cpu_count, PlacementPolicy, RejectPolicy, DEFAULT_STACK_SIZE};
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
@@ -9,6 +11,25 @@ pub struct Config {
     max_size: usize,
     keep_alive_time: u64,
     hook: bool,
+    //事件循环线程被阻塞超过此时间(ns)则由其他线程接管调度，0表示不检测
+    blocked_time: u64,
+    //每个事件循环的任务队列容量，0表示不限制
+    queue_capacity: usize,
+    //任务队列已满时的拒绝策略
+    reject_policy: RejectPolicy,
+    //拒绝策略为Block时最多等待名额的时间(ns)
+    block_timeout: u64,
+    //是否开启自动扩缩容
+    autoscale: bool,
+    autoscaler: Autoscaler,
+    //未取走的结果的保留时间(ns)，0表示一直保留
+    result_ttl: u64,
+    //每个事件循环最多保留的未取走的结果数，0表示不限制
+    result_capacity: usize,
+    //任务提交到哪个事件循环
+    placement: PlacementPolicy,
+    //排队任务数超过此值时，空闲的事件循环主动窃取其任务，0表示不主动窃取
+    steal_threshold: usize,
 }
 
 impl Config {
@@ -33,6 +54,16 @@ impl Config {
             max_size,
             keep_alive_time,
             hook,
+            blocked_time: 0,
+            queue_capacity: 0,
+            reject_policy: RejectPolicy::Abort,
+            block_timeout: DEFAULT_BLOCK_TIMEOUT,
+            autoscale: false,
+            autoscaler: Autoscaler::default(),
+            result_ttl: 0,
+            result_capacity: 0,
+            placement: PlacementPolicy::RoundRobin,
+            steal_threshold: 0,
         }
     }
 
@@ -66,6 +97,51 @@ impl Config {
         self.hook
     }
 
+    #[must_use]
+    pub fn blocked_time(&self) -> u64 {
+        self.blocked_time
+    }
+
+    #[must_use]
+    pub fn queue_capacity(&self) -> usize {
+        self.queue_capacity
+    }
+
+    #[must_use]
+    pub fn reject_policy(&self) -> RejectPolicy {
+        self.reject_policy
+    }
+
+    #[must_use]
+    pub fn block_timeout(&self) -> u64 {
+        self.block_timeout
+    }
+
+    #[must_use]
+    pub fn autoscaler(&self) -> Option<Autoscaler> {
+        self.autoscale.then_some(self.autoscaler)
+    }
+
+    #[must_use]
+    pub fn result_ttl(&self) -> u64 {
+        self.result_ttl
+    }
+
+    #[must_use]
+    pub fn result_capacity(&self) -> usize {
+        self.result_capacity
+    }
+
+    #[must_use]
+    pub fn placement(&self) -> PlacementPolicy {
+        self.placement
+    }
+
+    #[must_use]
+    pub fn steal_threshold(&self) -> usize {
+        self.steal_threshold
+    }
+
     pub fn set_event_loop_size(&mut self, event_loop_size: usize) -> &mut Self {
         assert!(
             event_loop_size > 0,
@@ -105,6 +181,52 @@ impl Config {
         self.hook = hook;
         self
     }
+
+    pub fn set_blocked_time(&mut self, blocked_time: u64) -> &mut Self {
+        self.blocked_time = blocked_time;
+        self
+    }
+
+    pub fn set_queue_capacity(&mut self, queue_capacity: usize) -> &mut Self {
+        self.queue_capacity = queue_capacity;
+        self
+    }
+
+    pub fn set_reject_policy(&mut self, reject_policy: RejectPolicy) -> &mut Self {
+        self.reject_policy = reject_policy;
+        self
+    }
+
+    pub fn set_block_timeout(&mut self, block_timeout: u64) -> &mut Self {
+        self.block_timeout = block_timeout;
+        self
+    }
+
+    pub fn set_autoscaler(&mut self, autoscaler: Option<Autoscaler>) -> &mut Self {
+        self.autoscale = autoscaler.is_some();
+        self.autoscaler = autoscaler.unwrap_or_default();
+        self
+    }
+
+    pub fn set_result_ttl(&mut self, result_ttl: u64) -> &mut Self {
+        self.result_ttl = result_ttl;
+        self
+    }
+
+    pub fn set_result_capacity(&mut self, result_capacity: usize) -> &mut Self {
+        self.result_capacity = result_capacity;
+        self
+    }
+
+    pub fn set_placement(&mut self, placement: PlacementPolicy) -> &mut Self {
+        self.placement = placement;
+        self
+    }
+
+    pub fn set_steal_threshold(&mut self, steal_threshold: usize) -> &mut Self {
+        self.steal_threshold = steal_threshold;
+        self
+    }
 }
 
 impl Default for Config {
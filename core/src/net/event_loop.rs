@@ -1,26 +1,35 @@
+use crate::co_pool::retry::RetryPolicy;
+use crate::co_pool::task::{TaskId, TaskPriority};
 use crate::co_pool::CoroutinePool;
 use crate::common::beans::BeanFactory;
 use crate::common::constants::{CoroutineState, PoolState, Syscall, SyscallState, SLICE};
+use crate::coroutine::suspender::Suspender;
+use crate::coroutine::CoroutineId;
 use crate::net::selector::{Event, Events, Poller, Selector};
+use crate::preempt::PreemptionGuard;
 use crate::scheduler::SchedulableCoroutine;
-use crate::{error, impl_current_for, impl_display_by_debug, info};
+use crate::{error, impl_current_for, impl_display_by_debug, info, warn};
 use crossbeam_utils::atomic::AtomicCell;
 use dashmap::DashSet;
 use once_cell::sync::Lazy;
 use rand::Rng;
-use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::collections::VecDeque;
+use std::ffi::c_int;
+use std::hash::Hash;
 use std::io::{Error, ErrorKind};
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Condvar, Mutex};
-use std::thread::JoinHandle;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread::{JoinHandle, ThreadId};
 use std::time::Duration;
 
 cfg_if::cfg_if! {
     if #[cfg(all(target_os = "linux", feature = "io_uring"))] {
         use libc::{epoll_event, iovec, msghdr, off_t, size_t, sockaddr, socklen_t, ssize_t};
         use dashmap::DashMap;
+        use std::ffi::c_void;
     }
 }
 
@@ -39,6 +48,10 @@ pub(crate) struct EventLoop<'e> {
     syscall_wait_table: DashMap<usize, Arc<(Mutex<Option<ssize_t>>, Condvar)>>,
     selector: Poller,
     pool: CoroutinePool<'e>,
+    //其他事件循环饱和时，由本事件循环窃取其排队的任务
+    steal: Mutex<Option<Weak<EventLoop<'e>>>>,
+    //当前负责调度的线程，阻塞时调度权会被其他线程接管
+    owner: AtomicCell<Option<ThreadId>>,
     phantom_data: PhantomData<&'e EventLoop<'e>>,
 }
 
@@ -75,6 +88,14 @@ impl Default for EventLoop<'_> {
 
 static COROUTINE_TOKENS: Lazy<DashSet<usize>> = Lazy::new(DashSet::new);
 
+//调度权被接管后留作备用的线程，接管时通过它们发送event-loop的名称
+#[allow(clippy::type_complexity)]
+static SPARES: Lazy<Mutex<VecDeque<(u64, Sender<String>)>>> =
+    Lazy::new(|| Mutex::new(VecDeque::new()));
+
+//备用线程的保活时间
+const SPARE_KEEP_ALIVE: Duration = Duration::from_secs(30);
+
 impl<'e> EventLoop<'e> {
     pub(super) fn new(
         name: String,
@@ -96,6 +117,8 @@ impl<'e> EventLoop<'e> {
             syscall_wait_table: DashMap::new(),
             selector: Poller::new()?,
             pool: CoroutinePool::new(name, stack_size, min_size, max_size, keep_alive_time),
+            steal: Mutex::new(None),
+            owner: AtomicCell::new(None),
             phantom_data: PhantomData,
         })
     }
@@ -103,12 +126,10 @@ impl<'e> EventLoop<'e> {
     #[allow(trivial_numeric_casts, clippy::cast_possible_truncation)]
     fn token(syscall: Syscall) -> usize {
         if let Some(co) = SchedulableCoroutine::current() {
-            let boxed: &'static mut CString = Box::leak(Box::from(
-                CString::new(co.name()).expect("build name failed!"),
-            ));
-            let cstr: &'static CStr = boxed.as_c_str();
-            let token = cstr.as_ptr().cast::<c_void>() as usize;
-            assert!(COROUTINE_TOKENS.insert(token));
+            let token = u64::from(co.id()) as usize;
+            let _guard = PreemptionGuard::new();
+            //上次等待超时的token可能还在，直接复用
+            _ = COROUTINE_TOKENS.insert(token);
             return token;
         }
         unsafe {
@@ -128,6 +149,238 @@ impl<'e> EventLoop<'e> {
         }
     }
 
+    /// Submit a new task to this event-loop, and wake up the event-loop if it's idle.
+    pub(super) fn submit_task(
+        &self,
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + 'e,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+    ) -> std::io::Result<TaskId> {
+        let task_id = self.pool.submit_task(name, func, param, priority)?;
+        self.wakeup();
+        Ok(task_id)
+    }
+
+    /// Submit a new task with the retry `policy` to this event-loop, and wake up the
+    /// event-loop if it's idle.
+    pub(super) fn submit_task_with_retry(
+        &self,
+        name: Option<String>,
+        func: impl Fn(Option<usize>) -> Result<Option<usize>, &'e str> + 'e,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+        policy: RetryPolicy,
+    ) -> std::io::Result<TaskId> {
+        let task_id = self
+            .pool
+            .submit_task_with_retry(name, func, param, priority, policy)?;
+        self.wakeup();
+        Ok(task_id)
+    }
+
+    /// Submit a new keyed task to this event-loop, and wake up the event-loop if it's idle.
+    pub(super) fn submit_keyed_task(
+        &self,
+        key: &(impl Hash + ?Sized),
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + 'e,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+    ) -> std::io::Result<TaskId> {
+        let task_id = self
+            .pool
+            .submit_keyed_task(key, name, func, param, priority)?;
+        self.wakeup();
+        Ok(task_id)
+    }
+
+    /// Submit a new grouped task to this event-loop, and wake up the event-loop if it's idle.
+    pub(super) fn submit_group_task(
+        &self,
+        group: &str,
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + 'e,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+    ) -> std::io::Result<TaskId> {
+        let task_id = self
+            .pool
+            .submit_group_task(group, name, func, param, priority)?;
+        self.wakeup();
+        Ok(task_id)
+    }
+
+    /// Set the limits of the `group` in this event-loop, and wake up the event-loop to
+    /// run the waiting tasks.
+    pub(super) fn set_group_limit(&self, group: &str, limit: usize, capacity: usize) {
+        self.pool.set_group_limit(group, limit, capacity);
+        self.wakeup();
+    }
+
+    /// Submit a new detached task to this event-loop, and wake up the event-loop if it's idle.
+    pub(super) fn submit_detached_task(
+        &self,
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + 'e,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+    ) -> std::io::Result<TaskId> {
+        let task_id = self
+            .pool
+            .submit_detached_task(name, func, param, priority)?;
+        self.wakeup();
+        Ok(task_id)
+    }
+
+    /// Submit a new task which is executed at the `timestamp` to this event-loop,
+    /// and wake up the event-loop to recalculate how long it can block.
+    pub(super) fn submit_task_at(
+        &self,
+        timestamp: u64,
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + 'e,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+    ) -> std::io::Result<TaskId> {
+        let task_id = self
+            .pool
+            .submit_task_at(timestamp, name, func, param, priority)?;
+        self.wakeup();
+        Ok(task_id)
+    }
+
+    /// Submit a periodic task to this event-loop, see [`CoroutinePool::schedule_at_fixed_rate`].
+    pub(super) fn schedule_at_fixed_rate(
+        &self,
+        timestamp: u64,
+        name: Option<String>,
+        func: impl Fn(Option<usize>) -> Option<usize> + 'e,
+        param: Option<usize>,
+        period: Duration,
+        priority: Option<TaskPriority>,
+    ) -> std::io::Result<TaskId> {
+        let task_id = self
+            .pool
+            .schedule_at_fixed_rate(timestamp, name, func, param, period, priority)?;
+        self.wakeup();
+        Ok(task_id)
+    }
+
+    /// Submit a periodic task to this event-loop, see [`CoroutinePool::schedule_with_fixed_delay`].
+    pub(super) fn schedule_with_fixed_delay(
+        &self,
+        timestamp: u64,
+        name: Option<String>,
+        func: impl Fn(Option<usize>) -> Option<usize> + 'e,
+        param: Option<usize>,
+        delay: Duration,
+        priority: Option<TaskPriority>,
+    ) -> std::io::Result<TaskId> {
+        let task_id = self
+            .pool
+            .schedule_with_fixed_delay(timestamp, name, func, param, delay, priority)?;
+        self.wakeup();
+        Ok(task_id)
+    }
+
+    /// Submit a continuation of the task to this event-loop, and wake up the event-loop
+    /// if it's idle, see [`CoroutinePool::submit_continuation`].
+    pub(super) fn submit_continuation(
+        &self,
+        task_id: TaskId,
+        func: impl FnOnce(Result<Option<usize>, &'e str>) -> Result<Option<usize>, &'e str> + 'e,
+        detached: bool,
+    ) -> std::io::Result<TaskId> {
+        let continuation_id = self.pool.submit_continuation(task_id, func, detached)?;
+        self.wakeup();
+        Ok(continuation_id)
+    }
+
+    /// Submit a new coroutine to this event-loop, and wake up the event-loop if it's idle.
+    pub(super) fn submit_co(
+        &self,
+        f: impl FnOnce(&Suspender<(), ()>, ()) -> Option<usize> + 'static,
+        stack_size: Option<usize>,
+    ) -> std::io::Result<CoroutineId> {
+        let co_id = self.pool.submit_co(f, stack_size)?;
+        self.wakeup();
+        Ok(co_id)
+    }
+
+    /// Wake up the thread which is blocked in the selector,
+    /// nothing happens if it's called by the thread scheduling this event-loop.
+    fn wakeup(&self) {
+        if self.owner.load() == Some(std::thread::current().id()) {
+            return;
+        }
+        if let Err(e) = self.selector.wakeup() {
+            error!("{} wakeup failed: {e}", self.name());
+        }
+    }
+
+    /// Ask this event-loop to steal the queued tasks from the saturated `victim`,
+    /// the tasks can only be stolen by the event-loop thread itself.
+    pub(super) fn request_steal(&self, victim: &Arc<EventLoop<'e>>) {
+        {
+            let _guard = PreemptionGuard::new();
+            *self.steal.lock().expect("lock failed") = Some(Arc::downgrade(victim));
+        }
+        self.wakeup();
+    }
+
+    /// Returns how long the event-loop thread can block in the selector,
+    /// `None` means until events happen or someone wakes it up. It never blocks
+    /// for more than a slice if other queues have tasks to steal.
+    fn idle_timeout(&self) -> Option<Duration> {
+        if !self.is_empty() && self.get_running_size() < self.get_max_size() {
+            //还有任务等待创建worker协程执行
+            return Some(Duration::ZERO);
+        }
+        let mut next = self.next_schedule_time();
+        if self.get_running_size() < self.get_max_size() && self.can_steal_tasks() {
+            //全局队列或其他事件循环还有任务，阻塞不能超过一个时间片
+            next = next.min(crate::common::get_timeout_time(SLICE));
+        }
+        cfg_if::cfg_if! {
+            if #[cfg(all(target_os = "linux", feature = "io_uring"))] {
+                //io_uring无法被唤醒，阻塞时间不能超过一个时间片
+                let next = next.min(crate::common::get_timeout_time(SLICE));
+            }
+        }
+        if u64::MAX == next {
+            return None;
+        }
+        Some(Duration::from_nanos(
+            next.saturating_sub(crate::common::now()),
+        ))
+    }
+
+    /// Schedule for one time slice, then block in the selector until events happen,
+    /// a timer expires or this event-loop is woken up.
+    fn run_once(&mut self) -> std::io::Result<()> {
+        let victim = {
+            let _guard = PreemptionGuard::new();
+            self.steal.lock().expect("lock failed").take()
+        };
+        if let Some(victim) = victim.as_ref().and_then(Weak::upgrade) {
+            _ = self.steal_tasks_from(&victim);
+        }
+        let handoffs = self.handoffs();
+        let left_time = self.try_timed_schedule_task(SLICE)?;
+        if handoffs != self.handoffs() {
+            //调度权已被接管，不能再等待事件
+            return Ok(());
+        }
+        //时间片用完说明还有就绪的协程，只检查事件不阻塞
+        let timeout = if 0 == left_time {
+            Some(Duration::ZERO)
+        } else {
+            self.idle_timeout()
+        };
+        self.wait_just(timeout)
+    }
+
     pub(super) fn add_read_event(&self, fd: c_int) -> std::io::Result<()> {
         self.selector
             .add_read_event(fd, EventLoop::token(Syscall::nio()))
@@ -268,9 +521,8 @@ impl<'e> EventLoop<'e> {
         if COROUTINE_TOKENS.remove(&token).is_none() {
             return;
         }
-        if let Ok(co_name) = CStr::from_ptr((token as *const c_void).cast::<c_char>()).to_str() {
-            self.try_resume(co_name);
-        }
+        self.try_resume(CoroutineId::from(token as u64));
+        self.wakeup();
     }
 
     pub(super) fn start(self) -> std::io::Result<Arc<Self>>
@@ -288,6 +540,10 @@ impl<'e> EventLoop<'e> {
         let bean_name = self.name().to_string().leak();
         let bean_name_in_thread = self.name().to_string().leak();
         BeanFactory::init_bean(bean_name, self);
+        let event_loop = BeanFactory::get_bean::<Self>(bean_name)
+            .unwrap_or_else(|| panic!("bean {bean_name} not exist !"));
+        //其他线程唤醒本event-loop的协程后，还要唤醒阻塞在selector上的线程
+        event_loop.set_waker(move || event_loop.wakeup());
         BeanFactory::init_bean(
             &thread_name,
             std::thread::Builder::new()
@@ -302,34 +558,7 @@ impl<'e> EventLoop<'e> {
                         _ = started.fetch_add(1, Ordering::Release);
                         cvar.notify_one();
                     }
-                    // thread per core
-                    info!(
-                        "{} has started, bind to CPU:{}",
-                        consumer.name(),
-                        core_affinity::set_for_current(core_affinity::CoreId { id: consumer.cpu })
-                    );
-                    Self::init_current(consumer);
-                    while PoolState::Running == consumer.state()
-                        || !consumer.is_empty()
-                        || consumer.get_running_size() > 0
-                    {
-                        _ = consumer.wait_event(Some(SLICE));
-                    }
-                    // notify stop flags
-                    {
-                        let (lock, cvar) = &*consumer.stop.clone();
-                        let mut pending = lock.lock().expect("lock failed");
-                        *pending = false;
-                        cvar.notify_one();
-                    }
-                    {
-                        let (lock, cvar) = &*consumer.shared_stop.clone();
-                        let started = lock.lock().expect("lock failed");
-                        _ = started.fetch_sub(1, Ordering::Release);
-                        cvar.notify_one();
-                    }
-                    Self::clean_current();
-                    info!("{} has exited", consumer.name());
+                    Self::serve(bean_name_in_thread);
                 })?,
         );
         unsafe {
@@ -340,6 +569,136 @@ impl<'e> EventLoop<'e> {
         }
     }
 
+    /// Schedule the event-loop with the `bean_name` on the current thread, and keep the
+    /// thread as a spare each time the scheduling is taken over.
+    fn serve(bean_name: &str)
+    where
+        'e: 'static,
+    {
+        let mut bean_name = bean_name.to_string();
+        loop {
+            let consumer = unsafe { BeanFactory::get_mut_bean::<Self>(&bean_name) }
+                .unwrap_or_else(|| panic!("bean {bean_name} not exist !"));
+            if !Self::do_loop(consumer) {
+                return;
+            }
+            let Some(next) = Self::spare() else {
+                return;
+            };
+            bean_name = next;
+        }
+    }
+
+    /// Returns `true` if the scheduling has been taken over by another thread.
+    fn do_loop(consumer: &mut Self) -> bool
+    where
+        'e: 'static,
+    {
+        #[cfg(unix)]
+        crate::coroutine::SignalStack::init_current();
+        // thread per core
+        info!(
+            "{} has started, bind to CPU:{}",
+            consumer.name(),
+            core_affinity::set_for_current(core_affinity::CoreId { id: consumer.cpu })
+        );
+        Self::init_current(consumer);
+        let handoffs = consumer.handoffs();
+        consumer.owner.store(Some(std::thread::current().id()));
+        while handoffs == consumer.handoffs()
+            && (PoolState::Running == consumer.state()
+                || !consumer.is_empty()
+                || consumer.get_running_size() > 0)
+        {
+            _ = consumer.run_once();
+        }
+        Self::clean_current();
+        if handoffs != consumer.handoffs() {
+            //阻塞的协程已归还，唤醒接管的线程处理它
+            if let Err(e) = consumer.selector.wakeup() {
+                error!("{} wakeup failed: {e}", consumer.name());
+            }
+            info!("{} was taken over", consumer.name());
+            return true;
+        }
+        // notify stop flags
+        {
+            let (lock, cvar) = &*consumer.stop.clone();
+            let mut pending = lock.lock().expect("lock failed");
+            *pending = false;
+            cvar.notify_one();
+        }
+        {
+            let (lock, cvar) = &*consumer.shared_stop.clone();
+            let started = lock.lock().expect("lock failed");
+            _ = started.fetch_sub(1, Ordering::Release);
+            cvar.notify_one();
+        }
+        info!("{} has exited", consumer.name());
+        false
+    }
+
+    /// Wait on the current thread until another event-loop needs to be taken over,
+    /// returns the bean name of it, or `None` if nobody needs this thread for a while.
+    fn spare() -> Option<String> {
+        static SPARE_ID: AtomicU64 = AtomicU64::new(0);
+        let id = SPARE_ID.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        SPARES.lock().expect("lock failed").push_back((id, sender));
+        if let Ok(bean_name) = receiver.recv_timeout(SPARE_KEEP_ALIVE) {
+            return Some(bean_name);
+        }
+        {
+            let mut spares = SPARES.lock().expect("lock failed");
+            if let Some(index) = spares.iter().position(|(spare, _)| *spare == id) {
+                _ = spares.remove(index);
+                return None;
+            }
+        }
+        //已被选中，名称马上就到
+        receiver.recv().ok()
+    }
+
+    /// Hand the scheduling of this event-loop over to another thread if the current
+    /// thread has been blocked in a coroutine for more than `blocked_time` ns.
+    ///
+    /// The new thread owns the ready queue, the suspended, parked and syscall
+    /// coroutines and the selector from then on. The blocked coroutine stays on its
+    /// thread until it returns, then it's handed back to the new thread, and the
+    /// blocked thread is kept as a spare for the next handoff.
+    pub(super) fn try_handoff(&self, blocked_time: u64) -> std::io::Result<bool>
+    where
+        'e: 'static,
+    {
+        let Some(ticket) = self.pool.blocked(blocked_time) else {
+            return Ok(false);
+        };
+        if !self.pool.take_over(ticket) {
+            //协程已返回或者已被接管
+            return Ok(false);
+        }
+        warn!(
+            "{} was blocked, hand it over to another thread",
+            self.name()
+        );
+        let mut bean_name = self.name().to_string();
+        //优先使用备用线程
+        while let Some((_, spare)) = Self::pop_spare() {
+            match spare.send(bean_name) {
+                Ok(()) => return Ok(true),
+                Err(e) => bean_name = e.0,
+            }
+        }
+        _ = std::thread::Builder::new()
+            .name(format!("{}-{ticket}", self.get_thread_name()))
+            .spawn(move || Self::serve(&bean_name))?;
+        Ok(true)
+    }
+
+    fn pop_spare() -> Option<(u64, Sender<String>)> {
+        SPARES.lock().expect("lock failed").pop_front()
+    }
+
     fn get_thread_name(&self) -> String {
         format!("{}-thread", self.name())
     }
@@ -371,6 +730,7 @@ impl<'e> EventLoop<'e> {
             PoolState::Running => {
                 if BeanFactory::remove_bean::<JoinHandle<()>>(&self.get_thread_name()).is_some() {
                     assert_eq!(PoolState::Running, self.stopping()?);
+                    self.wakeup();
                     //开启了单独的线程
                     let (lock, cvar) = &*self.stop;
                     let result = cvar
@@ -442,13 +802,15 @@ impl_io_uring!(sendmsg(fd: c_int, msg: *const msghdr, flags: c_int) -> ssize_t);
 #[cfg(all(test, not(all(unix, feature = "preemptive"))))]
 mod tests {
     use crate::net::event_loop::EventLoop;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::{Arc, Condvar, Mutex};
     use std::time::Duration;
 
     #[test]
     fn test_simple() -> std::io::Result<()> {
         let mut event_loop = EventLoop::default();
         event_loop.set_max_size(1);
-        _ = event_loop.submit_task(None, |_| panic!("test panic, just ignore it"), None)?;
+        _ = event_loop.submit_task(None, |_| panic!("test panic, just ignore it"), None, None)?;
         _ = event_loop.submit_task(
             None,
             |_| {
@@ -456,6 +818,7 @@ mod tests {
                 Some(2)
             },
             None,
+            None,
         )?;
         event_loop.stop_sync(Duration::from_secs(3))
     }
@@ -465,7 +828,7 @@ mod tests {
     fn test_simple_auto() -> std::io::Result<()> {
         let event_loop = EventLoop::default().start()?;
         event_loop.set_max_size(1);
-        _ = event_loop.submit_task(None, |_| panic!("test panic, just ignore it"), None)?;
+        _ = event_loop.submit_task(None, |_| panic!("test panic, just ignore it"), None, None)?;
         _ = event_loop.submit_task(
             None,
             |_| {
@@ -473,7 +836,93 @@ mod tests {
                 Some(2)
             },
             None,
+            None,
+        )?;
+        event_loop.stop(Duration::from_secs(3))
+    }
+
+    #[test]
+    fn test_handoff() -> std::io::Result<()> {
+        let event_loop = EventLoop::new(
+            String::from("test-handoff-event-loop"),
+            0,
+            crate::common::constants::DEFAULT_STACK_SIZE,
+            0,
+            65536,
+            0,
+            Arc::new((Mutex::new(AtomicUsize::new(0)), Condvar::new())),
+        )?
+        .start()?;
+        let (tx, rx) = std::sync::mpsc::channel();
+        let suspended_tx = tx.clone();
+        _ = event_loop.submit_co(
+            move |suspender, ()| {
+                suspended_tx.send(0).expect("send failed");
+                //阻塞期间在时间轮中到期
+                suspender.delay(Duration::from_millis(100));
+                suspended_tx.send(1).expect("send failed");
+                None
+            },
+            None,
+        )?;
+        assert_eq!(Ok(0), rx.recv_timeout(Duration::from_secs(3)));
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        let blocked_tx = tx.clone();
+        let pair = Arc::new((Mutex::new(false), Condvar::new()));
+        let pair2 = pair.clone();
+        _ = event_loop.submit_task(
+            None,
+            move |_| {
+                started_tx.send(()).expect("send failed");
+                //模拟调用未hook的阻塞函数
+                let (lock, cvar) = &*pair2;
+                drop(
+                    cvar.wait_while(lock.lock().expect("lock failed"), |released| !*released)
+                        .expect("lock failed"),
+                );
+                blocked_tx.send(4).expect("send failed");
+                Some(4)
+            },
+            None,
+            None,
         )?;
+        started_rx
+            .recv_timeout(Duration::from_secs(3))
+            .expect("the blocking task not started");
+        let ready_tx = tx.clone();
+        _ = event_loop.submit_co(
+            move |_, ()| {
+                ready_tx.send(2).expect("send failed");
+                None
+            },
+            None,
+        )?;
+        _ = event_loop.submit_task(
+            None,
+            move |_| {
+                tx.send(3).expect("send failed");
+                Some(3)
+            },
+            None,
+            None,
+        )?;
+        assert!(event_loop.try_handoff(0)?);
+        //被阻塞的事件循环的就绪协程、挂起协程和排队的任务由接管的线程执行
+        let mut received: Vec<i32> = (0..3)
+            .map(|_| {
+                rx.recv_timeout(Duration::from_secs(3))
+                    .expect("not progress")
+            })
+            .collect();
+        received.sort_unstable();
+        assert_eq!(vec![1, 2, 3], received);
+        {
+            let (lock, cvar) = &*pair;
+            *lock.lock().expect("lock failed") = true;
+            cvar.notify_one();
+        }
+        assert_eq!(Ok(4), rx.recv_timeout(Duration::from_secs(3)));
+        //阻塞的协程归还后由接管的线程完成停止
         event_loop.stop(Duration::from_secs(3))
     }
 }
@@ -0,0 +1,336 @@
+use crate::co_pool::task::{TaskId, TaskPriority, TASK_CANCELLED};
+use crate::net::join::{join_all, JoinHandle};
+use crate::net::EventLoops;
+use derivative::Derivative;
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind};
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The id of a node in the [`TaskGraph`].
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct NodeId(usize);
+
+type NodeFunc = Box<dyn FnOnce(&[Option<usize>]) -> Option<usize>>;
+
+#[repr(C)]
+#[derive(Derivative)]
+#[derivative(Debug)]
+struct Node {
+    name: Option<String>,
+    #[derivative(Debug = "ignore")]
+    func: NodeFunc,
+    priority: Option<TaskPriority>,
+    dependencies: Vec<NodeId>,
+}
+
+/// A builder of tasks which depend on each other.
+///
+/// Each node is submitted by [`EventLoops::submit_task`] once all of its dependencies
+/// succeed, and receives their results in the order they were added. If a node fails,
+/// all of its downstream nodes are cancelled instead of being submitted.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct TaskGraph {
+    nodes: Vec<Node>,
+}
+
+impl TaskGraph {
+    /// Create a new empty `TaskGraph`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a node which runs `func` with the results of its dependencies.
+    pub fn add_node(
+        &mut self,
+        name: Option<String>,
+        func: impl FnOnce(&[Option<usize>]) -> Option<usize> + 'static,
+        priority: Option<TaskPriority>,
+    ) -> NodeId {
+        self.nodes.push(Node {
+            name,
+            func: Box::new(func),
+            priority,
+            dependencies: Vec::new(),
+        });
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Make `node` wait for the result of `dependency`.
+    pub fn add_dependency(&mut self, node: NodeId, dependency: NodeId) -> &mut Self {
+        assert!(
+            dependency.0 < self.nodes.len(),
+            "dependency {dependency:?} not found"
+        );
+        self.nodes
+            .get_mut(node.0)
+            .unwrap_or_else(|| panic!("node {node:?} not found"))
+            .dependencies
+            .push(dependency);
+        self
+    }
+
+    /// Returns the nodes in topological order.
+    fn sort(&self) -> std::io::Result<Vec<usize>> {
+        let mut pending: Vec<usize> = self.nodes.iter().map(|n| n.dependencies.len()).collect();
+        let mut dependents = vec![Vec::new(); self.nodes.len()];
+        for (index, node) in self.nodes.iter().enumerate() {
+            for dependency in &node.dependencies {
+                dependents[dependency.0].push(index);
+            }
+        }
+        let mut ready: VecDeque<usize> = (0..self.nodes.len())
+            .filter(|index| 0 == pending[*index])
+            .collect();
+        let mut sorted = Vec::with_capacity(self.nodes.len());
+        while let Some(index) = ready.pop_front() {
+            sorted.push(index);
+            for dependent in &dependents[index] {
+                pending[*dependent] -= 1;
+                if 0 == pending[*dependent] {
+                    ready.push_back(*dependent);
+                }
+            }
+        }
+        if sorted.len() < self.nodes.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "The task graph has a cycle !",
+            ));
+        }
+        Ok(sorted)
+    }
+
+    /// Check the graph and submit the nodes without dependencies.
+    ///
+    /// # Errors
+    /// if the graph has a cycle, nothing is submitted.
+    pub fn submit(self) -> std::io::Result<GraphHandle> {
+        let sorted = self.sort()?;
+        let mut dependents = vec![Vec::new(); self.nodes.len()];
+        let mut states = Vec::with_capacity(self.nodes.len());
+        for (index, node) in self.nodes.into_iter().enumerate() {
+            for (slot, dependency) in node.dependencies.iter().enumerate() {
+                dependents[dependency.0].push((index, slot));
+            }
+            states.push(NodeState::Pending {
+                pending: node.dependencies.len(),
+                inputs: vec![None; node.dependencies.len()],
+                name: node.name,
+                func: node.func,
+                priority: node.priority,
+            });
+        }
+        let graph = Arc::new(GraphState {
+            states: Mutex::new(states),
+            dependents,
+        });
+        for index in &sorted {
+            graph.try_submit(*index);
+        }
+        Ok(GraphHandle { graph, sorted })
+    }
+}
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+enum NodeState {
+    Pending {
+        pending: usize,
+        inputs: Vec<Option<usize>>,
+        name: Option<String>,
+        #[derivative(Debug = "ignore")]
+        func: NodeFunc,
+        priority: Option<TaskPriority>,
+    },
+    Submitted(JoinHandle),
+    Done(Result<Option<usize>, &'static str>),
+}
+
+#[derive(Debug)]
+struct GraphState {
+    states: Mutex<Vec<NodeState>>,
+    //(下游节点, 输入位置)
+    dependents: Vec<Vec<(usize, usize)>>,
+}
+
+//和提交到`EventLoops`的任务一样，节点可能在任意事件循环上执行
+unsafe impl Send for GraphState {}
+
+unsafe impl Sync for GraphState {}
+
+impl GraphState {
+    /// Submit the node if all of its dependencies succeeded.
+    fn try_submit(self: &Arc<Self>, index: usize) {
+        let mut states = self.states.lock().expect("lock failed");
+        let NodeState::Pending { pending: 0, .. } = states[index] else {
+            return;
+        };
+        let NodeState::Pending {
+            inputs,
+            name,
+            func,
+            priority,
+            ..
+        } = std::mem::replace(&mut states[index], NodeState::Done(Err(TASK_CANCELLED)))
+        else {
+            unreachable!()
+        };
+        //提交时不能持有锁，拒绝策略可能让当前线程直接执行任务
+        drop(states);
+        let graph = self.clone();
+        let handle = EventLoops::submit_task(
+            name,
+            move |_| match std::panic::catch_unwind(AssertUnwindSafe(|| func(&inputs))) {
+                Ok(output) => {
+                    graph.complete(index, output);
+                    output
+                }
+                Err(e) => {
+                    graph.cancel_dependents(index);
+                    std::panic::resume_unwind(e)
+                }
+            },
+            None,
+            priority,
+        );
+        if TaskId::INVALID != handle.id() {
+            self.states.lock().expect("lock failed")[index] = NodeState::Submitted(handle);
+            return;
+        }
+        self.states.lock().expect("lock failed")[index] =
+            NodeState::Done(Err("The task was rejected !"));
+        self.cancel_dependents(index);
+    }
+
+    /// Pass the output to the downstream nodes and submit the ready ones.
+    fn complete(self: &Arc<Self>, index: usize, output: Option<usize>) {
+        let mut ready = Vec::new();
+        {
+            let mut states = self.states.lock().expect("lock failed");
+            for (dependent, slot) in &self.dependents[index] {
+                if let NodeState::Pending {
+                    pending, inputs, ..
+                } = &mut states[*dependent]
+                {
+                    inputs[*slot] = output;
+                    *pending -= 1;
+                    if 0 == *pending {
+                        ready.push(*dependent);
+                    }
+                }
+            }
+        }
+        for dependent in ready {
+            self.try_submit(dependent);
+        }
+    }
+
+    /// Cancel all the downstream nodes of the failed one.
+    fn cancel_dependents(&self, index: usize) {
+        let mut states = self.states.lock().expect("lock failed");
+        let mut failed = VecDeque::from([index]);
+        while let Some(index) = failed.pop_front() {
+            for (dependent, _) in &self.dependents[index] {
+                if let NodeState::Pending { .. } = states[*dependent] {
+                    //丢弃闭包，及时释放其捕获的资源
+                    states[*dependent] = NodeState::Done(Err(TASK_CANCELLED));
+                    failed.push_back(*dependent);
+                }
+            }
+        }
+    }
+}
+
+/// The handle to join the outputs of a submitted [`TaskGraph`].
+#[repr(C)]
+#[derive(Debug)]
+pub struct GraphHandle {
+    graph: Arc<GraphState>,
+    sorted: Vec<usize>,
+}
+
+impl GraphHandle {
+    /// join with `Duration`.
+    ///
+    /// # Errors
+    /// see `timeout_at_join`.
+    #[allow(clippy::type_complexity)]
+    pub fn timeout_join(
+        &self,
+        dur: Duration,
+    ) -> std::io::Result<Vec<Result<Option<usize>, &'static str>>> {
+        self.timeout_at_join(crate::common::get_timeout_time(dur))
+    }
+
+    /// join.
+    ///
+    /// # Errors
+    /// see `timeout_at_join`.
+    #[allow(clippy::type_complexity)]
+    pub fn join(&self) -> std::io::Result<Vec<Result<Option<usize>, &'static str>>> {
+        self.timeout_at_join(u64::MAX)
+    }
+
+    /// Wait for all the nodes to complete before the `timeout_time` timestamp, the
+    /// results are in the same order as the nodes were added, the cancelled nodes
+    /// are `Err(TASK_CANCELLED)`.
+    ///
+    /// # Errors
+    /// if timeout, the collected outputs are kept for the next join.
+    #[allow(clippy::type_complexity)]
+    pub fn timeout_at_join(
+        &self,
+        timeout_time: u64,
+    ) -> std::io::Result<Vec<Result<Option<usize>, &'static str>>> {
+        let mut results = vec![Err(TASK_CANCELLED); self.sorted.len()];
+        //按拓扑序等待，等到上游的结果时下游已经提交或取消
+        for index in &self.sorted {
+            let mut states = self.graph.states.lock().expect("lock failed");
+            results[*index] = match &states[*index] {
+                NodeState::Submitted(_) => {
+                    let NodeState::Submitted(handle) = std::mem::replace(
+                        &mut states[*index],
+                        NodeState::Done(Err(TASK_CANCELLED)),
+                    ) else {
+                        unreachable!()
+                    };
+                    drop(states);
+                    let result = join_all(&[&handle], timeout_time)
+                        .map(|mut result| result.pop().expect("the result should be collected"));
+                    //保存结果，超时后可以再次等待
+                    self.graph.states.lock().expect("lock failed")[*index] = match result {
+                        Ok(result) => NodeState::Done(result),
+                        Err(_) => NodeState::Submitted(handle),
+                    };
+                    result?
+                }
+                NodeState::Done(result) => *result,
+                //上游被外部取消，永远不会提交
+                NodeState::Pending { .. } => Err(TASK_CANCELLED),
+            };
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort() {
+        let mut graph = TaskGraph::new();
+        let a = graph.add_node(None, |_| Some(1), None);
+        let b = graph.add_node(None, |_| Some(2), None);
+        let c = graph.add_node(None, |inputs| inputs[0], None);
+        _ = graph.add_dependency(a, c).add_dependency(b, c);
+        assert_eq!(vec![2, 0, 1], graph.sort().unwrap());
+        _ = graph.add_dependency(c, a);
+        assert_eq!(ErrorKind::InvalidInput, graph.sort().unwrap_err().kind());
+    }
+}
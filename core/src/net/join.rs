@@ -1,37 +1,110 @@
+use crate::co_pool::retry::Attempt;
+use crate::co_pool::task::{cancelled_error, is_cancelled, TaskId};
+use crate::co_pool::waiter::{put_back, wait_results, TaskResult};
+use crate::co_pool::CoroutinePool;
 use crate::net::event_loop::EventLoop;
-use std::ffi::{c_char, CStr, CString};
+use std::ffi::c_longlong;
 use std::io::{Error, ErrorKind};
 use std::sync::Arc;
 use std::time::Duration;
 
-#[allow(missing_docs)]
+/// The value returned by the C ABI join functions when the task was cancelled.
+pub const JOIN_CANCELLED: c_longlong = -2;
+
+/// The pointer-sized variant of the task handle, used by the C ABI.
+///
+/// It only borrows the event loop which lives as long as the runtime, so nothing
+/// needs to be released when it's dropped on either side of the C ABI.
+///
+/// It has no `Drop` since it's passed by value across the C ABI, so dropping it
+/// doesn't detach the task: the result is kept by the event loop until it's joined.
+/// The handles which will never be joined should be [`JoinHandle::detach`]ed, or the
+/// kept results should be bounded by [`crate::net::config::Config::set_result_ttl`]
+/// and [`crate::net::config::Config::set_result_capacity`].
+#[allow(missing_docs, missing_copy_implementations)]
 #[repr(C)]
 #[derive(Debug)]
-pub struct JoinHandle(&'static Arc<EventLoop<'static>>, *const c_char);
+pub struct JoinHandle(&'static Arc<EventLoop<'static>>, TaskId);
 
 impl JoinHandle {
     /// create `JoinHandle` instance.
     pub(crate) fn err(pool: &'static Arc<EventLoop<'static>>) -> Self {
-        Self::new(pool, "")
+        Self::new(pool, TaskId::INVALID)
     }
 
     /// create `JoinHandle` instance.
-    pub(crate) fn new(pool: &'static Arc<EventLoop<'static>>, name: &str) -> Self {
-        let boxed: &'static mut CString = Box::leak(Box::from(
-            CString::new(name).expect("init JoinHandle failed!"),
-        ));
-        let cstr: &'static CStr = boxed.as_c_str();
-        JoinHandle(pool, cstr.as_ptr())
+    pub(crate) fn new(pool: &'static Arc<EventLoop<'static>>, task_id: TaskId) -> Self {
+        JoinHandle(pool, task_id)
     }
 
-    /// get the task name.
-    ///
-    /// # Errors
-    /// if the task name is invalid.
-    pub fn get_name(&self) -> std::io::Result<&str> {
-        unsafe { CStr::from_ptr(self.1) }
-            .to_str()
-            .map_err(|_| Error::new(ErrorKind::InvalidInput, "Invalid task name"))
+    /// get the task id.
+    #[must_use]
+    pub fn id(&self) -> TaskId {
+        self.1
+    }
+
+    /// cancel the task, see [`crate::co_pool::CoroutinePool::cancel_task`].
+    #[must_use]
+    pub fn cancel(&self) -> bool {
+        TaskId::INVALID != self.1 && self.0.cancel_task(self.1)
+    }
+
+    /// Nobody will join the task, so its result is dropped instead of being kept,
+    /// see [`crate::co_pool::CoroutinePool::detach_task`].
+    pub fn detach(self) {
+        if TaskId::INVALID != self.1 {
+            self.0.detach_task(self.1);
+        }
+    }
+
+    /// Run `f` as a new task once this task completes, `f` receives the result, and the
+    /// returned handle joins the result of `f`. The returned handle is invalid if this
+    /// task has been joined.
+    #[must_use]
+    pub fn then(
+        self,
+        f: impl FnOnce(Result<Option<usize>, &'static str>) -> Result<Option<usize>, &'static str>
+            + 'static,
+    ) -> Self {
+        if TaskId::INVALID == self.1 {
+            return self;
+        }
+        self.0.submit_continuation(self.1, f, false).map_or_else(
+            |_| JoinHandle::err(self.0),
+            |id| JoinHandle::new(self.0, id),
+        )
+    }
+
+    /// Like [`JoinHandle::then`], but `f` is only called if this task succeeds,
+    /// otherwise the failure is passed on.
+    #[must_use]
+    pub fn map(self, f: impl FnOnce(Option<usize>) -> Option<usize> + 'static) -> Self {
+        self.then(|result| result.map(f))
+    }
+
+    /// Like [`JoinHandle::map`], but `f` can fail.
+    #[must_use]
+    pub fn and_then(
+        self,
+        f: impl FnOnce(Option<usize>) -> Result<Option<usize>, &'static str> + 'static,
+    ) -> Self {
+        self.then(|result| result.and_then(f))
+    }
+
+    /// Run `callback` as a new task once this task completes,
+    /// and the result of this task is consumed by `callback`.
+    pub fn on_complete(self, callback: impl FnOnce(Result<Option<usize>, &'static str>) + 'static) {
+        if TaskId::INVALID == self.1 {
+            return;
+        }
+        _ = self.0.submit_continuation(
+            self.1,
+            move |result| {
+                callback(result);
+                Ok(None)
+            },
+            true,
+        );
     }
 
     /// join with `Duration`.
@@ -53,18 +126,150 @@ impl JoinHandle {
     /// join with timeout.
     ///
     /// # Errors
-    /// if join failed.
+    /// if timeout, the result has been joined, evicted or detached, or the task was
+    /// cancelled.
     pub fn timeout_at_join(
         &self,
         timeout_time: u64,
     ) -> std::io::Result<Result<Option<usize>, &str>> {
-        let name = self.get_name()?;
-        if name.is_empty() {
-            return Err(Error::new(ErrorKind::InvalidInput, "Invalid task name"));
+        if TaskId::INVALID == self.1 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Invalid task id"));
+        }
+        self.0
+            .wait_task_result(
+                self.1,
+                Duration::from_nanos(timeout_time.saturating_sub(crate::common::now())),
+            )
+            .and_then(|result| {
+                if is_cancelled(&result) {
+                    return Err(cancelled_error());
+                }
+                Ok(result)
+            })
+    }
+
+    /// Like [`JoinHandle::timeout_at_join`], but also returns the runs of the task
+    /// submitted by [`crate::net::EventLoops::submit_task_with_retry`].
+    ///
+    /// # Errors
+    /// see `timeout_at_join`.
+    #[allow(clippy::type_complexity)]
+    pub fn timeout_at_join_with_attempts(
+        &self,
+        timeout_time: u64,
+    ) -> std::io::Result<(Result<Option<usize>, &'static str>, Vec<Attempt<'static>>)> {
+        if TaskId::INVALID == self.1 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Invalid task id"));
+        }
+        self.0
+            .wait_task_attempts(
+                self.1,
+                Duration::from_nanos(timeout_time.saturating_sub(crate::common::now())),
+            )
+            .and_then(|(result, attempts)| {
+                if is_cancelled(&result) {
+                    return Err(cancelled_error());
+                }
+                Ok((result, attempts))
+            })
+    }
+
+    /// join with the runs of the task.
+    ///
+    /// # Errors
+    /// see `timeout_at_join`.
+    #[allow(clippy::type_complexity)]
+    pub fn join_with_attempts(
+        &self,
+    ) -> std::io::Result<(Result<Option<usize>, &'static str>, Vec<Attempt<'static>>)> {
+        self.timeout_at_join_with_attempts(u64::MAX)
+    }
+}
+
+fn tasks<'h>(handles: &[&'h JoinHandle]) -> Vec<(&'h CoroutinePool<'static>, TaskId)> {
+    handles.iter().map(|h| (&***h.0, h.1)).collect()
+}
+
+//等待`handles`中的任务直到`done`返回`true`
+fn wait(
+    handles: &[&JoinHandle],
+    timeout_time: u64,
+    done: impl Fn(&[Option<TaskResult<'static>>]) -> bool,
+) -> std::io::Result<(Vec<Option<TaskResult<'static>>>, usize)> {
+    if handles.is_empty() || handles.iter().any(|h| TaskId::INVALID == h.1) {
+        return Err(Error::new(ErrorKind::InvalidInput, "Invalid task id"));
+    }
+    wait_results(&tasks(handles), timeout_time, done)
+}
+
+/// Wait for all the tasks to complete before the `timeout_time` timestamp, the results
+/// are in the same order as `handles`.
+///
+/// Inside a coroutine it suspends until any of the tasks completes, on a thread it
+/// blocks on a single condvar.
+///
+/// # Errors
+/// if timeout, or the result of any task will never be available, the results collected
+/// so far are put back, so they can still be joined.
+pub fn join_all(
+    handles: &[&JoinHandle],
+    timeout_time: u64,
+) -> std::io::Result<Vec<Result<Option<usize>, &'static str>>> {
+    if handles.is_empty() {
+        return Ok(Vec::new());
+    }
+    wait(handles, timeout_time, |results| {
+        results.iter().all(Option::is_some)
+    })
+    .map(|(results, _)| results.into_iter().flatten().map(|(r, _)| r).collect())
+}
+
+/// Wait for the first task to complete before the `timeout_time` timestamp, returns its
+/// index in `handles` and its result, the other tasks are left untouched.
+///
+/// # Errors
+/// if timeout, or the result of any task will never be available.
+pub fn join_any(
+    handles: &[&JoinHandle],
+    timeout_time: u64,
+) -> std::io::Result<(usize, Result<Option<usize>, &'static str>)> {
+    let (mut results, index) = wait(handles, timeout_time, |_| true)?;
+    Ok((
+        index,
+        results[index]
+            .take()
+            .map(|(r, _)| r)
+            .expect("the result should be collected"),
+    ))
+}
+
+/// Wait for the first task to succeed before the `timeout_time` timestamp, and cancel
+/// the rest, returns its index in `handles` and its result. If all of them fail, the
+/// last failure is returned. The failures collected before are put back, so the
+/// failed tasks can still be joined.
+///
+/// # Errors
+/// if timeout, or the result of any task will never be available, the failures collected
+/// so far are put back.
+pub fn race(
+    handles: &[&JoinHandle],
+    timeout_time: u64,
+) -> std::io::Result<(usize, Result<Option<usize>, &'static str>)> {
+    let (mut results, index) = wait(handles, timeout_time, |results| {
+        results
+            .iter()
+            .any(|result| matches!(result, Some((Ok(_), _))))
+            || results.iter().all(Option::is_some)
+    })?;
+    for (handle, result) in handles.iter().zip(&results) {
+        if result.is_none() {
+            _ = handle.cancel();
         }
-        self.0.wait_task_result(
-            name,
-            Duration::from_nanos(timeout_time.saturating_sub(crate::common::now())),
-        )
     }
+    let result = results[index]
+        .take()
+        .map(|(r, _)| r)
+        .expect("the result should be collected");
+    put_back(&tasks(handles), &mut results);
+    Ok((index, result))
 }
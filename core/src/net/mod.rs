@@ -1,11 +1,20 @@
+use crate::co_pool::autoscaler::ScaleListener;
+use crate::co_pool::group::GroupMetrics;
+use crate::co_pool::listener::TaskListener;
+use crate::co_pool::retry::RetryPolicy;
+use crate::co_pool::task::TaskPriority;
+use crate::common::constants::{PlacementPolicy, PoolState};
+use crate::common::{get_timeout_time, hash};
 use crate::coroutine::suspender::Suspender;
 use crate::net::config::Config;
 use crate::net::event_loop::EventLoop;
 use crate::net::join::JoinHandle;
 use crate::{error, info};
 use once_cell::sync::OnceCell;
+use rand::Rng;
 use std::collections::VecDeque;
 use std::ffi::c_int;
+use std::hash::Hash;
 use std::io::{Error, ErrorKind};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
@@ -37,6 +46,12 @@ pub mod config;
 /// Task join abstraction and impl.
 pub mod join;
 
+/// Task dependency graph.
+pub mod graph;
+
+/// Supervisors which restart the failed tasks.
+pub mod supervisor;
+
 static INSTANCE: OnceCell<EventLoops> = OnceCell::new();
 
 /// The manager for `EventLoop`.
@@ -44,6 +59,8 @@ static INSTANCE: OnceCell<EventLoops> = OnceCell::new();
 #[derive(Debug)]
 pub struct EventLoops {
     index: AtomicUsize,
+    //任务提交到哪个事件循环
+    placement: PlacementPolicy,
     loops: VecDeque<Arc<EventLoop<'static>>>,
     shared_stop: Arc<(Mutex<AtomicUsize>, Condvar)>,
 }
@@ -56,7 +73,7 @@ impl EventLoops {
     /// Init the `EventLoops`.
     pub fn init(config: &Config) {
         _ = INSTANCE.get_or_init(|| {
-            let loops = Self::new(
+            let mut loops = Self::new(
                 config.event_loop_size(),
                 config.stack_size(),
                 config.min_size(),
@@ -64,6 +81,15 @@ impl EventLoops {
                 config.keep_alive_time(),
             )
             .expect("init default EventLoops failed !");
+            loops.placement = config.placement();
+            for event_loop in &loops.loops {
+                event_loop.set_queue_capacity(config.queue_capacity());
+                event_loop.set_reject_policy(config.reject_policy());
+                event_loop.set_block_timeout(config.block_timeout());
+                event_loop.set_autoscaler(config.autoscaler());
+                event_loop.set_result_ttl(config.result_ttl());
+                event_loop.set_result_capacity(config.result_capacity());
+            }
             #[cfg(feature = "log")]
             let _ = tracing_subscriber::fmt()
                 .with_thread_names(true)
@@ -74,10 +100,54 @@ impl EventLoops {
                 ))
                 .try_init();
             info!("open-coroutine init with {config:#?}");
+            if config.blocked_time() > 0 || config.steal_threshold() > 0 {
+                Self::start_sysmon(config.blocked_time(), config.steal_threshold())
+                    .expect("start sysmon failed !");
+            }
             loops
         });
     }
 
+    /// Start a thread to check whether any event-loop has been blocked for more than
+    /// `blocked_time` ns, another thread will take over its scheduling. And whether
+    /// any event-loop has more than `steal_threshold` queued tasks, the idle ones will
+    /// steal its tasks. `0` means don't check.
+    fn start_sysmon(blocked_time: u64, steal_threshold: usize) -> std::io::Result<()> {
+        let mut interval = Duration::MAX;
+        if blocked_time > 0 {
+            interval = Duration::from_nanos(blocked_time / 2)
+                .clamp(Duration::from_millis(1), Duration::from_millis(100));
+        }
+        if steal_threshold > 0 {
+            interval = interval.min(Duration::from_millis(10));
+        }
+        _ = std::thread::Builder::new()
+            .name("open-coroutine-sysmon".to_string())
+            .spawn(move || {
+                #[cfg(unix)]
+                crate::coroutine::SignalStack::init_current();
+                let instance = INSTANCE.wait();
+                while instance
+                    .loops
+                    .iter()
+                    .any(|event_loop| PoolState::Running == event_loop.state())
+                {
+                    if blocked_time > 0 {
+                        for event_loop in &instance.loops {
+                            if let Err(e) = event_loop.try_handoff(blocked_time) {
+                                error!("{} handoff failed: {e}", event_loop.name());
+                            }
+                        }
+                    }
+                    if steal_threshold > 0 {
+                        instance.balance(steal_threshold);
+                    }
+                    std::thread::sleep(interval);
+                }
+            })?;
+        Ok(())
+    }
+
     /// Create a new `EventLoops`.
     pub fn new(
         event_loop_size: usize,
@@ -104,11 +174,57 @@ impl EventLoops {
         }
         Ok(Self {
             index: AtomicUsize::new(0),
+            placement: PlacementPolicy::default(),
             loops,
             shared_stop,
         })
     }
 
+    /// Let the idle event-loops steal the queued tasks of the event-loop which has the
+    /// most queued tasks, if it has more than `steal_threshold`.
+    fn balance(&self, steal_threshold: usize) {
+        let Some(victim) = self
+            .loops
+            .iter()
+            .filter(|event_loop| event_loop.size() > steal_threshold)
+            //排队数相同时选负载最重的，共用本地队列的事件循环排队数总是相同
+            .max_by_key(|event_loop| (event_loop.size(), event_loop.busyness()))
+        else {
+            return;
+        };
+        for event_loop in &self.loops {
+            //只让没有协程要执行的事件循环窃取，事件循环多于本地队列时会共用同一个队列，
+            //它排队的任务可能就是victim的，也要唤醒它来执行
+            if !Arc::ptr_eq(event_loop, victim)
+                && 0 == event_loop.busyness().saturating_sub(event_loop.size())
+            {
+                event_loop.request_steal(victim);
+            }
+        }
+    }
+
+    /// Get the `EventLoop` to submit a task to according to the [`PlacementPolicy`].
+    fn place() -> &'static Arc<EventLoop<'static>> {
+        let instance = INSTANCE.get().expect("EventLoops not init !");
+        let current = EventLoop::current().and_then(|current| {
+            instance
+                .loops
+                .iter()
+                .position(|event_loop| std::ptr::eq(event_loop.as_ref(), current))
+        });
+        let index = select(
+            instance.placement,
+            instance.index.fetch_add(1, Ordering::Release),
+            current,
+            instance.loops.len(),
+            |index| instance.loops[index].busyness(),
+        );
+        instance
+            .loops
+            .get(index)
+            .unwrap_or_else(move || panic!("init event-loop-{index} failed!"))
+    }
+
     fn round_robin() -> &'static Arc<EventLoop<'static>> {
         let instance = INSTANCE.get().expect("EventLoops not init !");
         let index = instance.index.fetch_add(1, Ordering::Release) % instance.loops.len();
@@ -118,25 +234,267 @@ impl EventLoops {
             .unwrap_or_else(move || panic!("init event-loop-{index} failed!"))
     }
 
+    /// Get the `EventLoop` which the `key` is hashed to.
+    fn hashed(key: &(impl Hash + ?Sized)) -> &'static Arc<EventLoop<'static>> {
+        let instance = INSTANCE.get().expect("EventLoops not init !");
+        let index = usize::try_from(hash(key) % instance.loops.len() as u64).expect("overflow");
+        instance
+            .loops
+            .get(index)
+            .unwrap_or_else(move || panic!("init event-loop-{index} failed!"))
+    }
+
     /// Get a `EventLoop`, prefer current.
     fn event_loop() -> &'static EventLoop<'static> {
         EventLoop::current().unwrap_or_else(|| Self::round_robin())
     }
 
-    /// Submit a new task to event-loop.
+    /// Returns a `JoinHandle` which is not bound to any task, it's returned when the task
+    /// can't be submitted, and joining it returns an error.
+    #[must_use]
+    pub fn invalid_handle() -> JoinHandle {
+        JoinHandle::err(Self::round_robin())
+    }
+
+    /// Submit a new task to event-loop, the task with higher `priority` is executed first.
     ///
     /// Allow multiple threads to concurrently submit task to the pool,
     /// but only allow one thread to execute scheduling.
+    ///
+    /// The result is kept until it's joined even if the returned handle is dropped,
+    /// detach the handle if the result is not needed, see [`JoinHandle`].
     pub fn submit_task(
         name: Option<String>,
         func: impl FnOnce(Option<usize>) -> Option<usize> + 'static,
         param: Option<usize>,
+        priority: Option<TaskPriority>,
     ) -> JoinHandle {
-        let event_loop = Self::round_robin();
-        event_loop.submit_task(name, func, param).map_or_else(
-            |_| JoinHandle::err(event_loop),
-            |n| JoinHandle::new(event_loop, n.as_str()),
-        )
+        let event_loop = Self::place();
+        event_loop
+            .submit_task(name, func, param, priority)
+            .map_or_else(
+                |_| JoinHandle::err(event_loop),
+                |task_id| JoinHandle::new(event_loop, task_id),
+            )
+    }
+
+    /// Submit a new task to event-loop which runs again according to the `policy` if it
+    /// fails, see [`crate::co_pool::CoroutinePool::submit_task_with_retry`]. Join it by
+    /// [`JoinHandle::join_with_attempts`] to get all the runs.
+    pub fn submit_task_with_retry(
+        name: Option<String>,
+        func: impl Fn(Option<usize>) -> Result<Option<usize>, &'static str> + 'static,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+        policy: RetryPolicy,
+    ) -> JoinHandle {
+        let event_loop = Self::place();
+        event_loop
+            .submit_task_with_retry(name, func, param, priority, policy)
+            .map_or_else(
+                |_| JoinHandle::err(event_loop),
+                |task_id| JoinHandle::new(event_loop, task_id),
+            )
+    }
+
+    /// Submit a new task to event-loop like [`EventLoops::submit_task`], but the tasks
+    /// with the same `key` are hashed to the same event-loop and run strictly one after
+    /// another, see [`crate::co_pool::CoroutinePool::submit_keyed_task`].
+    pub fn submit_task_keyed(
+        key: &(impl Hash + ?Sized),
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + 'static,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+    ) -> JoinHandle {
+        let event_loop = Self::hashed(key);
+        event_loop
+            .submit_keyed_task(key, name, func, param, priority)
+            .map_or_else(
+                |_| JoinHandle::err(event_loop),
+                |task_id| JoinHandle::new(event_loop, task_id),
+            )
+    }
+
+    /// Set the limits of the task `group`, the group lives in the event-loop which its
+    /// name is hashed to, see [`crate::co_pool::CoroutinePool::set_group_limit`].
+    pub fn set_group_limit(group: &str, limit: usize, capacity: usize) {
+        Self::hashed(group).set_group_limit(group, limit, capacity);
+    }
+
+    /// Get the metrics of the task `group`, `None` means the group doesn't exist.
+    #[must_use]
+    pub fn get_group_metrics(group: &str) -> Option<GroupMetrics> {
+        Self::hashed(group).get_group_metrics(group)
+    }
+
+    /// Submit a new task to event-loop like [`EventLoops::submit_task`], but at most the
+    /// limit of the `group` tasks in it are in flight, the excess ones wait in the group,
+    /// see [`crate::co_pool::CoroutinePool::submit_group_task`].
+    pub fn submit_task_in_group(
+        group: &str,
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + 'static,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+    ) -> JoinHandle {
+        let event_loop = Self::hashed(group);
+        event_loop
+            .submit_group_task(group, name, func, param, priority)
+            .map_or_else(
+                |_| JoinHandle::err(event_loop),
+                |task_id| JoinHandle::new(event_loop, task_id),
+            )
+    }
+
+    /// Submit a new task to event-loop like [`EventLoops::submit_task`], but nobody can
+    /// join it, so its result is never saved.
+    ///
+    /// # Errors
+    /// if the task was rejected.
+    pub fn spawn_detached(
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + 'static,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+    ) -> std::io::Result<()> {
+        Self::spawn_cancellable(name, func, param, priority).map(|_| ())
+    }
+
+    /// Like [`EventLoops::spawn_detached`], but returns the handle which can only be
+    /// used to cancel the task.
+    pub(crate) fn spawn_cancellable(
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + 'static,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+    ) -> std::io::Result<JoinHandle> {
+        let event_loop = Self::place();
+        event_loop
+            .submit_detached_task(name, func, param, priority)
+            .map(|task_id| JoinHandle::new(event_loop, task_id))
+    }
+
+    /// Submit a new task to event-loop which is executed after `delay`.
+    pub fn submit_task_after(
+        delay: Duration,
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + 'static,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+    ) -> JoinHandle {
+        Self::submit_task_at(get_timeout_time(delay), name, func, param, priority)
+    }
+
+    /// Submit a new task to event-loop which is executed at the `timestamp` in ns.
+    pub fn submit_task_at(
+        timestamp: u64,
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + 'static,
+        param: Option<usize>,
+        priority: Option<TaskPriority>,
+    ) -> JoinHandle {
+        let event_loop = Self::place();
+        event_loop
+            .submit_task_at(timestamp, name, func, param, priority)
+            .map_or_else(
+                |_| JoinHandle::err(event_loop),
+                |task_id| JoinHandle::new(event_loop, task_id),
+            )
+    }
+
+    /// Submit a periodic task to event-loop which first runs after `initial_delay`,
+    /// and then once every `period`, the runs missed by an overrun are skipped.
+    ///
+    /// Cancel the returned handle to stop it, see
+    /// [`crate::co_pool::CoroutinePool::schedule_at_fixed_rate`].
+    pub fn schedule_at_fixed_rate(
+        initial_delay: Duration,
+        name: Option<String>,
+        func: impl Fn(Option<usize>) -> Option<usize> + 'static,
+        param: Option<usize>,
+        period: Duration,
+        priority: Option<TaskPriority>,
+    ) -> JoinHandle {
+        let event_loop = Self::place();
+        event_loop
+            .schedule_at_fixed_rate(
+                get_timeout_time(initial_delay),
+                name,
+                func,
+                param,
+                period,
+                priority,
+            )
+            .map_or_else(
+                |_| JoinHandle::err(event_loop),
+                |task_id| JoinHandle::new(event_loop, task_id),
+            )
+    }
+
+    /// Submit a periodic task to event-loop which first runs after `initial_delay`,
+    /// and then `delay` after the previous run finishes.
+    ///
+    /// Cancel the returned handle to stop it, see
+    /// [`crate::co_pool::CoroutinePool::schedule_with_fixed_delay`].
+    pub fn schedule_with_fixed_delay(
+        initial_delay: Duration,
+        name: Option<String>,
+        func: impl Fn(Option<usize>) -> Option<usize> + 'static,
+        param: Option<usize>,
+        delay: Duration,
+        priority: Option<TaskPriority>,
+    ) -> JoinHandle {
+        let event_loop = Self::place();
+        event_loop
+            .schedule_with_fixed_delay(
+                get_timeout_time(initial_delay),
+                name,
+                func,
+                param,
+                delay,
+                priority,
+            )
+            .map_or_else(
+                |_| JoinHandle::err(event_loop),
+                |task_id| JoinHandle::new(event_loop, task_id),
+            )
+    }
+
+    /// Cancel the unfinished task with the given `name`.
+    ///
+    /// Returns `false` if no such task, see [`crate::co_pool::CoroutinePool::cancel_task`].
+    #[must_use]
+    pub fn cancel_task(name: &str) -> bool {
+        INSTANCE.get().is_some_and(|instance| {
+            instance
+                .loops
+                .iter()
+                .any(|event_loop| event_loop.cancel_task_by_name(name))
+        })
+    }
+
+    /// Add a listener to observe the lifecycle of the tasks in all event-loops.
+    ///
+    /// The listener can't be held by [`Config`], so add it after `init`.
+    pub fn add_task_listener(listener: impl TaskListener + 'static) {
+        let listener: &'static dyn TaskListener = Box::leak(Box::new(listener));
+        let instance = INSTANCE.get().expect("EventLoops not init !");
+        for event_loop in &instance.loops {
+            event_loop.add_raw_task_listener(listener);
+        }
+    }
+
+    /// Add a listener to observe the decisions of the autoscalers of all event-loops,
+    /// see [`Config::set_autoscaler`].
+    ///
+    /// The listener can't be held by [`Config`], so add it after `init`.
+    pub fn add_scale_listener(listener: impl ScaleListener + 'static) {
+        let listener: &'static dyn ScaleListener = Box::leak(Box::new(listener));
+        let instance = INSTANCE.get().expect("EventLoops not init !");
+        for event_loop in &instance.loops {
+            event_loop.add_raw_scale_listener(listener);
+        }
     }
 
     /// Submit a new coroutine to event-loop.
@@ -147,7 +505,11 @@ impl EventLoops {
         f: impl FnOnce(&Suspender<(), ()>, ()) -> Option<usize> + 'static,
         stack_size: Option<usize>,
     ) -> std::io::Result<()> {
-        Self::round_robin().submit_co(f, stack_size)
+        let event_loop = Self::place();
+        //没有办法获取结果，不需要保存
+        event_loop
+            .submit_co(f, stack_size)
+            .map(|co_id| event_loop.detach(co_id))
     }
 
     /// Waiting for read or write events to occur.
@@ -240,6 +602,42 @@ impl EventLoops {
     }
 }
 
+/// Returns the index of the event-loop to submit a task to, `turn` increases on every
+/// submission, `current` is the index of the current event-loop, and `load` returns
+/// how much work an event-loop has, see [`crate::co_pool::CoroutinePool::busyness`].
+fn select(
+    placement: PlacementPolicy,
+    turn: usize,
+    current: Option<usize>,
+    len: usize,
+    load: impl Fn(usize) -> usize,
+) -> usize {
+    let start = turn % len;
+    match placement {
+        PlacementPolicy::RoundRobin => start,
+        //从轮转的位置开始找，负载相同时不会总是选中同一个
+        PlacementPolicy::LeastQueueDepth => (0..len)
+            .map(|i| (start + i) % len)
+            .min_by_key(|index| load(*index))
+            .unwrap_or(start),
+        PlacementPolicy::PowerOfTwoChoices => {
+            if 1 == len {
+                return 0;
+            }
+            //随机选择两个不同的事件循环
+            let mut rng = rand::thread_rng();
+            let a = rng.gen_range(0..len);
+            let b = (a + rng.gen_range(1..len)) % len;
+            if load(b) < load(a) {
+                b
+            } else {
+                a
+            }
+        }
+        PlacementPolicy::CurrentFirst => current.unwrap_or(start),
+    }
+}
+
 macro_rules! impl_io_uring {
     ( $syscall: ident($($arg: ident : $arg_type: ty),*) -> $result: ty ) => {
         #[cfg(all(target_os = "linux", feature = "io_uring"))]
@@ -274,3 +672,113 @@ impl_io_uring!(pwrite(fd: c_int, buf: *const c_void, count: size_t, offset: off_
 impl_io_uring!(writev(fd: c_int, iov: *const iovec, iovcnt: c_int) -> ssize_t);
 impl_io_uring!(pwritev(fd: c_int, iov: *const iovec, iovcnt: c_int, offset: off_t) -> ssize_t);
 impl_io_uring!(sendmsg(fd: c_int, msg: *const msghdr, flags: c_int) -> ssize_t);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let loads = [3, 1, 1, 5];
+        let load = |index: usize| loads[index];
+        assert_eq!(1, select(PlacementPolicy::RoundRobin, 5, Some(3), 4, load));
+        // the tie is broken by the turn
+        assert_eq!(
+            1,
+            select(PlacementPolicy::LeastQueueDepth, 0, None, 4, load)
+        );
+        assert_eq!(
+            2,
+            select(PlacementPolicy::LeastQueueDepth, 2, None, 4, load)
+        );
+        assert_eq!(
+            3,
+            select(PlacementPolicy::CurrentFirst, 0, Some(3), 4, load)
+        );
+        assert_eq!(2, select(PlacementPolicy::CurrentFirst, 6, None, 4, load));
+        // never choose the most loaded one
+        for turn in 0..100 {
+            assert_ne!(
+                3,
+                select(PlacementPolicy::PowerOfTwoChoices, turn, None, 4, load)
+            );
+        }
+    }
+
+    #[test]
+    fn test_join_handle_drop() -> std::io::Result<()> {
+        use std::sync::Condvar;
+        let event_loop: &'static Arc<EventLoop<'static>> = Box::leak(Box::new(
+            EventLoop::new(
+                String::from("test-join-handle-drop-event-loop"),
+                0,
+                crate::common::constants::DEFAULT_STACK_SIZE,
+                0,
+                1,
+                0,
+                Arc::new((Mutex::new(AtomicUsize::new(0)), Condvar::new())),
+            )?
+            .start()?,
+        ));
+        let dropped = event_loop.submit_task(None, |_| Some(1), None, None)?;
+        let detached = event_loop.submit_task(None, |_| Some(2), None, None)?;
+        //丢弃句柄不会分离任务，结果一直保留到被join
+        _ = JoinHandle::new(event_loop, dropped);
+        JoinHandle::new(event_loop, detached).detach();
+        assert_eq!(
+            Ok(Some(1)),
+            JoinHandle::new(event_loop, dropped).timeout_join(Duration::from_secs(3))?
+        );
+        assert!(JoinHandle::new(event_loop, detached)
+            .timeout_join(Duration::from_secs(3))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_balance() -> std::io::Result<()> {
+        use crate::common::constants::DEFAULT_STACK_SIZE;
+        use crate::common::now;
+        use std::sync::atomic::AtomicBool;
+        static STARTED: AtomicBool = AtomicBool::new(false);
+        static BLOCKED: AtomicBool = AtomicBool::new(true);
+        let loops = EventLoops::new(2, DEFAULT_STACK_SIZE, 0, 1, 0)?;
+        let busy = &loops.loops[0];
+        //阻塞唯一的worker，排队的任务只能被其他事件循环窃取
+        _ = busy.submit_task(
+            None,
+            |_| {
+                STARTED.store(true, Ordering::Release);
+                while BLOCKED.load(Ordering::Acquire) {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                None
+            },
+            None,
+            None,
+        )?;
+        //等阻塞的任务开始执行，否则它可能被其他事件循环窃取
+        while !STARTED.load(Ordering::Acquire) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let tasks = (0..4)
+            .map(|i| busy.submit_task(None, move |_| Some(i), None, None))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let timeout_time = get_timeout_time(Duration::from_secs(3));
+        let mut results = vec![None; tasks.len()];
+        while results.iter().any(Option::is_none) && now() < timeout_time {
+            loops.balance(0);
+            for (result, task_id) in results.iter_mut().zip(&tasks) {
+                if result.is_none() {
+                    *result = busy.try_get_task_result(*task_id);
+                }
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        BLOCKED.store(false, Ordering::Release);
+        // the results are saved in the event-loop which the tasks were submitted to
+        let expected: Vec<_> = (0..4).map(|i| Some(Ok(Some(i)))).collect();
+        assert_eq!(expected, results);
+        Ok(())
+    }
+}
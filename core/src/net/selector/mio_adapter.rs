@@ -3,7 +3,7 @@ use crossbeam_utils::atomic::AtomicCell;
 use derivative::Derivative;
 use mio::event::Event;
 use mio::unix::SourceFd;
-use mio::{Events, Interest, Poll, Token};
+use mio::{Events, Interest, Poll, Token, Waker};
 use std::ffi::c_int;
 use std::ops::Deref;
 use std::sync::atomic::AtomicBool;
@@ -54,14 +54,19 @@ pub(crate) struct Poller {
     blocker: CondvarBlocker,
     #[derivative(Debug = "ignore")]
     inner: AtomicCell<Poll>,
+    #[derivative(Debug = "ignore")]
+    waker: Waker,
 }
 
 impl Poller {
     pub(crate) fn new() -> std::io::Result<Self> {
+        let poll = Poll::new()?;
+        let waker = Waker::new(poll.registry(), Token(super::WAKER_TOKEN))?;
         Ok(Self {
             waiting: AtomicBool::new(false),
             blocker: CondvarBlocker::default(),
-            inner: AtomicCell::new(Poll::new()?),
+            inner: AtomicCell::new(poll),
+            waker,
         })
     }
 }
@@ -88,6 +93,10 @@ impl super::Selector<Interest, Event, Events> for Poller {
         inner.poll(events, timeout)
     }
 
+    fn do_wakeup(&self) -> std::io::Result<()> {
+        self.waker.wake()
+    }
+
     fn do_register(&self, fd: c_int, token: usize, interests: Interest) -> std::io::Result<()> {
         self.registry()
             .register(&mut SourceFd(&fd), Token(token), interests)
@@ -1,5 +1,6 @@
 use crate::common::constants::SLICE;
 use crate::common::CondvarBlocker;
+use crate::preempt::PreemptionGuard;
 use dashmap::{DashMap, DashSet};
 use once_cell::sync::Lazy;
 use std::ffi::c_int;
@@ -30,6 +31,9 @@ pub(crate) trait Event {
     fn writable(&self) -> bool;
 }
 
+/// The token reserved for waking up the selector.
+pub(crate) const WAKER_TOKEN: usize = usize::MAX;
+
 static TOKEN_FD: Lazy<DashMap<usize, c_int>> = Lazy::new(DashMap::new);
 
 static READABLE_RECORDS: Lazy<DashSet<c_int>> = Lazy::new(DashSet::new);
@@ -76,9 +80,19 @@ pub(crate) trait Selector<I: Interest, E: Event, S: EventIterator<E>> {
         result
     }
 
+    /// Wake up the thread which is blocked in `select`.
+    ///
+    /// # Errors
+    /// if wakeup failed.
+    fn wakeup(&self) -> std::io::Result<()> {
+        self.blocker().notify();
+        self.do_wakeup()
+    }
+
     /// # Errors
     /// if add failed.
     fn add_read_event(&self, fd: c_int, token: usize) -> std::io::Result<()> {
+        let _guard = PreemptionGuard::new();
         if READABLE_RECORDS.contains(&fd) {
             return Ok(());
         }
@@ -98,6 +112,7 @@ pub(crate) trait Selector<I: Interest, E: Event, S: EventIterator<E>> {
     /// # Errors
     /// if add failed.
     fn add_write_event(&self, fd: c_int, token: usize) -> std::io::Result<()> {
+        let _guard = PreemptionGuard::new();
         if WRITABLE_RECORDS.contains(&fd) {
             return Ok(());
         }
@@ -117,6 +132,7 @@ pub(crate) trait Selector<I: Interest, E: Event, S: EventIterator<E>> {
     /// # Errors
     /// if delete failed.
     fn del_event(&self, fd: c_int) -> std::io::Result<()> {
+        let _guard = PreemptionGuard::new();
         if READABLE_RECORDS.contains(&fd) || WRITABLE_RECORDS.contains(&fd) {
             let token = READABLE_TOKEN_RECORDS
                 .remove(&fd)
@@ -135,6 +151,7 @@ pub(crate) trait Selector<I: Interest, E: Event, S: EventIterator<E>> {
     /// # Panics
     /// if clean failed.
     fn del_read_event(&self, fd: c_int) -> std::io::Result<()> {
+        let _guard = PreemptionGuard::new();
         if READABLE_RECORDS.contains(&fd) {
             if WRITABLE_RECORDS.contains(&fd) {
                 //写事件不能删
@@ -158,6 +175,7 @@ pub(crate) trait Selector<I: Interest, E: Event, S: EventIterator<E>> {
     /// # Panics
     /// if clean failed.
     fn del_write_event(&self, fd: c_int) -> std::io::Result<()> {
+        let _guard = PreemptionGuard::new();
         if WRITABLE_RECORDS.contains(&fd) {
             if READABLE_RECORDS.contains(&fd) {
                 //读事件不能删
@@ -205,6 +223,9 @@ pub(crate) trait Selector<I: Interest, E: Event, S: EventIterator<E>> {
     /// For inner impls.
     fn do_select(&self, events: &mut S, timeout: Option<Duration>) -> std::io::Result<()>;
 
+    /// For inner impls.
+    fn do_wakeup(&self) -> std::io::Result<()>;
+
     /// For inner impls.
     fn do_register(&self, fd: c_int, token: usize, interests: I) -> std::io::Result<()>;
 
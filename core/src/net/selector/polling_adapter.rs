@@ -89,6 +89,10 @@ impl super::Selector<Event, Event, Events> for Poller {
         self.wait(events, timeout).map(|_| ())
     }
 
+    fn do_wakeup(&self) -> std::io::Result<()> {
+        self.notify()
+    }
+
     fn do_register(&self, fd: c_int, _: usize, interests: Event) -> std::io::Result<()> {
         cfg_if::cfg_if! {
             if #[cfg(windows)] {
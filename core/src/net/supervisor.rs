@@ -0,0 +1,345 @@
+use crate::common::now;
+use crate::error;
+use crate::net::join::JoinHandle;
+use crate::net::EventLoops;
+use derivative::Derivative;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How the children are restarted when one of them fails.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum RestartStrategy {
+    /// Only restart the failed child.
+    #[default]
+    OneForOne,
+    /// Stop all the other children, and then restart all of them.
+    OneForAll,
+}
+
+/// A trait to observe the [`Supervisor`], mainly used to escalate the failures which
+/// can't be recovered by restarting.
+#[allow(unused_variables)]
+pub trait SupervisorListener: Debug {
+    /// Callback before restarting because the `child` failed with the `error`.
+    fn on_restart(&self, supervisor: &str, child: &str, error: &str) {}
+
+    /// Callback after the supervisor gives up because the children failed more often
+    /// than its intensity allows, then all the children are stopped.
+    fn on_escalate(&self, supervisor: &str, child: &str, error: &str) {}
+}
+
+type ChildFunc = Arc<dyn Fn(Option<usize>) -> Option<usize>>;
+
+#[repr(C)]
+#[derive(Derivative)]
+#[derivative(Debug)]
+struct Child {
+    name: String,
+    #[derivative(Debug = "ignore")]
+    func: ChildFunc,
+    param: Option<usize>,
+    //是否有任务正在执行
+    alive: bool,
+    //用于停止正在执行的任务
+    handle: Option<JoinHandle>,
+}
+
+/// A supervisor modeled on Erlang/OTP, the children are long-running tasks which are
+/// restarted on any event-loop when they panic, the children which return are not
+/// restarted.
+///
+/// If the children fail more than `max_restarts` times in `period`, the supervisor
+/// stops all of them and escalates by [`SupervisorListener::on_escalate`].
+///
+/// Note that a running child is stopped by cancelling its task, so it only stops at
+/// its next hooked syscall.
+#[repr(C)]
+#[derive(Debug)]
+pub struct Supervisor {
+    name: String,
+    strategy: RestartStrategy,
+    max_restarts: usize,
+    //单位ns
+    period: u64,
+    children: Vec<Child>,
+    listeners: Vec<&'static dyn SupervisorListener>,
+}
+
+impl Supervisor {
+    /// Create a new `Supervisor`, which allows 3 restarts in 5 seconds by default.
+    #[must_use]
+    pub fn new(name: String, strategy: RestartStrategy) -> Self {
+        Supervisor {
+            name,
+            strategy,
+            max_restarts: 3,
+            period: 5_000_000_000,
+            children: Vec::new(),
+            listeners: Vec::new(),
+        }
+    }
+
+    /// Allow at most `max_restarts` restarts in any `period`.
+    pub fn set_intensity(&mut self, max_restarts: usize, period: Duration) -> &mut Self {
+        self.max_restarts = max_restarts;
+        self.period = u64::try_from(period.as_nanos()).unwrap_or(u64::MAX);
+        self
+    }
+
+    /// Add a child which is started with the `param` every time.
+    pub fn add_child(
+        &mut self,
+        name: String,
+        func: impl Fn(Option<usize>) -> Option<usize> + 'static,
+        param: Option<usize>,
+    ) -> &mut Self {
+        self.children.push(Child {
+            name,
+            func: Arc::new(func),
+            param,
+            alive: false,
+            handle: None,
+        });
+        self
+    }
+
+    /// Add a listener to observe the restarts and the escalation.
+    pub fn add_listener(&mut self, listener: impl SupervisorListener + 'static) -> &mut Self {
+        self.listeners.push(Box::leak(Box::new(listener)));
+        self
+    }
+
+    /// Start all the children on the event-loops.
+    #[must_use]
+    pub fn start(self) -> SupervisorHandle {
+        let count = self.children.len();
+        let shared = Arc::new(Shared(Mutex::new(State {
+            supervisor: self,
+            generation: 0,
+            restarts: VecDeque::new(),
+            restarted: 0,
+            stopped: false,
+            escalated: false,
+        })));
+        for index in 0..count {
+            shared.spawn(index);
+        }
+        SupervisorHandle(shared)
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    supervisor: Supervisor,
+    //OneForAll重启时加一，旧的任务退出时不再重启
+    generation: u64,
+    //在周期内重启的时间
+    restarts: VecDeque<u64>,
+    restarted: usize,
+    stopped: bool,
+    escalated: bool,
+}
+
+impl State {
+    //取消排队中的任务会立即丢弃其闭包并调用`on_exit`，所以要在释放锁后再取消
+    fn stop_children(&mut self) -> Vec<JoinHandle> {
+        self.supervisor
+            .children
+            .iter_mut()
+            .filter_map(|child| child.handle.take())
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+struct Shared(Mutex<State>);
+
+//和提交到`EventLoops`的任务一样，子任务可能在任意事件循环上执行
+unsafe impl Send for Shared {}
+
+unsafe impl Sync for Shared {}
+
+impl Shared {
+    fn spawn(self: &Arc<Self>, index: usize) {
+        let (func, param, generation) = {
+            let mut state = self.0.lock().expect("lock failed");
+            if state.stopped {
+                return;
+            }
+            let generation = state.generation;
+            let child = &mut state.supervisor.children[index];
+            child.alive = true;
+            (child.func.clone(), child.param, generation)
+        };
+        //提交时不能持有锁，拒绝策略可能让当前线程直接执行任务
+        let mut exit = Exit {
+            shared: self.clone(),
+            index,
+            generation,
+            error: Some(String::from("The child was dropped before it ran !")),
+        };
+        match EventLoops::spawn_cancellable(
+            None,
+            move |param| {
+                let result = std::panic::catch_unwind(AssertUnwindSafe(|| func(param)));
+                exit.error = result.as_ref().err().map(|e| message(&**e));
+                drop(exit);
+                result.unwrap_or_else(|e| std::panic::resume_unwind(e))
+            },
+            param,
+            None,
+        ) {
+            Ok(handle) => {
+                let mut state = self.0.lock().expect("lock failed");
+                if generation == state.generation && !state.stopped {
+                    state.supervisor.children[index].handle = Some(handle);
+                } else {
+                    drop(state);
+                    _ = handle.cancel();
+                }
+            }
+            Err(e) => {
+                error!("start child failed: {e}");
+            }
+        }
+    }
+
+    fn on_exit(self: &Arc<Self>, index: usize, generation: u64, error: Option<String>) {
+        let mut state = self.0.lock().expect("lock failed");
+        if generation != state.generation {
+            //OneForAll重启前被停止的任务，全部退出后再一起重启
+            state.supervisor.children[index].alive = false;
+            let all_exited = state.supervisor.children.iter().all(|child| !child.alive);
+            let count = state.supervisor.children.len();
+            drop(state);
+            if all_exited {
+                for index in 0..count {
+                    self.spawn(index);
+                }
+            }
+            return;
+        }
+        let child = &mut state.supervisor.children[index];
+        child.alive = false;
+        child.handle = None;
+        let Some(error) = error else {
+            return;
+        };
+        if state.stopped {
+            return;
+        }
+        let child = state.supervisor.children[index].name.clone();
+        let name = state.supervisor.name.clone();
+        let listeners = state.supervisor.listeners.clone();
+        let now = now();
+        let period = state.supervisor.period;
+        state.restarts.push_back(now);
+        while state
+            .restarts
+            .front()
+            .is_some_and(|time| now.saturating_sub(*time) > period)
+        {
+            _ = state.restarts.pop_front();
+        }
+        if state.restarts.len() > state.supervisor.max_restarts {
+            state.stopped = true;
+            state.escalated = true;
+            let stopped = state.stop_children();
+            drop(state);
+            cancel(stopped);
+            for listener in listeners {
+                listener.on_escalate(&name, &child, &error);
+            }
+            return;
+        }
+        state.restarted += 1;
+        let mut stopped = Vec::new();
+        let restart = match state.supervisor.strategy {
+            RestartStrategy::OneForOne => vec![index],
+            RestartStrategy::OneForAll => {
+                state.generation += 1;
+                stopped = state.stop_children();
+                if state.supervisor.children.iter().any(|child| child.alive) {
+                    //等被停止的任务全部退出
+                    Vec::new()
+                } else {
+                    (0..state.supervisor.children.len()).collect()
+                }
+            }
+        };
+        drop(state);
+        cancel(stopped);
+        for listener in listeners {
+            listener.on_restart(&name, &child, &error);
+        }
+        for index in restart {
+            self.spawn(index);
+        }
+    }
+}
+
+/// Notify the supervisor when the child exits, a child dropped before it runs is
+/// treated as failed.
+#[derive(Debug)]
+struct Exit {
+    shared: Arc<Shared>,
+    index: usize,
+    generation: u64,
+    error: Option<String>,
+}
+
+impl Drop for Exit {
+    fn drop(&mut self) {
+        self.shared
+            .on_exit(self.index, self.generation, self.error.take());
+    }
+}
+
+fn cancel(handles: Vec<JoinHandle>) {
+    for handle in handles {
+        _ = handle.cancel();
+    }
+}
+
+fn message(e: &(dyn Any + Send)) -> String {
+    if let Some(msg) = e.downcast_ref::<&'static str>() {
+        return (*msg).to_string();
+    }
+    e.downcast_ref::<String>()
+        .cloned()
+        .unwrap_or_else(|| String::from("child failed without message"))
+}
+
+/// The handle of a started [`Supervisor`].
+#[repr(C)]
+#[derive(Debug)]
+pub struct SupervisorHandle(Arc<Shared>);
+
+impl SupervisorHandle {
+    /// Stop all the children, and never restart them.
+    pub fn stop(&self) {
+        let stopped = {
+            let mut state = self.0 .0.lock().expect("lock failed");
+            state.stopped = true;
+            state.stop_children()
+        };
+        cancel(stopped);
+    }
+
+    /// Returns `true` if the supervisor has given up and escalated.
+    #[must_use]
+    pub fn is_escalated(&self) -> bool {
+        self.0 .0.lock().expect("lock failed").escalated
+    }
+
+    /// Returns how many times the children have been restarted.
+    #[must_use]
+    pub fn restarted(&self) -> usize {
+        self.0 .0.lock().expect("lock failed").restarted
+    }
+}
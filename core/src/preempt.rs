@@ -0,0 +1,165 @@
+use crate::scheduler::SchedulableSuspender;
+use std::cell::Cell;
+use std::io::{Error, ErrorKind};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+thread_local! {
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+    static PENDING: Cell<bool> = const { Cell::new(false) };
+}
+
+const MAX_REGIONS: usize = 64;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_REGION: (AtomicUsize, AtomicUsize) = (AtomicUsize::new(0), AtomicUsize::new(0));
+
+/// The signal handler can not take locks, so the regions are only appended.
+static REGIONS: [(AtomicUsize, AtomicUsize); MAX_REGIONS] = [EMPTY_REGION; MAX_REGIONS];
+
+static REGION_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Mark the code in `[start, end)` as non-preemptible, a coroutine interrupted
+/// there will not be suspended until it reaches the next safepoint.
+///
+/// # Errors
+/// if the range is empty or too many regions have been registered.
+///
+/// # Examples
+///
+/// ```
+/// use open_coroutine_core::preempt::{is_non_preemptible, register_non_preemptible};
+///
+/// register_non_preemptible(0x1000, 0x2000).expect("register failed");
+/// assert!(is_non_preemptible(0x1000));
+/// assert!(is_non_preemptible(0x1fff));
+/// assert!(!is_non_preemptible(0x2000));
+/// ```
+pub fn register_non_preemptible(start: usize, end: usize) -> std::io::Result<()> {
+    if start >= end {
+        return Err(Error::new(ErrorKind::InvalidInput, "empty region"));
+    }
+    let index = REGION_COUNT.fetch_add(1, Ordering::AcqRel);
+    if index >= MAX_REGIONS {
+        _ = REGION_COUNT.fetch_sub(1, Ordering::AcqRel);
+        return Err(Error::new(
+            ErrorKind::Other,
+            "too many non-preemptible regions",
+        ));
+    }
+    let (region_start, region_end) = &REGIONS[index];
+    region_start.store(start, Ordering::Release);
+    region_end.store(end, Ordering::Release);
+    Ok(())
+}
+
+/// Returns `true` if `ip` is inside a non-preemptible region.
+#[must_use]
+pub fn is_non_preemptible(ip: usize) -> bool {
+    let count = REGION_COUNT.load(Ordering::Acquire).min(MAX_REGIONS);
+    REGIONS[..count]
+        .iter()
+        .any(|(start, end)| start.load(Ordering::Acquire) <= ip && ip < end.load(Ordering::Acquire))
+}
+
+/// A scoped guard which disables preemption of the current coroutine until it is dropped.
+///
+/// Guards can be nested, if the time slice expired while any guard was held, the
+/// coroutine yields when the outermost guard is dropped.
+///
+/// The guard should not be held across a suspension point.
+///
+/// # Examples
+///
+/// ```
+/// use open_coroutine_core::preempt::PreemptionGuard;
+///
+/// assert!(!PreemptionGuard::is_active());
+/// {
+///     let _guard = PreemptionGuard::new();
+///     assert!(PreemptionGuard::is_active());
+///     {
+///         let _nested = PreemptionGuard::new();
+///         assert!(PreemptionGuard::is_active());
+///     }
+///     assert!(PreemptionGuard::is_active());
+/// }
+/// assert!(!PreemptionGuard::is_active());
+/// ```
+#[repr(C)]
+#[derive(Debug)]
+pub struct PreemptionGuard(PhantomData<*const ()>);
+
+impl PreemptionGuard {
+    /// Enter a critical section, the current coroutine will not be preempted until the guard is dropped.
+    #[must_use]
+    pub fn new() -> Self {
+        DEPTH.with(|depth| depth.set(depth.get() + 1));
+        PreemptionGuard(PhantomData)
+    }
+
+    /// Returns `true` if the current thread is in a critical section.
+    #[must_use]
+    pub fn is_active() -> bool {
+        DEPTH.with(Cell::get) > 0
+    }
+
+    /// Record a preemption request which will be served when the outermost guard
+    /// is dropped or at the next safepoint.
+    #[cfg(all(unix, feature = "preemptive"))]
+    pub(crate) fn defer() {
+        PENDING.with(|pending| pending.set(true));
+    }
+
+    /// Yield if a preemption request was deferred and the current thread is not
+    /// in a critical section, it's generally only required for framework level crates.
+    pub fn safepoint() {
+        if Self::is_active() || !PENDING.with(|pending| pending.replace(false)) {
+            return;
+        }
+        if let Some(suspender) = SchedulableSuspender::current() {
+            suspender.suspend();
+        }
+    }
+}
+
+impl Default for PreemptionGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for PreemptionGuard {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get().saturating_sub(1)));
+        //时间片已在临界区内耗尽，退出临界区后立即让出
+        Self::safepoint();
+    }
+}
+
+/// Like [`PreemptionGuard`], but the preemption request deferred while it's held is left
+/// to the next safepoint when it's dropped, so dropping it never suspends the current
+/// coroutine.
+///
+/// It's used where suspending is not allowed, such as in the middle of a state transition.
+#[cfg(all(target_os = "linux", feature = "preemptive"))]
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct DeferringGuard(PhantomData<*const ()>);
+
+#[cfg(all(target_os = "linux", feature = "preemptive"))]
+impl DeferringGuard {
+    /// Enter a critical section without a safepoint at the exit.
+    #[must_use]
+    pub(crate) fn new() -> Self {
+        DEPTH.with(|depth| depth.set(depth.get() + 1));
+        DeferringGuard(PhantomData)
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "preemptive"))]
+impl Drop for DeferringGuard {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get().saturating_sub(1)));
+    }
+}
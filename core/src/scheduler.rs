@@ -5,14 +5,20 @@ use crate::common::work_steal::{LocalQueue, WorkStealQueue};
 use crate::common::{get_timeout_time, now};
 use crate::coroutine::listener::Listener;
 use crate::coroutine::suspender::Suspender;
-use crate::coroutine::Coroutine;
+use crate::coroutine::{Coroutine, CoroutineId};
+use crate::preempt::PreemptionGuard;
 use crate::{co, impl_current_for, impl_display_by_debug, impl_for_named};
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use std::collections::VecDeque;
 use std::io::{Error, ErrorKind};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
 use std::time::Duration;
 
+//协程本地变量中的停车标记
+const PARKING: &str = "open-coroutine-parking";
+
 /// A type for Scheduler.
 pub type SchedulableCoroutineState = CoroutineState<(), Option<usize>>;
 
@@ -22,6 +28,13 @@ pub type SchedulableCoroutine<'s> = Coroutine<'s, (), (), Option<usize>>;
 /// A type for Scheduler.
 pub type SchedulableSuspender<'s> = Suspender<'s, (), ()>;
 
+//协程的结果，或者协程已分离不需要保存结果
+#[derive(Debug)]
+enum CoResult<'s> {
+    Saved(Result<Option<usize>, &'s str>),
+    Detached,
+}
+
 /// The scheduler impls.
 #[repr(C)]
 #[derive(Debug)]
@@ -31,9 +44,21 @@ pub struct Scheduler<'s> {
     listeners: VecDeque<&'s dyn Listener<(), Option<usize>>>,
     ready: LocalQueue<'s, SchedulableCoroutine<'s>>,
     suspend: TimerList<SchedulableCoroutine<'s>>,
-    syscall: DashMap<&'s str, SchedulableCoroutine<'s>>,
-    syscall_suspend: TimerList<&'s str>,
-    results: DashMap<&'s str, Result<Option<usize>, &'s str>>,
+    syscall: DashMap<CoroutineId, SchedulableCoroutine<'s>>,
+    syscall_suspend: TimerList<CoroutineId>,
+    //停车的协程，None表示在停车前已被唤醒
+    parked: DashMap<CoroutineId, Option<SchedulableCoroutine<'s>>>,
+    park_timeout: TimerList<CoroutineId>,
+    //同一个map记录结果和分离标记，分离与保存结果互斥
+    results: DashMap<CoroutineId, CoResult<'s>>,
+    tickets: AtomicU64,
+    //正在执行的协程对应的票据，0表示当前没有协程在执行
+    resuming: AtomicU64,
+    resumed_at: AtomicU64,
+    //调度权被其他线程接管的次数
+    handoffs: AtomicU64,
+    //调度权被接管后才返回的协程，由接管的线程继续处理
+    returned: Mutex<VecDeque<(SchedulableCoroutine<'s>, SchedulableCoroutineState)>>,
 }
 
 impl Default for Scheduler<'_> {
@@ -68,6 +93,11 @@ impl Drop for Scheduler<'_> {
             "There are still coroutines to be carried out in the syscall queue:{:#?} !",
             self.syscall
         );
+        assert!(
+            self.parked.iter().all(|entry| entry.value().is_none()),
+            "There are still coroutines to be carried out in the parked queue:{:#?} !",
+            self.parked
+        );
     }
 }
 
@@ -92,7 +122,14 @@ impl<'s> Scheduler<'s> {
             suspend: TimerList::default(),
             syscall: DashMap::default(),
             syscall_suspend: TimerList::default(),
+            parked: DashMap::default(),
+            park_timeout: TimerList::default(),
             results: DashMap::default(),
+            tickets: AtomicU64::new(0),
+            resuming: AtomicU64::new(0),
+            resumed_at: AtomicU64::new(0),
+            handoffs: AtomicU64::new(0),
+            returned: Mutex::new(VecDeque::new()),
         }
     }
 
@@ -112,13 +149,15 @@ impl<'s> Scheduler<'s> {
     /// Allow multiple threads to concurrently submit coroutine to the scheduler,
     /// but only allow one thread to execute scheduling.
     ///
+    /// Returns the id of the new coroutine.
+    ///
     /// # Errors
     /// if create coroutine fails.
     pub fn submit_co(
         &self,
         f: impl FnOnce(&Suspender<(), ()>, ()) -> Option<usize> + 'static,
         stack_size: Option<usize>,
-    ) -> std::io::Result<()> {
+    ) -> std::io::Result<CoroutineId> {
         let mut co = co!(
             format!("{}@{}", self.name(), uuid::Uuid::new_v4()),
             f,
@@ -127,8 +166,8 @@ impl<'s> Scheduler<'s> {
         for listener in self.listeners.clone() {
             co.add_raw_listener(listener);
         }
-        // let co_name = Box::leak(Box::from(coroutine.get_name()));
-        self.submit_raw_co(co)
+        let co_id = co.id();
+        self.submit_raw_co(co).map(|()| co_id)
     }
 
     /// Add a listener to this scheduler.
@@ -141,6 +180,7 @@ impl<'s> Scheduler<'s> {
     /// Allow multiple threads to concurrently submit coroutine to the scheduler,
     /// but only allow one thread to execute scheduling.
     pub fn submit_raw_co(&self, coroutine: SchedulableCoroutine<'s>) -> std::io::Result<()> {
+        let _guard = PreemptionGuard::new();
         self.ready.push_back(coroutine);
         Ok(())
     }
@@ -152,8 +192,9 @@ impl<'s> Scheduler<'s> {
     ///
     /// # Errors
     /// if change to ready fails.
-    pub fn try_resume(&self, co_name: &'s str) {
-        if let Some((_, co)) = self.syscall.remove(&co_name) {
+    pub fn try_resume(&self, co_id: CoroutineId) {
+        let _guard = PreemptionGuard::new();
+        if let Some((_, co)) = self.syscall.remove(&co_id) {
             match co.state() {
                 CoroutineState::SystemCall(val, syscall, SyscallState::Suspend(_)) => {
                     co.syscall(val, syscall, SyscallState::Callback)
@@ -165,6 +206,118 @@ impl<'s> Scheduler<'s> {
         }
     }
 
+    /// Park the current coroutine until [`Scheduler::unpark`] is called with its id or
+    /// the `timeout_time` timestamp is reached, it's generally only required for framework
+    /// level crates.
+    ///
+    /// An `unpark` which happens before the coroutine is parked will not be lost.
+    pub fn park(&self, suspender: &SchedulableSuspender, timeout_time: u64) {
+        //协程挂起后会被移动，不能持有它的引用
+        let Some(co_id) = SchedulableCoroutine::current().map(|co| {
+            _ = co.put(PARKING, timeout_time);
+            co.id()
+        }) else {
+            return;
+        };
+        suspender.suspend();
+        let _guard = PreemptionGuard::new();
+        //超时醒来时清理残留的唤醒标记
+        _ = self.parked.remove_if(&co_id, |_, co| co.is_none());
+    }
+
+    /// Wake up the coroutine parked by [`Scheduler::park`],
+    /// it's generally only required for framework level crates.
+    ///
+    /// If the coroutine has not been parked yet, its next park returns immediately.
+    pub fn unpark(&self, co_id: CoroutineId) {
+        let _guard = PreemptionGuard::new();
+        match self.parked.entry(co_id) {
+            Entry::Occupied(entry) => {
+                if entry.get().is_some() {
+                    if let Some(co) = entry.remove() {
+                        self.ready.push_back(co);
+                    }
+                }
+            }
+            Entry::Vacant(entry) => _ = entry.insert(None),
+        }
+    }
+
+    fn do_park(&mut self, coroutine: SchedulableCoroutine<'s>, timeout_time: u64) {
+        let _guard = PreemptionGuard::new();
+        let co_id = coroutine.id();
+        match self.parked.entry(co_id) {
+            Entry::Occupied(entry) => {
+                //停车前已被唤醒
+                _ = entry.remove();
+                self.ready.push_back(coroutine);
+            }
+            Entry::Vacant(entry) => {
+                _ = entry.insert(Some(coroutine));
+                if timeout_time < u64::MAX {
+                    self.park_timeout.insert(timeout_time, co_id);
+                }
+            }
+        }
+    }
+
+    /// Returns the timestamp when this scheduler has coroutines to resume next,
+    /// `u64::MAX` means it's idle until someone submits or wakes up a coroutine.
+    pub(crate) fn next_schedule_time(&self) -> u64 {
+        if !self.ready.is_empty() {
+            return 0;
+        }
+        {
+            let _guard = PreemptionGuard::new();
+            if !self.returned.lock().expect("lock failed").is_empty() {
+                //有接管前阻塞的协程已归还
+                return 0;
+            }
+        }
+        [
+            self.suspend.front().map(|(t, _)| *t),
+            self.syscall_suspend.front().map(|(t, _)| *t),
+            self.park_timeout.front().map(|(t, _)| *t),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap_or(u64::MAX)
+    }
+
+    /// Returns the number of coroutines suspended in syscalls.
+    pub(crate) fn syscall_size(&self) -> usize {
+        self.syscall.len()
+    }
+
+    /// Returns the number of coroutines which are ready to run.
+    pub(crate) fn ready_size(&self) -> usize {
+        self.ready.len()
+    }
+
+    /// Attempt to obtain the result of the coroutine with the given `co_id`.
+    pub fn try_get_co_result(&self, co_id: CoroutineId) -> Option<Result<Option<usize>, &'s str>> {
+        let _guard = PreemptionGuard::new();
+        match self
+            .results
+            .remove_if(&co_id, |_, r| matches!(r, CoResult::Saved(_)))
+        {
+            Some((_, CoResult::Saved(result))) => Some(result),
+            _ => None,
+        }
+    }
+
+    /// Discard the result of the coroutine with the given `co_id`,
+    /// it's generally used for coroutines which nobody will wait for.
+    pub fn detach(&self, co_id: CoroutineId) {
+        let _guard = PreemptionGuard::new();
+        match self.results.entry(co_id) {
+            //已保存的结果直接丢弃
+            Entry::Occupied(entry) => _ = entry.remove(),
+            Entry::Vacant(entry) => _ = entry.insert(CoResult::Detached),
+        }
+    }
+
     /// Schedule the coroutines.
     ///
     /// Allow multiple threads to concurrently submit coroutine to the scheduler,
@@ -209,55 +362,142 @@ impl<'s> Scheduler<'s> {
             if 0 == left_time {
                 return Ok(0);
             }
+            self.check_returned()?;
             self.check_ready()?;
             // schedule coroutines
             if let Some(mut coroutine) = self.ready.pop_front() {
-                match coroutine.resume()? {
-                    CoroutineState::SystemCall((), _, state) => {
-                        //挂起协程到系统调用表
-                        let co_name = Box::leak(Box::from(coroutine.name()));
-                        //如果已包含，说明当前系统调用还有上层父系统调用，因此直接忽略插入结果
-                        _ = self.syscall.insert(co_name, coroutine);
-                        if let SyscallState::Suspend(timestamp) = state {
-                            self.syscall_suspend.insert(timestamp, co_name);
-                        }
-                    }
-                    CoroutineState::Suspend((), timestamp) => {
-                        if timestamp > now() {
-                            //挂起协程到时间轮
-                            self.suspend.insert(timestamp, coroutine);
-                        } else {
-                            //放入就绪队列尾部
-                            self.ready.push_back(coroutine);
-                        }
-                    }
-                    CoroutineState::Complete(result) => {
-                        let co_name = Box::leak(Box::from(coroutine.name()));
-                        assert!(
-                            self.results.insert(co_name, Ok(result)).is_none(),
-                            "not consume result"
-                        );
-                    }
-                    CoroutineState::Error(message) => {
-                        let co_name = Box::leak(Box::from(coroutine.name()));
-                        assert!(
-                            self.results.insert(co_name, Err(message)).is_none(),
-                            "not consume result"
-                        );
-                    }
-                    _ => {
-                        return Err(Error::new(
-                            ErrorKind::Other,
-                            "try_timeout_schedule should never execute to here",
-                        ));
-                    }
+                let ticket = self.tickets.fetch_add(1, Ordering::Relaxed) + 1;
+                self.resumed_at.store(now(), Ordering::Release);
+                self.resuming.store(ticket, Ordering::Release);
+                let state = coroutine.resume();
+                if self
+                    .resuming
+                    .compare_exchange(ticket, 0, Ordering::AcqRel, Ordering::Acquire)
+                    .is_err()
+                {
+                    //调度权已被接管，协程交给接管的线程处理，当前线程不再调度
+                    let _guard = PreemptionGuard::new();
+                    self.returned
+                        .lock()
+                        .expect("lock failed")
+                        .push_back((coroutine, state?));
+                    return Ok(0);
                 }
+                self.schedule_resumed(coroutine, state?)?;
                 continue;
             }
             return Ok(left_time);
         }
     }
 
+    fn schedule_resumed(
+        &mut self,
+        coroutine: SchedulableCoroutine<'s>,
+        state: SchedulableCoroutineState,
+    ) -> std::io::Result<()> {
+        match state {
+            CoroutineState::SystemCall((), _, state) => {
+                //挂起协程到系统调用表
+                let co_id = coroutine.id();
+                //如果已包含，说明当前系统调用还有上层父系统调用，因此直接忽略插入结果
+                _ = self.syscall.insert(co_id, coroutine);
+                if let SyscallState::Suspend(timestamp) = state {
+                    self.syscall_suspend.insert(timestamp, co_id);
+                }
+            }
+            CoroutineState::Suspend((), timestamp) => {
+                if let Some(timeout_time) = coroutine.remove::<u64>(PARKING) {
+                    //停车直到被唤醒或者超时
+                    self.do_park(coroutine, timeout_time);
+                } else if timestamp > now() {
+                    //挂起协程到时间轮
+                    self.suspend.insert(timestamp, coroutine);
+                } else {
+                    //放入就绪队列尾部
+                    self.ready.push_back(coroutine);
+                }
+            }
+            CoroutineState::Complete(result) => self.save_result(coroutine.id(), Ok(result)),
+            CoroutineState::Error(message) => self.save_result(coroutine.id(), Err(message)),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    "try_timeout_schedule should never execute to here",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn save_result(&self, co_id: CoroutineId, result: Result<Option<usize>, &'s str>) {
+        let _guard = PreemptionGuard::new();
+        //协程结束前未停车，清理残留的唤醒标记
+        _ = self.parked.remove_if(&co_id, |_, co| co.is_none());
+        match self.results.entry(co_id) {
+            Entry::Occupied(entry) => {
+                assert!(
+                    matches!(entry.get(), CoResult::Detached),
+                    "not consume result"
+                );
+                _ = entry.remove();
+            }
+            Entry::Vacant(entry) => _ = entry.insert(CoResult::Saved(result)),
+        }
+    }
+
+    /// Returns the ticket of the coroutine being resumed if the thread which is
+    /// scheduling has been blocked in it for at least `blocked_time` ns.
+    ///
+    /// The blocked coroutine can't be moved to another thread, but the caller can
+    /// take over the scheduling by [`Scheduler::take_over`].
+    pub(crate) fn blocked(&self, blocked_time: u64) -> Option<u64> {
+        let ticket = self.resuming.load(Ordering::Acquire);
+        (0 != ticket
+            && now().saturating_sub(self.resumed_at.load(Ordering::Acquire)) >= blocked_time)
+            .then_some(ticket)
+    }
+
+    /// Take over the scheduling from the thread which is blocked in the coroutine with
+    /// the `ticket`, returns `false` if the coroutine has returned or the scheduling
+    /// has already been taken over.
+    ///
+    /// On success the caller should schedule in place of the blocked thread, which
+    /// stops scheduling and hands the coroutine back once it returns.
+    pub(crate) fn take_over(&self, ticket: u64) -> bool {
+        let _guard = PreemptionGuard::new();
+        //持有锁直到计数增加，保证被阻塞的线程归还协程时能看到新的计数
+        let _returned = self.returned.lock().expect("lock failed");
+        if self
+            .resuming
+            .compare_exchange(ticket, 0, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return false;
+        }
+        _ = self.handoffs.fetch_add(1, Ordering::Release);
+        true
+    }
+
+    /// Returns how many times the scheduling has been taken over, the thread which
+    /// began scheduling before the last change should stop scheduling.
+    #[must_use]
+    pub(crate) fn handoffs(&self) -> u64 {
+        self.handoffs.load(Ordering::Acquire)
+    }
+
+    fn check_returned(&mut self) -> std::io::Result<()> {
+        loop {
+            let returned = {
+                let _guard = PreemptionGuard::new();
+                self.returned.lock().expect("lock failed").pop_front()
+            };
+            let Some((coroutine, state)) = returned else {
+                return Ok(());
+            };
+            self.schedule_resumed(coroutine, state)?;
+        }
+    }
+
     fn check_ready(&mut self) -> std::io::Result<()> {
         // Check if the elements in the suspend queue are ready
         for _ in 0..self.suspend.entry_len() {
@@ -273,6 +513,24 @@ impl<'s> Scheduler<'s> {
                 }
             }
         }
+        // Check if the elements in the park timeout queue are ready
+        for _ in 0..self.park_timeout.entry_len() {
+            if let Some((exec_time, _)) = self.park_timeout.front() {
+                if now() < *exec_time {
+                    break;
+                }
+                if let Some((_, mut entry)) = self.park_timeout.pop_front() {
+                    let _guard = PreemptionGuard::new();
+                    while let Some(co_id) = entry.pop_front() {
+                        if let Some((_, Some(co))) =
+                            self.parked.remove_if(&co_id, |_, co| co.is_some())
+                        {
+                            self.ready.push_back(co);
+                        }
+                    }
+                }
+            }
+        }
         // Check if the elements in the syscall suspend queue are ready
         for _ in 0..self.syscall_suspend.entry_len() {
             if let Some((exec_time, _)) = self.syscall_suspend.front() {
@@ -280,8 +538,8 @@ impl<'s> Scheduler<'s> {
                     break;
                 }
                 if let Some((_, mut entry)) = self.syscall_suspend.pop_front() {
-                    while let Some(co_name) = entry.pop_front() {
-                        if let Some((_, co)) = self.syscall.remove(&co_name) {
+                    while let Some(co_id) = entry.pop_front() {
+                        if let Some((_, co)) = self.syscall.remove(&co_id) {
                             match co.state() {
                                 CoroutineState::SystemCall(
                                     val,
@@ -301,3 +559,79 @@ impl<'s> Scheduler<'s> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocked() -> std::io::Result<()> {
+        let mut scheduler = Scheduler::default();
+        assert_eq!(None, scheduler.blocked(0));
+        let co = scheduler.submit_co(
+            |_, ()| {
+                //模拟调用未hook的阻塞函数
+                let scheduler = Scheduler::current().expect("no scheduler");
+                let ticket = scheduler.blocked(0).expect("not resuming");
+                assert_eq!(Some(ticket), scheduler.blocked(0));
+                assert_eq!(None, scheduler.blocked(u64::MAX));
+                usize::try_from(ticket).ok()
+            },
+            None,
+        )?;
+        scheduler.try_schedule()?;
+        let Some(Ok(Some(ticket))) = scheduler.try_get_co_result(co) else {
+            panic!("coroutine failed");
+        };
+        assert_ne!(Some(ticket as u64), scheduler.blocked(0));
+        assert_eq!(None, scheduler.blocked(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_take_over() -> std::io::Result<()> {
+        let mut scheduler = Scheduler::default();
+        let blocked = scheduler.submit_co(
+            |_, ()| {
+                //模拟监控线程在协程阻塞时接管调度
+                let scheduler = Scheduler::current().expect("no scheduler");
+                let ticket = scheduler.blocked(0).expect("not resuming");
+                assert!(scheduler.take_over(ticket));
+                assert!(!scheduler.take_over(ticket));
+                assert_eq!(None, scheduler.blocked(0));
+                Some(1)
+            },
+            None,
+        )?;
+        let ready = scheduler.submit_co(|_, ()| Some(2), None)?;
+        //被接管后不再调度，阻塞的协程归还给接管者
+        assert_eq!(0, scheduler.try_timeout_schedule(u64::MAX)?);
+        assert_eq!(1, scheduler.handoffs());
+        assert!(scheduler.try_get_co_result(blocked).is_none());
+        assert!(scheduler.try_get_co_result(ready).is_none());
+        assert_eq!(0, scheduler.next_schedule_time());
+        //作为接管者继续调度
+        scheduler.try_schedule()?;
+        assert_eq!(Some(Ok(Some(1))), scheduler.try_get_co_result(blocked));
+        assert_eq!(Some(Ok(Some(2))), scheduler.try_get_co_result(ready));
+        assert_eq!(1, scheduler.handoffs());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpark_without_park() -> std::io::Result<()> {
+        let mut scheduler = Scheduler::default();
+        _ = scheduler.submit_co(
+            |_, ()| {
+                let co_id = SchedulableCoroutine::current().expect("no coroutine").id();
+                Scheduler::current().expect("no scheduler").unpark(co_id);
+                None
+            },
+            None,
+        )?;
+        scheduler.try_schedule()?;
+        //协程没有停车就结束了，唤醒标记不能残留
+        assert!(scheduler.parked.is_empty());
+        Ok(())
+    }
+}
@@ -4,6 +4,31 @@ pub extern "C" fn reset_errno() {
     set_errno(0);
 }
 
+/// If the task running in the current coroutine has been cancelled, fail the `syscall`
+/// with `ECANCELED` instead of executing it.
+///
+/// The syscalls used to release resources or locks are never failed, and neither are
+/// the ones which can't return `-1`.
+#[cfg(unix)]
+#[must_use]
+pub fn cancelled<R: TryFrom<i8>>(syscall: crate::common::constants::Syscall) -> Option<R> {
+    use crate::common::constants::Syscall;
+    if matches!(
+        syscall,
+        Syscall::close
+            | Syscall::pthread_mutex_lock
+            | Syscall::pthread_mutex_trylock
+            | Syscall::pthread_mutex_unlock
+            | Syscall::pthread_cond_timedwait
+    ) || !crate::co_pool::CoroutinePool::current_task_cancelled()
+    {
+        return None;
+    }
+    let r = R::try_from(-1).ok()?;
+    set_errno(libc::ECANCELED);
+    Some(r)
+}
+
 #[macro_export]
 macro_rules! log_syscall {
     ( $socket:expr, $done:expr, $once_result:expr ) => {
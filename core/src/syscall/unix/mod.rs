@@ -48,6 +48,13 @@ macro_rules! impl_facade {
             ) -> $result {
                 let syscall = $crate::common::constants::Syscall::$syscall;
                 $crate::info!("enter syscall {}", syscall);
+                //响应在不安全区域内被推迟的抢占
+                $crate::preempt::PreemptionGuard::safepoint();
+                //所属任务已被取消
+                if let Some(r) = $crate::syscall::common::cancelled(syscall) {
+                    $crate::info!("exit syscall {} cancelled", syscall);
+                    return r;
+                }
                 if let Some(co) = $crate::scheduler::SchedulableCoroutine::current() {
                     let new_state = $crate::common::constants::SyscallState::Executing;
                     if co.syscall((), syscall, new_state).is_err() {
@@ -9,6 +9,7 @@ fn co_pool_basic() -> std::io::Result<()> {
         Some(String::from("test_panic")),
         |_| panic!("test panic, just ignore it"),
         None,
+        None,
     )?;
     assert!(!pool.is_empty());
     pool.submit_task(
@@ -18,6 +19,7 @@ fn co_pool_basic() -> std::io::Result<()> {
             Some(2)
         },
         None,
+        None,
     )?;
     pool.try_schedule_task()
 }
@@ -39,6 +41,7 @@ fn co_pool_suspend() -> std::io::Result<()> {
             param
         },
         None,
+        None,
     )?;
     _ = pool.submit_task(
         None,
@@ -47,6 +50,7 @@ fn co_pool_suspend() -> std::io::Result<()> {
             Some(1)
         },
         None,
+        None,
     )?;
     pool.try_schedule_task()?;
     std::thread::sleep(std::time::Duration::from_millis(200));
@@ -58,7 +62,7 @@ fn co_pool_suspend() -> std::io::Result<()> {
 fn co_pool_stop() -> std::io::Result<()> {
     let pool = open_coroutine_core::co_pool::CoroutinePool::default();
     pool.set_max_size(1);
-    _ = pool.submit_task(None, |_| panic!("test panic, just ignore it"), None)?;
+    _ = pool.submit_task(None, |_| panic!("test panic, just ignore it"), None, None)?;
     pool.submit_task(
         None,
         |_| {
@@ -66,6 +70,798 @@ fn co_pool_stop() -> std::io::Result<()> {
             Some(2)
         },
         None,
+        None,
     )
     .map(|_| ())
 }
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_duplicate_name() -> std::io::Result<()> {
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(1);
+    let task_id = pool.submit_task(Some(String::from("duplicate")), |_| Some(1), None, None)?;
+    let error = pool
+        .submit_task(Some(String::from("duplicate")), |_| Some(2), None, None)
+        .unwrap_err();
+    assert_eq!(std::io::ErrorKind::AlreadyExists, error.kind());
+    pool.try_schedule_task()?;
+    assert_eq!(Some(Ok(Some(1))), pool.try_get_task_result(task_id));
+    // the name can be reused after the task finished
+    let task_id = pool.submit_task(Some(String::from("duplicate")), |_| Some(3), None, None)?;
+    pool.try_schedule_task()?;
+    assert_eq!(Some(Ok(Some(3))), pool.try_get_task_result(task_id));
+    assert_eq!(None, pool.try_get_task_result(task_id));
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_park_idle() -> std::io::Result<()> {
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_min_size(1);
+    pool.set_max_size(1);
+    let first = pool.submit_task(None, |_| Some(1), None, None)?;
+    //空闲的核心worker停车后，调度应该立即返回而不是空转到超时
+    let left_time = pool.try_timed_schedule_task(std::time::Duration::from_secs(1))?;
+    assert!(left_time > 0);
+    assert_eq!(1, pool.get_running_size());
+    assert_eq!(Some(Ok(Some(1))), pool.try_get_task_result(first));
+    //提交任务会直接唤醒停车的worker
+    let second = pool.submit_task(None, |_| Some(2), None, None)?;
+    let left_time = pool.try_timed_schedule_task(std::time::Duration::from_secs(1))?;
+    assert!(left_time > 0);
+    assert_eq!(1, pool.get_running_size());
+    assert_eq!(Some(Ok(Some(2))), pool.try_get_task_result(second));
+    pool.stop(std::time::Duration::from_secs(1))
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_reject_policy() -> std::io::Result<()> {
+    use open_coroutine_core::common::constants::RejectPolicy;
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(1);
+    pool.set_queue_capacity(1);
+    let first = pool.submit_task(None, |_| Some(1), None, None)?;
+    assert!(pool.submit_task(None, |_| Some(2), None, None).is_err());
+    assert_eq!(1, pool.get_rejected_count());
+
+    pool.set_reject_policy(RejectPolicy::CallerRuns);
+    let caller_runs = pool.submit_task(None, |_| Some(3), None, None)?;
+    assert_eq!(Some(Ok(Some(3))), pool.try_get_task_result(caller_runs));
+    // the task run by the caller is not rejected
+    assert_eq!(1, pool.get_rejected_count());
+
+    pool.set_reject_policy(RejectPolicy::DiscardOldest);
+    let newest = pool.submit_task(None, |_| Some(4), None, None)?;
+    assert_eq!(
+        Some(Err("The task was discarded !")),
+        pool.try_get_task_result(first)
+    );
+    assert_eq!(2, pool.get_rejected_count());
+
+    pool.try_schedule_task()?;
+    assert_eq!(Some(Ok(Some(4))), pool.try_get_task_result(newest));
+    // the place is given back after the task leaves the queue
+    pool.set_reject_policy(RejectPolicy::Abort);
+    let task_id = pool.submit_task(None, |_| Some(5), None, None)?;
+    pool.try_schedule_task()?;
+    assert_eq!(Some(Ok(Some(5))), pool.try_get_task_result(task_id));
+    assert_eq!(2, pool.get_rejected_count());
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_reject_block() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::CoroutinePool;
+    use open_coroutine_core::common::constants::RejectPolicy;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static EXECUTED: AtomicUsize = AtomicUsize::new(0);
+    let mut pool = CoroutinePool::default();
+    pool.set_max_size(2);
+    pool.set_queue_capacity(1);
+    pool.set_reject_policy(RejectPolicy::Block);
+    let task_id = pool.submit_task(
+        None,
+        |_| {
+            let pool = CoroutinePool::current().expect("no pool");
+            for _ in 0..2 {
+                //本池的协程提交时队列已满，不挂起而是直接执行，否则可能没有worker归还名额
+                _ = pool
+                    .submit_task(
+                        None,
+                        |p| {
+                            _ = EXECUTED.fetch_add(1, Ordering::Release);
+                            p
+                        },
+                        None,
+                        None,
+                    )
+                    .expect("submit failed");
+            }
+            assert!(EXECUTED.load(Ordering::Acquire) >= 1);
+            Some(1)
+        },
+        None,
+        None,
+    )?;
+    pool.try_timed_schedule_task(std::time::Duration::from_secs(1))?;
+    assert_eq!(Some(Ok(Some(1))), pool.try_get_task_result(task_id));
+    assert_eq!(2, EXECUTED.load(Ordering::Acquire));
+    assert_eq!(0, pool.get_rejected_count());
+    pool.stop(std::time::Duration::from_secs(1))
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_reject_block_timeout() -> std::io::Result<()> {
+    use open_coroutine_core::common::constants::RejectPolicy;
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(1);
+    pool.set_queue_capacity(1);
+    pool.set_reject_policy(RejectPolicy::Block);
+    pool.set_block_timeout(50_000_000);
+    let queued = pool.submit_task(None, |_| Some(1), None, None)?;
+    //没有调度，名额不会归还，超时后拒绝
+    let start = std::time::Instant::now();
+    assert!(pool.submit_task(None, |_| Some(2), None, None).is_err());
+    assert!(start.elapsed() >= std::time::Duration::from_millis(50));
+    assert_eq!(1, pool.get_rejected_count());
+    pool.try_schedule_task()?;
+    assert_eq!(Some(Ok(Some(1))), pool.try_get_task_result(queued));
+    pool.stop(std::time::Duration::from_secs(1))
+}
+
+#[test]
+fn co_pool_cancel_queued() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::task::is_cancelled;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    static EXECUTED: AtomicBool = AtomicBool::new(false);
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    let task_id = pool.submit_task(
+        Some(String::from("cancel_queued")),
+        |_| {
+            EXECUTED.store(true, Ordering::Release);
+            Some(1)
+        },
+        None,
+        None,
+    )?;
+    assert!(pool.cancel_task_by_name("cancel_queued"));
+    assert!(!pool.cancel_task(task_id));
+    assert!(pool
+        .try_get_task_result(task_id)
+        .is_some_and(|result| is_cancelled(&result)));
+    // the name can be reused once the task is cancelled
+    let task_id = pool.submit_task(Some(String::from("cancel_queued")), |p| p, None, None)?;
+    pool.try_schedule_task()?;
+    assert_eq!(Some(Ok(None)), pool.try_get_task_result(task_id));
+    assert!(!EXECUTED.load(Ordering::Acquire));
+    Ok(())
+}
+
+#[test]
+fn co_pool_cancel_eagerly() -> std::io::Result<()> {
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_queue_capacity(1);
+    let captured = std::sync::Arc::new(());
+    let clone = captured.clone();
+    let task_id = pool.submit_task(
+        None,
+        move |p| {
+            drop(clone);
+            p
+        },
+        None,
+        None,
+    )?;
+    assert!(pool.submit_task(None, |p| p, None, None).is_err());
+    assert_eq!(2, std::sync::Arc::strong_count(&captured));
+    // the closure and the place are given up before the task leaves the queue
+    assert!(pool.cancel_task(task_id));
+    assert_eq!(1, std::sync::Arc::strong_count(&captured));
+    let task_id = pool.submit_task(None, |_| Some(1), None, None)?;
+    pool.try_schedule_task()?;
+    assert_eq!(Some(Ok(Some(1))), pool.try_get_task_result(task_id));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn co_pool_cancel_running() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::task::is_cancelled;
+    use std::sync::atomic::{AtomicI32, Ordering};
+    static ERRNO: AtomicI32 = AtomicI32::new(0);
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(1);
+    let task_id = pool.submit_task(
+        None,
+        |_| {
+            if let Some(suspender) = open_coroutine_core::scheduler::SchedulableSuspender::current()
+            {
+                suspender.delay(std::time::Duration::from_millis(50));
+            }
+            // the cancelled task fails the hooked syscalls
+            if -1 == open_coroutine_core::syscall::usleep(None, 1) {
+                ERRNO.store(
+                    std::io::Error::last_os_error().raw_os_error().unwrap_or(0),
+                    Ordering::Release,
+                );
+            }
+            Some(1)
+        },
+        None,
+        None,
+    )?;
+    pool.try_schedule_task()?;
+    assert!(pool.cancel_task(task_id));
+    assert!(!pool.cancel_task(task_id));
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    pool.try_schedule_task()?;
+    assert_eq!(libc::ECANCELED, ERRNO.load(Ordering::Acquire));
+    assert!(pool
+        .try_get_task_result(task_id)
+        .is_some_and(|result| is_cancelled(&result)));
+    Ok(())
+}
+
+#[test]
+fn co_pool_continuation() -> std::io::Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COMPLETED: AtomicUsize = AtomicUsize::new(0);
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    let task_id = pool.submit_task(None, |_| Some(1), None, None)?;
+    let then = pool.submit_continuation(task_id, |r| r.map(|v| v.map(|v| v + 1)), false)?;
+    pool.try_schedule_task()?;
+    assert_eq!(Some(Ok(Some(2))), pool.try_get_task_result(then));
+    // the result is consumed by the continuation
+    assert_eq!(None, pool.try_get_task_result(task_id));
+    // the result has been joined
+    assert!(pool.submit_continuation(then, |r| r, false).is_err());
+
+    // the continuation submitted after the task completes
+    let task_id = pool.submit_task(None, |_| panic!("test panic, just ignore it"), None, None)?;
+    pool.try_schedule_task()?;
+    let then = pool.submit_continuation(task_id, |r| r.map(|_| Some(0)), false)?;
+    _ = pool.submit_continuation(
+        then,
+        |r| {
+            assert_eq!(Err("test panic, just ignore it"), r);
+            COMPLETED.store(1, Ordering::Release);
+            Ok(None)
+        },
+        true,
+    )?;
+    pool.try_schedule_task()?;
+    assert_eq!(1, COMPLETED.load(Ordering::Acquire));
+    assert_eq!(None, pool.try_get_task_result(then));
+    Ok(())
+}
+
+#[test]
+fn co_pool_continuation_stolen() -> std::io::Result<()> {
+    let owner = open_coroutine_core::co_pool::CoroutinePool::default();
+    let mut thief = open_coroutine_core::co_pool::CoroutinePool::default();
+    let task_id = owner.submit_task(None, |_| Some(1), None, None)?;
+    let then = owner.submit_continuation(task_id, |r| r.map(|v| v.map(|v| v + 1)), false)?;
+    // the task and its continuation run in the thief, the result is saved in the owner
+    thief.try_schedule_task()?;
+    assert!(owner.is_empty());
+    assert_eq!(None, thief.try_get_task_result(then));
+    assert_eq!(Some(Ok(Some(2))), owner.try_get_task_result(then));
+    Ok(())
+}
+
+#[test]
+fn co_pool_delayed_and_periodic() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::task::is_cancelled;
+    use open_coroutine_core::common::now;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    let delayed = pool.submit_task_at(now() + 50_000_000, None, |_| Some(1), None, None)?;
+    let periodic = pool.schedule_at_fixed_rate(
+        now(),
+        None,
+        |_| {
+            _ = RUNS.fetch_add(1, Ordering::Release);
+            None
+        },
+        None,
+        Duration::from_millis(10),
+        None,
+    )?;
+    assert!(pool
+        .schedule_with_fixed_delay(now(), None, |p| p, None, Duration::ZERO, None)
+        .is_err());
+    pool.try_schedule_task()?;
+    assert_eq!(None, pool.try_get_task_result(delayed));
+    let timeout_time = now() + 100_000_000;
+    while now() < timeout_time {
+        _ = pool.try_timed_schedule_task(Duration::from_millis(10))?;
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(Some(Ok(Some(1))), pool.try_get_task_result(delayed));
+    let runs = RUNS.load(Ordering::Acquire);
+    assert!((2..=11).contains(&runs), "unexpected runs {runs}");
+    // the results of the runs are not saved
+    assert_eq!(None, pool.try_get_task_result(periodic));
+
+    assert!(pool.cancel_task(periodic));
+    assert!(pool
+        .try_get_task_result(periodic)
+        .is_some_and(|result| is_cancelled(&result)));
+    std::thread::sleep(Duration::from_millis(20));
+    pool.try_schedule_task()?;
+    assert_eq!(runs, RUNS.load(Ordering::Acquire));
+
+    // the cancelled task is removed from the timers before it's due
+    let captured = std::sync::Arc::new(());
+    let clone = captured.clone();
+    let periodic = pool.schedule_with_fixed_delay(
+        now() + 1_000_000_000,
+        None,
+        move |p| {
+            _ = &clone;
+            p
+        },
+        None,
+        Duration::from_secs(1),
+        None,
+    )?;
+    assert_eq!(2, std::sync::Arc::strong_count(&captured));
+    assert!(pool.cancel_task(periodic));
+    assert_eq!(1, std::sync::Arc::strong_count(&captured));
+    assert!(pool
+        .try_get_task_result(periodic)
+        .is_some_and(|result| is_cancelled(&result)));
+    Ok(())
+}
+
+#[test]
+fn co_pool_autoscale() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::autoscaler::{Autoscaler, ScaleEvent, ScaleListener};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    static GROWN: AtomicUsize = AtomicUsize::new(0);
+    static SHRUNK: AtomicUsize = AtomicUsize::new(0);
+    #[derive(Debug)]
+    struct Counter;
+    impl ScaleListener for Counter {
+        fn on_scale(&self, _: &str, event: ScaleEvent) {
+            match event {
+                ScaleEvent::Grow { count, .. } => _ = GROWN.fetch_add(count, Ordering::Release),
+                ScaleEvent::Shrink { .. } => _ = SHRUNK.fetch_add(1, Ordering::Release),
+            }
+        }
+    }
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(8);
+    pool.set_keep_alive_time(10_000_000_000);
+    let mut autoscaler = Autoscaler::default();
+    _ = autoscaler.set_queue_depth(1).set_idle_time(0);
+    pool.set_autoscaler(Some(autoscaler));
+    pool.add_scale_listener(Counter);
+    for _ in 0..8 {
+        _ = pool.submit_task(None, |_| Some(1), None, None)?;
+    }
+    //排队的任务太多，一次扩容多个worker
+    pool.try_schedule_task()?;
+    assert_eq!(4, GROWN.load(Ordering::Acquire));
+    //空闲的worker不必等到保活时间结束就会退出，包括调度时创建的第一个worker
+    assert_eq!(0, pool.get_running_size());
+    assert_eq!(5, SHRUNK.load(Ordering::Acquire));
+    pool.stop(Duration::from_secs(1))
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_result_retention() -> std::io::Result<()> {
+    use std::time::Duration;
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_result_capacity(2);
+    let detached = pool.submit_detached_task(None, |_| Some(0), None, None)?;
+    let dropped = pool.submit_task(None, |_| Some(1), None, None)?;
+    pool.detach_task(dropped);
+    let tasks = (2..6)
+        .map(|i| pool.submit_task(None, move |_| Some(i), None, None))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    pool.try_schedule_task()?;
+    assert_eq!(None, pool.try_get_task_result(detached));
+    assert_eq!(None, pool.try_get_task_result(dropped));
+    //只保留最新的两个结果
+    assert_eq!(2, pool.get_evicted_count());
+    assert_eq!(None, pool.try_get_task_result(tasks[1]));
+    assert_eq!(Some(Ok(Some(4))), pool.try_get_task_result(tasks[2]));
+    assert_eq!(Some(Ok(Some(5))), pool.try_get_task_result(tasks[3]));
+
+    pool.set_result_ttl(10_000_000);
+    let expired = pool.submit_task(None, |_| Some(6), None, None)?;
+    pool.try_schedule_task()?;
+    std::thread::sleep(Duration::from_millis(20));
+    pool.try_schedule_task()?;
+    assert_eq!(None, pool.try_get_task_result(expired));
+    assert_eq!(3, pool.get_evicted_count());
+    pool.stop(Duration::from_secs(1))
+}
+
+#[test]
+fn co_pool_detach_everywhere() -> std::io::Result<()> {
+    use open_coroutine_core::common::constants::RejectPolicy;
+    use std::io::ErrorKind;
+    use std::time::Duration;
+    let mut owner = open_coroutine_core::co_pool::CoroutinePool::default();
+    owner.set_queue_capacity(1);
+    owner.set_reject_policy(RejectPolicy::DiscardOldest);
+    // the detached task neither saves its result when it's cancelled nor discarded
+    let cancelled = owner.submit_detached_task(None, |_| Some(1), None, None)?;
+    assert!(owner.cancel_task(cancelled));
+    let discarded = owner.submit_detached_task(None, |_| Some(2), None, None)?;
+    let stolen = owner.submit_task(None, |_| Some(3), None, None)?;
+    owner.detach_task(stolen);
+    // the task detached in the owner runs in the thief, nobody saves its result
+    let mut thief = open_coroutine_core::co_pool::CoroutinePool::default();
+    thief.try_schedule_task()?;
+    for task_id in [cancelled, discarded, stolen] {
+        assert_eq!(None, owner.try_get_task_result(task_id));
+        assert_eq!(None, thief.try_get_task_result(task_id));
+        let error = owner
+            .wait_task_result(task_id, Duration::from_secs(3))
+            .expect_err("the result should never be available");
+        assert_eq!(ErrorKind::NotFound, error.kind());
+    }
+
+    // the join on an evicted result fails immediately instead of timing out
+    owner.set_result_capacity(1);
+    let evicted = owner.submit_task(None, |_| Some(4), None, None)?;
+    owner.try_schedule_task()?;
+    let kept = owner.submit_task(None, |_| Some(5), None, None)?;
+    owner.try_schedule_task()?;
+    assert_eq!(1, owner.get_evicted_count());
+    let error = owner
+        .wait_task_result(evicted, Duration::from_secs(3))
+        .expect_err("the result should be evicted");
+    assert_eq!(ErrorKind::NotFound, error.kind());
+    assert_eq!(
+        Ok(Some(5)),
+        owner.wait_task_result(kept, Duration::from_secs(3))?
+    );
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_join_in_coroutine() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::CoroutinePool;
+    use open_coroutine_core::common::now;
+    use open_coroutine_core::scheduler::SchedulableSuspender;
+    use std::io::ErrorKind;
+    use std::time::Duration;
+    let mut pool = CoroutinePool::default();
+    pool.set_max_size(2);
+    let target = pool.submit_task(
+        None,
+        |_| {
+            if let Some(suspender) = SchedulableSuspender::current() {
+                suspender.delay(Duration::from_millis(50));
+            }
+            Some(1)
+        },
+        None,
+        None,
+    )?;
+    let waiter = pool.submit_task(
+        None,
+        move |_| {
+            let pool = CoroutinePool::current().expect("current pool not found");
+            //等待时挂起当前协程，超时由调度器唤醒
+            let timed_out = pool
+                .wait_task_result(target, Duration::from_millis(10))
+                .is_err_and(|e| ErrorKind::TimedOut == e.kind());
+            let result = pool
+                .wait_task_result(target, Duration::from_secs(1))
+                .ok()?
+                .ok()?;
+            timed_out.then_some(result?)
+        },
+        None,
+        None,
+    )?;
+    let timeout_time = now() + 1_000_000_000;
+    let mut result = None;
+    while result.is_none() && now() < timeout_time {
+        _ = pool.try_timed_schedule_task(Duration::from_millis(10))?;
+        result = pool.try_get_task_result(waiter);
+    }
+    assert_eq!(Some(Ok(Some(1))), result);
+    pool.stop(Duration::from_secs(1))
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_join_at_max_size() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::CoroutinePool;
+    use open_coroutine_core::common::now;
+    use std::time::Duration;
+    let mut pool = CoroutinePool::default();
+    pool.set_max_size(1);
+    let waiter = pool.submit_task(
+        None,
+        |_| {
+            let pool = CoroutinePool::current().expect("current pool not found");
+            let target = pool.submit_task(None, |_| Some(1), None, None).ok()?;
+            //等待的协程不占用名额，被等待的任务由新的协程执行
+            let result = pool
+                .wait_task_result(target, Duration::from_secs(1))
+                .ok()?
+                .ok()?;
+            result.map(|r| r + 1)
+        },
+        None,
+        None,
+    )?;
+    let timeout_time = now() + 1_000_000_000;
+    let mut result = None;
+    while result.is_none() && now() < timeout_time {
+        _ = pool.try_timed_schedule_task(Duration::from_millis(10))?;
+        result = pool.try_get_task_result(waiter);
+    }
+    assert_eq!(Some(Ok(Some(2))), result);
+    assert!(pool.get_running_size() <= 2);
+    pool.stop(Duration::from_secs(1))
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_task_listener() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::listener::TaskListener;
+    use open_coroutine_core::co_pool::task::is_cancelled;
+    use open_coroutine_core::common::constants::RejectPolicy;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    static SUBMITTED: AtomicUsize = AtomicUsize::new(0);
+    static STARTED: AtomicUsize = AtomicUsize::new(0);
+    static COMPLETED: AtomicUsize = AtomicUsize::new(0);
+    static FAILED: AtomicUsize = AtomicUsize::new(0);
+    static REJECTED: AtomicUsize = AtomicUsize::new(0);
+    static CANCELLED: AtomicUsize = AtomicUsize::new(0);
+    #[derive(Debug)]
+    struct Counter;
+    impl TaskListener for Counter {
+        fn on_submit(&self, _: &str) {
+            _ = SUBMITTED.fetch_add(1, Ordering::Release);
+        }
+        fn on_start(&self, _: &str, _: u64) {
+            _ = STARTED.fetch_add(1, Ordering::Release);
+        }
+        fn on_complete(&self, name: &str, _: u64, _: u64, result: Option<usize>) {
+            assert_eq!(("completed", Some(1)), (name, result));
+            _ = COMPLETED.fetch_add(1, Ordering::Release);
+        }
+        fn on_error(&self, name: &str, _: u64, _: u64, _: &str) {
+            assert_eq!("failed", name);
+            _ = FAILED.fetch_add(1, Ordering::Release);
+        }
+        fn on_reject(&self, name: &str) {
+            assert_eq!("rejected", name);
+            _ = REJECTED.fetch_add(1, Ordering::Release);
+        }
+        fn on_cancel(&self, name: &str, _: u64, run_time: u64) {
+            assert_eq!(("cancelled", 0), (name, run_time));
+            _ = CANCELLED.fetch_add(1, Ordering::Release);
+        }
+    }
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(1);
+    pool.set_queue_capacity(1);
+    pool.set_reject_policy(RejectPolicy::Abort);
+    pool.add_task_listener(Counter);
+    _ = pool.submit_task(Some(String::from("completed")), |_| Some(1), None, None)?;
+    assert!(pool
+        .submit_task(Some(String::from("rejected")), |_| Some(2), None, None)
+        .is_err());
+    pool.try_schedule_task()?;
+    _ = pool.submit_task(
+        Some(String::from("failed")),
+        |_| panic!("test panic, just ignore it"),
+        None,
+        None,
+    )?;
+    pool.try_schedule_task()?;
+    let cancelled = pool.submit_task(Some(String::from("cancelled")), |_| Some(3), None, None)?;
+    assert!(pool.cancel_task(cancelled));
+    pool.try_schedule_task()?;
+    assert!(pool
+        .try_get_task_result(cancelled)
+        .is_some_and(|result| is_cancelled(&result)));
+    assert_eq!(4, SUBMITTED.load(Ordering::Acquire));
+    assert_eq!(2, STARTED.load(Ordering::Acquire));
+    assert_eq!(1, COMPLETED.load(Ordering::Acquire));
+    assert_eq!(1, FAILED.load(Ordering::Acquire));
+    assert_eq!(1, REJECTED.load(Ordering::Acquire));
+    assert_eq!(1, CANCELLED.load(Ordering::Acquire));
+    pool.stop(Duration::from_secs(1))
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_keyed() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::CoroutinePool;
+    use open_coroutine_core::common::now;
+    use open_coroutine_core::scheduler::SchedulableSuspender;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    static ORDER: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+    fn record(i: usize, delay: u64) -> Option<usize> {
+        if let Some(suspender) = SchedulableSuspender::current() {
+            suspender.delay(Duration::from_millis(delay));
+        }
+        ORDER.lock().expect("lock failed").push(i);
+        Some(i)
+    }
+    let mut pool = CoroutinePool::default();
+    pool.set_max_size(4);
+    let mut tasks = Vec::new();
+    for (i, delay) in [(0, 30), (1, 10)] {
+        tasks.push(pool.submit_keyed_task("a", None, move |_| record(i, delay), None, None)?);
+    }
+    //不同key的任务不受影响
+    tasks.push(pool.submit_keyed_task("b", None, |_| record(2, 0), None, None)?);
+    let timeout_time = now() + 1_000_000_000;
+    while ORDER.lock().expect("lock failed").len() < 3 && now() < timeout_time {
+        _ = pool.try_timed_schedule_task(Duration::from_millis(10))?;
+    }
+    assert_eq!(vec![2, 0, 1], *ORDER.lock().expect("lock failed"));
+    for (i, task_id) in tasks.into_iter().enumerate() {
+        assert_eq!(Some(Ok(Some(i))), pool.try_get_task_result(task_id));
+    }
+    pool.stop(Duration::from_secs(1))
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_group() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::group::GroupMetrics;
+    use open_coroutine_core::co_pool::CoroutinePool;
+    use open_coroutine_core::common::now;
+    use open_coroutine_core::scheduler::SchedulableSuspender;
+    use std::io::ErrorKind;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    static ORDER: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+    fn record(i: usize, delay: u64) -> Option<usize> {
+        ORDER.lock().expect("lock failed").push(i);
+        if let Some(suspender) = SchedulableSuspender::current() {
+            suspender.delay(Duration::from_millis(delay));
+        }
+        ORDER.lock().expect("lock failed").push(i);
+        Some(i)
+    }
+    let mut pool = CoroutinePool::default();
+    pool.set_max_size(4);
+    assert_eq!(
+        ErrorKind::NotFound,
+        pool.submit_group_task("slow", None, |p| p, None, None)
+            .unwrap_err()
+            .kind()
+    );
+    pool.set_group_limit("slow", 1, 1);
+    let mut tasks = Vec::new();
+    for i in 0..2 {
+        tasks.push(pool.submit_group_task("slow", None, move |_| record(i, 30), None, None)?);
+    }
+    //分组内排队的任务已满
+    assert!(pool
+        .submit_group_task("slow", None, |p| p, None, None)
+        .is_err());
+    assert_eq!(
+        Some(GroupMetrics {
+            limit: 1,
+            capacity: 1,
+            in_flight: 1,
+            waiting: 1,
+        }),
+        pool.get_group_metrics("slow")
+    );
+    //不在分组内的任务不受影响
+    tasks.push(pool.submit_task(None, |_| record(2, 0), None, None)?);
+    let timeout_time = now() + 1_000_000_000;
+    while ORDER.lock().expect("lock failed").len() < 6 && now() < timeout_time {
+        _ = pool.try_timed_schedule_task(Duration::from_millis(10))?;
+    }
+    assert_eq!(vec![0, 2, 2, 0, 1, 1], *ORDER.lock().expect("lock failed"));
+    for (i, task_id) in tasks.into_iter().enumerate() {
+        assert_eq!(Some(Ok(Some(i))), pool.try_get_task_result(task_id));
+    }
+    assert_eq!(Some(0), pool.get_group_metrics("slow").map(|m| m.in_flight));
+    pool.stop(Duration::from_secs(1))
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_retry() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::retry::RetryPolicy;
+    use open_coroutine_core::co_pool::CoroutinePool;
+    use open_coroutine_core::common::now;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    let mut pool = CoroutinePool::default();
+    let mut policy = RetryPolicy::default();
+    _ = policy
+        .set_max_attempts(3)
+        .set_backoff(10_000_000)
+        .set_retryable(|error| error != "fatal");
+    let succeeded = pool.submit_task_with_retry(
+        None,
+        |_| match RUNS.fetch_add(1, Ordering::AcqRel) {
+            0 => Err("error"),
+            1 => panic!("test panic, just ignore it"),
+            _ => Ok(Some(1)),
+        },
+        None,
+        None,
+        policy,
+    )?;
+    let fatal = pool.submit_task_with_retry(None, |_| Err("fatal"), None, None, policy)?;
+    let start = now();
+    let timeout_time = start + 1_000_000_000;
+    let mut result = None;
+    while result.is_none() && now() < timeout_time {
+        _ = pool.try_timed_schedule_task(Duration::from_millis(1))?;
+        result = pool.try_get_task_attempts(succeeded);
+    }
+    let (result, attempts) = result.expect("no result");
+    assert_eq!(Ok(Some(1)), result);
+    assert_eq!(3, attempts.len());
+    assert_eq!(Err("error"), attempts[0].result);
+    assert!(attempts[1].result.is_err());
+    assert_eq!(Ok(Some(1)), attempts[2].result);
+    //重试前等待，而不是立即执行
+    assert!(attempts[1].start_time - attempts[0].start_time >= 8_000_000);
+    // the error is not retryable
+    let (result, attempts) = pool.try_get_task_attempts(fatal).expect("no result");
+    assert_eq!(Err("fatal"), result);
+    assert_eq!(1, attempts.len());
+    pool.stop(Duration::from_secs(1))
+}
+
+#[test]
+fn co_pool_retry_stolen() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::retry::RetryPolicy;
+    use open_coroutine_core::co_pool::CoroutinePool;
+    use open_coroutine_core::common::now;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    let owner = CoroutinePool::default();
+    let mut thief = CoroutinePool::default();
+    let mut policy = RetryPolicy::default();
+    _ = policy.set_max_attempts(2).set_backoff(0).set_jitter(0);
+    let task_id = owner.submit_task_with_retry(
+        None,
+        |_| match RUNS.fetch_add(1, Ordering::AcqRel) {
+            0 => Err("error"),
+            _ => Ok(Some(1)),
+        },
+        None,
+        None,
+        policy,
+    )?;
+    // the runs in the thief are recorded in the task, and saved in the owner
+    let timeout_time = now() + 1_000_000_000;
+    let mut result = None;
+    while result.is_none() && now() < timeout_time {
+        _ = thief.try_timed_schedule_task(Duration::from_millis(1))?;
+        result = owner.try_get_task_attempts(task_id);
+    }
+    let (result, attempts) = result.expect("no result");
+    assert_eq!(Ok(Some(1)), result);
+    assert_eq!(2, attempts.len());
+    assert_eq!(Err("error"), attempts[0].result);
+    assert_eq!(None, thief.try_get_task_attempts(task_id));
+    Ok(())
+}
@@ -153,6 +153,80 @@ fn coroutine_invalid_memory_reference() -> std::io::Result<()> {
     Ok(())
 }
 
+#[cfg(unix)]
+#[test]
+fn coroutine_errno() -> std::io::Result<()> {
+    use nix::errno::Errno;
+
+    let mut co1: Coroutine<(), (), ()> = co!(|suspender, ()| {
+        assert_eq!(-1, unsafe { libc::close(-1) });
+        assert_eq!(Errno::EBADF, Errno::last());
+        suspender.suspend();
+        assert_eq!(Errno::EBADF, Errno::last());
+    })?;
+    let mut co2: Coroutine<(), (), ()> = co!(|suspender, ()| {
+        assert_eq!(-1, unsafe {
+            libc::open("/not/exists\0".as_ptr().cast(), libc::O_RDONLY)
+        });
+        assert_eq!(Errno::ENOENT, Errno::last());
+        suspender.suspend();
+        assert_eq!(Errno::ENOENT, Errno::last());
+    })?;
+    Errno::set_raw(libc::EINTR);
+    assert_eq!(CoroutineState::Suspend((), 0), co1.resume()?);
+    assert_eq!(Errno::EINTR, Errno::last());
+    assert_eq!(CoroutineState::Suspend((), 0), co2.resume()?);
+    assert_eq!(Errno::EINTR, Errno::last());
+    assert_eq!(CoroutineState::Complete(()), co1.resume()?);
+    assert_eq!(CoroutineState::Complete(()), co2.resume()?);
+    assert_eq!(Errno::EINTR, Errno::last());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn thread_stack_overflow() -> std::io::Result<()> {
+    #[inline(never)]
+    fn recurse(depth: usize) -> usize {
+        let buf = std::hint::black_box([depth; 64]);
+        if buf[0] == usize::MAX {
+            return 0;
+        }
+        recurse(depth + 1) + buf[1]
+    }
+
+    if std::env::var("OPEN_COROUTINE_STACK_OVERFLOW").is_ok() {
+        _ = std::thread::Builder::new()
+            .name("overflow".to_string())
+            .stack_size(256 * 1024)
+            .spawn(|| {
+                //删除rust标准库注册的备用信号栈
+                unsafe {
+                    let mut disable: libc::stack_t = std::mem::zeroed();
+                    disable.ss_flags = libc::SS_DISABLE;
+                    _ = libc::sigaltstack(std::ptr::addr_of!(disable), std::ptr::null_mut());
+                }
+                //首次恢复协程时注册备用信号栈
+                let mut coroutine: Coroutine<(), (), ()> = co!(|_, ()| {}).unwrap();
+                assert_eq!(CoroutineState::Complete(()), coroutine.resume().unwrap());
+                println!("{}", recurse(0));
+            })?
+            .join();
+        unreachable!("the thread stack should overflow");
+    }
+    let output = std::process::Command::new(std::env::current_exe()?)
+        .args(["thread_stack_overflow", "--exact", "--nocapture"])
+        .env("OPEN_COROUTINE_STACK_OVERFLOW", "1")
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("thread 'overflow' has overflowed its stack"),
+        "unexpected stderr: {stderr}"
+    );
+    Ok(())
+}
+
 #[cfg(all(unix, feature = "preemptive"))]
 #[test]
 fn coroutine_preemptive() -> std::io::Result<()> {
@@ -233,3 +307,168 @@ fn coroutine_syscall_not_preemptive() -> std::io::Result<()> {
         ))
     }
 }
+
+#[cfg(all(unix, feature = "preemptive"))]
+#[test]
+fn coroutine_preemption_guard() -> std::io::Result<()> {
+    use open_coroutine_core::common::now;
+    use open_coroutine_core::preempt::PreemptionGuard;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static LEFT: AtomicBool = AtomicBool::new(false);
+    let pair = std::sync::Arc::new((std::sync::Mutex::new(true), std::sync::Condvar::new()));
+    let pair2 = pair.clone();
+    _ = std::thread::Builder::new()
+        .name("preemption_guard".to_string())
+        .spawn(move || {
+            let mut coroutine: Coroutine<(), (), ()> = co!(|_, ()| {
+                {
+                    let _guard = PreemptionGuard::new();
+                    let _nested = PreemptionGuard::new();
+                    // spin far beyond the time slice
+                    let timeout_time = now() + 100_000_000;
+                    while now() < timeout_time {}
+                    LEFT.store(true, Ordering::Release);
+                }
+                // should yield when leaving the critical section
+                unreachable!();
+            })?;
+            assert_eq!(CoroutineState::Suspend((), 0), coroutine.resume()?);
+            assert!(LEFT.load(Ordering::Acquire));
+            // should execute to here
+            let (lock, cvar) = &*pair2;
+            let mut pending = lock.lock().unwrap();
+            *pending = false;
+            cvar.notify_one();
+            Ok::<(), std::io::Error>(())
+        });
+    // wait for the thread to start up
+    let (lock, cvar) = &*pair;
+    let result = cvar
+        .wait_timeout_while(
+            lock.lock().unwrap(),
+            std::time::Duration::from_millis(3000),
+            |&mut pending| pending,
+        )
+        .unwrap();
+    if result.1.timed_out() {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "The coroutine should yield when leaving the critical section",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(all(unix, feature = "preemptive"))]
+#[test]
+fn coroutine_non_preemptible_region() -> std::io::Result<()> {
+    use open_coroutine_core::preempt::{register_non_preemptible, PreemptionGuard};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    const TIMES: u64 = 200_000_000;
+    static PROGRESS: AtomicU64 = AtomicU64::new(0);
+
+    #[inline(never)]
+    extern "C" fn spin(progress: *mut u64, times: u64) {
+        let mut i = 0;
+        while i < times {
+            i += 1;
+            unsafe { *progress = i };
+        }
+    }
+    let start = spin as *const () as usize;
+    register_non_preemptible(start, start + 256)?;
+
+    let pair = std::sync::Arc::new((std::sync::Mutex::new(true), std::sync::Condvar::new()));
+    let pair2 = pair.clone();
+    _ = std::thread::Builder::new()
+        .name("non_preemptible_region".to_string())
+        .spawn(move || {
+            let mut coroutine: Coroutine<(), (), ()> = co!(|_, ()| {
+                spin(PROGRESS.as_ptr(), TIMES);
+                // the deferred preemption should be served here
+                PreemptionGuard::safepoint();
+                unreachable!();
+            })?;
+            assert_eq!(CoroutineState::Suspend((), 0), coroutine.resume()?);
+            assert_eq!(TIMES, PROGRESS.load(Ordering::Acquire));
+            // should execute to here
+            let (lock, cvar) = &*pair2;
+            let mut pending = lock.lock().unwrap();
+            *pending = false;
+            cvar.notify_one();
+            Ok::<(), std::io::Error>(())
+        });
+    // wait for the thread to start up
+    let (lock, cvar) = &*pair;
+    let result = cvar
+        .wait_timeout_while(
+            lock.lock().unwrap(),
+            std::time::Duration::from_millis(5000),
+            |&mut pending| pending,
+        )
+        .unwrap();
+    if result.1.timed_out() {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "The coroutine should not be suspended in a non-preemptible region",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(all(unix, feature = "preemptive"))]
+#[test]
+fn coroutine_preemptive_errno() -> std::io::Result<()> {
+    use nix::errno::Errno;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static RESUMED: AtomicBool = AtomicBool::new(false);
+    let pair = std::sync::Arc::new((std::sync::Mutex::new(true), std::sync::Condvar::new()));
+    let pair2 = pair.clone();
+    _ = std::thread::Builder::new()
+        .name("preemptive_errno".to_string())
+        .spawn(move || {
+            let mut coroutine: Coroutine<(), (), ()> = co!(|_, ()| {
+                assert_eq!(-1, unsafe { libc::close(-1) });
+                while !RESUMED.load(Ordering::Acquire) {
+                    std::hint::spin_loop();
+                }
+                assert_eq!(Errno::EBADF, Errno::last());
+            })?;
+            assert_eq!(CoroutineState::Suspend((), 0), coroutine.resume()?);
+            //被抢占后，其他代码修改了errno
+            assert_eq!(-1, unsafe {
+                libc::open("/not/exists\0".as_ptr().cast(), libc::O_RDONLY)
+            });
+            RESUMED.store(true, Ordering::Release);
+            assert_eq!(CoroutineState::Complete(()), coroutine.resume()?);
+            assert_eq!(Errno::ENOENT, Errno::last());
+            // should execute to here
+            let (lock, cvar) = &*pair2;
+            let mut pending = lock.lock().unwrap();
+            *pending = false;
+            cvar.notify_one();
+            Ok::<(), std::io::Error>(())
+        });
+    // wait for the thread to start up
+    let (lock, cvar) = &*pair;
+    let result = cvar
+        .wait_timeout_while(
+            lock.lock().unwrap(),
+            std::time::Duration::from_millis(3000),
+            |&mut pending| pending,
+        )
+        .unwrap();
+    if result.1.timed_out() {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "The errno should be restored after the coroutine was preempted",
+        ))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+use open_coroutine_core::net::config::Config;
+use open_coroutine_core::net::EventLoops;
+
+#[test]
+fn net_task_graph() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::task::is_cancelled;
+    use open_coroutine_core::net::graph::TaskGraph;
+    use std::io::ErrorKind;
+    use std::time::Duration;
+    EventLoops::init(&Config::default());
+    let mut graph = TaskGraph::new();
+    let a = graph.add_node(None, |_| Some(1), None);
+    let b = graph.add_node(None, |_| Some(2), None);
+    let c = graph.add_node(None, |inputs| Some(inputs.iter().flatten().sum()), None);
+    let d = graph.add_node(None, |_| panic!("test panic, just ignore it"), None);
+    let e = graph.add_node(None, |_| Some(5), None);
+    _ = graph
+        .add_dependency(c, a)
+        .add_dependency(c, b)
+        .add_dependency(d, c)
+        .add_dependency(e, d);
+    let results = graph.submit()?.timeout_join(Duration::from_secs(3))?;
+    assert_eq!(Ok(Some(1)), results[0]);
+    assert_eq!(Ok(Some(2)), results[1]);
+    assert_eq!(Ok(Some(3)), results[2]);
+    assert!(results[3].is_err() && !is_cancelled(&results[3]));
+    // the downstream of the failed node is never submitted
+    assert!(is_cancelled(&results[4]));
+
+    let mut cyclic = TaskGraph::new();
+    let a = cyclic.add_node(None, |_| Some(1), None);
+    let b = cyclic.add_node(None, |_| Some(2), None);
+    _ = cyclic.add_dependency(a, b).add_dependency(b, a);
+    assert_eq!(ErrorKind::InvalidInput, cyclic.submit().unwrap_err().kind());
+    Ok(())
+}
+
+#[test]
+fn net_supervisor() {
+    use open_coroutine_core::common::now;
+    use open_coroutine_core::net::supervisor::{RestartStrategy, Supervisor, SupervisorListener};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    static FLAKY: AtomicUsize = AtomicUsize::new(0);
+    static STABLE: AtomicUsize = AtomicUsize::new(0);
+    static ESCALATED: AtomicUsize = AtomicUsize::new(0);
+    #[derive(Debug)]
+    struct Escalation;
+    impl SupervisorListener for Escalation {
+        fn on_escalate(&self, supervisor: &str, child: &str, _: &str) {
+            assert_eq!(("broken", "panic"), (supervisor, child));
+            _ = ESCALATED.fetch_add(1, Ordering::Release);
+        }
+    }
+    EventLoops::init(&Config::default());
+    let wait = |done: &dyn Fn() -> bool| {
+        let timeout_time = now() + 3_000_000_000;
+        while !done() && now() < timeout_time {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    };
+    // restart all the children when one of them fails
+    let mut supervisor = Supervisor::new(String::from("all"), RestartStrategy::OneForAll);
+    _ = supervisor
+        .add_child(
+            String::from("flaky"),
+            |_| {
+                if FLAKY.fetch_add(1, Ordering::AcqRel) < 2 {
+                    panic!("test panic, just ignore it");
+                }
+                None
+            },
+            None,
+        )
+        .add_child(
+            String::from("stable"),
+            |_| {
+                _ = STABLE.fetch_add(1, Ordering::AcqRel);
+                None
+            },
+            None,
+        );
+    let handle = supervisor.start();
+    wait(&|| FLAKY.load(Ordering::Acquire) >= 3 && STABLE.load(Ordering::Acquire) >= 1);
+    assert_eq!(3, FLAKY.load(Ordering::Acquire));
+    // the stable child may be stopped before it runs
+    assert!((1..=3).contains(&STABLE.load(Ordering::Acquire)));
+    assert_eq!(2, handle.restarted());
+    assert!(!handle.is_escalated());
+
+    // give up when the child fails too often
+    let mut supervisor = Supervisor::new(String::from("broken"), RestartStrategy::OneForOne);
+    _ = supervisor
+        .set_intensity(2, Duration::from_secs(10))
+        .add_child(
+            String::from("panic"),
+            |_| panic!("test panic, just ignore it"),
+            None,
+        )
+        .add_listener(Escalation);
+    let handle = supervisor.start();
+    wait(&|| handle.is_escalated());
+    assert!(handle.is_escalated());
+    assert_eq!(2, handle.restarted());
+    assert_eq!(1, ESCALATED.load(Ordering::Acquire));
+}
+
+#[test]
+fn net_group() -> std::io::Result<()> {
+    use open_coroutine_core::net::join::join_all;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static RUNNING: AtomicUsize = AtomicUsize::new(0);
+    static PEAK: AtomicUsize = AtomicUsize::new(0);
+    EventLoops::init(&Config::default());
+    EventLoops::set_group_limit("net", 2, 0);
+    let handles: Vec<_> = (0..6)
+        .map(|i| {
+            EventLoops::submit_task_in_group(
+                "net",
+                None,
+                move |_| {
+                    let running = RUNNING.fetch_add(1, Ordering::AcqRel) + 1;
+                    _ = PEAK.fetch_max(running, Ordering::AcqRel);
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                    _ = RUNNING.fetch_sub(1, Ordering::AcqRel);
+                    Some(i)
+                },
+                None,
+                None,
+            )
+        })
+        .collect();
+    let results = join_all(&handles.iter().collect::<Vec<_>>(), u64::MAX)?;
+    assert_eq!((0..6).map(|i| Ok(Some(i))).collect::<Vec<_>>(), results);
+    assert!(PEAK.load(Ordering::Acquire) <= 2);
+    let metrics = EventLoops::get_group_metrics("net").expect("no group");
+    assert_eq!((0, 0), (metrics.in_flight, metrics.waiting));
+    Ok(())
+}
+
+#[test]
+fn net_join_put_back() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::listener::TaskListener;
+    use open_coroutine_core::common::{get_timeout_time, now};
+    use open_coroutine_core::net::join::{join_all, race};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    static RELEASED: AtomicBool = AtomicBool::new(false);
+    static FAILED: AtomicBool = AtomicBool::new(false);
+    #[derive(Debug)]
+    struct Failure;
+    impl TaskListener for Failure {
+        fn on_error(&self, name: &str, _: u64, _: u64, _: &str) {
+            if "put-back-failed" == name {
+                FAILED.store(true, Ordering::Release);
+            }
+        }
+    }
+    EventLoops::init(&Config::default());
+    EventLoops::add_task_listener(Failure);
+    let fast = EventLoops::submit_task(None, |_| Some(1), None, None);
+    //挂起等待放行，不阻塞事件循环线程
+    let slow = EventLoops::submit_task(
+        None,
+        |_| {
+            while !RELEASED.load(Ordering::Acquire) {
+                EventLoops::wait_event(Some(Duration::from_millis(1))).expect("wait failed");
+            }
+            Some(2)
+        },
+        None,
+        None,
+    );
+    assert_eq!(Ok(Some(1)), fast.timeout_join(Duration::from_secs(3))?);
+    let fast = EventLoops::submit_task(None, |_| Some(1), None, None);
+    // the result of the fast task is put back when join_all times out
+    assert!(join_all(&[&fast, &slow], get_timeout_time(Duration::from_millis(10))).is_err());
+    assert_eq!(Ok(Some(1)), fast.timeout_join(Duration::from_secs(3))?);
+
+    // the failures collected before the winner are put back
+    let failed = EventLoops::submit_task(
+        Some(String::from("put-back-failed")),
+        |_| panic!("test panic, just ignore it"),
+        None,
+        None,
+    );
+    //失败的任务结束后才放行，race不会取消它
+    let timeout_time = get_timeout_time(Duration::from_secs(3));
+    while !FAILED.load(Ordering::Acquire) && now() < timeout_time {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    RELEASED.store(true, Ordering::Release);
+    let (index, result) = race(&[&failed, &slow], get_timeout_time(Duration::from_secs(3)))?;
+    assert_eq!((1, Ok(Some(2))), (index, result));
+    assert!(failed.timeout_join(Duration::from_secs(3))?.is_err());
+    Ok(())
+}
@@ -36,6 +36,23 @@ fn scheduler_backtrace() -> std::io::Result<()> {
     scheduler.try_schedule()
 }
 
+#[test]
+fn scheduler_detach() -> std::io::Result<()> {
+    let mut scheduler = Scheduler::default();
+    let before = scheduler.submit_co(|_, _| Some(1), None)?;
+    let after = scheduler.submit_co(|_, _| Some(2), None)?;
+    let kept = scheduler.submit_co(|_, _| Some(3), None)?;
+    //结束前分离，结果不会保存
+    scheduler.detach(before);
+    scheduler.try_schedule()?;
+    //结束后分离，保存的结果被丢弃
+    scheduler.detach(after);
+    assert_eq!(None, scheduler.try_get_co_result(before));
+    assert_eq!(None, scheduler.try_get_co_result(after));
+    assert_eq!(Some(Ok(Some(3))), scheduler.try_get_co_result(kept));
+    Ok(())
+}
+
 #[test]
 fn scheduler_suspend() -> std::io::Result<()> {
     let mut scheduler = Scheduler::default();
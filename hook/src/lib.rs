@@ -48,11 +48,18 @@
 //! see `https://github.com/acl-dev/open-coroutine`
 
 use once_cell::sync::OnceCell;
-use open_coroutine_core::co_pool::task::UserTaskFunc;
+use open_coroutine_core::co_pool::listener::{UserTaskListener, UserTaskListenerFunc};
+use open_coroutine_core::co_pool::task::{
+    cancelled_error, is_cancelled, TaskPriority, UserContinuationFunc, UserParamDrop, UserTaskFunc,
+    TASK_CANCELLED,
+};
+use open_coroutine_core::common::get_timeout_time;
 use open_coroutine_core::net::config::Config;
-use open_coroutine_core::net::join::JoinHandle;
+use open_coroutine_core::net::join::{join_all, join_any, race, JoinHandle, JOIN_CANCELLED};
 use open_coroutine_core::net::{EventLoops, UserFunc};
+use open_coroutine_core::preempt::PreemptionGuard;
 use open_coroutine_core::scheduler::SchedulableCoroutine;
+use std::cell::RefCell;
 use std::ffi::{c_int, c_longlong, c_uint};
 use std::time::Duration;
 
@@ -93,10 +100,163 @@ pub extern "C" fn open_coroutine_stop(secs: c_uint) -> c_int {
     -1
 }
 
+//任务未执行时(如被取消)释放参数
+struct TaskParam(usize, UserParamDrop);
+
+impl TaskParam {
+    fn into_inner(self) -> usize {
+        let param = self.0;
+        std::mem::forget(self);
+        param
+    }
+}
+
+impl Drop for TaskParam {
+    fn drop(&mut self) {
+        (self.1)(self.0);
+    }
+}
+
 ///创建任务
 #[no_mangle]
 pub extern "C" fn task_crate(f: UserTaskFunc, param: usize) -> JoinHandle {
-    EventLoops::submit_task(None, move |p| Some(f(p.unwrap_or(0))), Some(param))
+    EventLoops::submit_task(None, move |p| Some(f(p.unwrap_or(0))), Some(param), None)
+}
+
+///创建任务，`priority`取值0(高)、1(普通)、2(低)，优先级高的任务先执行，
+///任务未执行或`priority`非法时用`drop_param`释放参数
+#[no_mangle]
+pub extern "C" fn task_crate_with_priority(
+    f: UserTaskFunc,
+    param: usize,
+    drop_param: UserParamDrop,
+    priority: c_int,
+) -> JoinHandle {
+    let param = TaskParam(param, drop_param);
+    let Ok(priority) = TaskPriority::try_from(priority) else {
+        return EventLoops::invalid_handle();
+    };
+    EventLoops::submit_task(
+        None,
+        move |_| Some(f(param.into_inner())),
+        None,
+        Some(priority),
+    )
+}
+
+///创建按key串行执行的任务，`key`相同的任务在同一个事件循环上按提交顺序执行，
+///`priority`的取值同`task_crate_with_priority`
+#[no_mangle]
+pub extern "C" fn task_crate_keyed(
+    key: u64,
+    f: UserTaskFunc,
+    param: usize,
+    drop_param: UserParamDrop,
+    priority: c_int,
+) -> JoinHandle {
+    let param = TaskParam(param, drop_param);
+    let Ok(priority) = TaskPriority::try_from(priority) else {
+        return EventLoops::invalid_handle();
+    };
+    EventLoops::submit_task_keyed(
+        &key,
+        None,
+        move |_| Some(f(param.into_inner())),
+        None,
+        Some(priority),
+    )
+}
+
+///创建不关心结果的任务，任务未执行时用`drop_param`释放参数，
+///`priority`的取值同`task_crate_with_priority`，非法时返回-1
+#[no_mangle]
+pub extern "C" fn task_spawn_detached(
+    f: UserTaskFunc,
+    param: usize,
+    drop_param: UserParamDrop,
+    priority: c_int,
+) -> c_int {
+    let param = TaskParam(param, drop_param);
+    let Ok(priority) = TaskPriority::try_from(priority) else {
+        return -1;
+    };
+    match EventLoops::spawn_detached(
+        None,
+        move |_| Some(f(param.into_inner())),
+        None,
+        Some(priority),
+    ) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+///不再等待任务完成，任务的结果不会被保存
+#[no_mangle]
+pub extern "C" fn task_detach(handle: JoinHandle) {
+    handle.detach();
+}
+
+//前置任务的结果，同`task_join`的返回值
+fn result_code(result: &Result<Option<usize>, &str>) -> c_longlong {
+    match result {
+        Ok(Some(ptr)) => c_longlong::try_from(*ptr).expect("overflow"),
+        Ok(None) => 0,
+        Err(_) if is_cancelled(result) => JOIN_CANCELLED,
+        Err(_) => -1,
+    }
+}
+
+fn continuation(
+    f: UserContinuationFunc,
+    param: usize,
+    drop_param: UserParamDrop,
+) -> impl FnOnce(Result<Option<usize>, &'static str>) -> Result<Option<usize>, &'static str> {
+    let param = TaskParam(param, drop_param);
+    move |result| match f(param.into_inner(), result_code(&result)) {
+        JOIN_CANCELLED => Err(TASK_CANCELLED),
+        code if code < 0 => Err("The continuation failed !"),
+        code => Ok(Some(usize::try_from(code).expect("overflow"))),
+    }
+}
+
+///任务完成后由执行它的event-loop执行后续任务，未执行时用`drop_param`释放参数
+#[no_mangle]
+pub extern "C" fn task_then(
+    handle: JoinHandle,
+    f: UserContinuationFunc,
+    param: usize,
+    drop_param: UserParamDrop,
+) -> JoinHandle {
+    handle.then(continuation(f, param, drop_param))
+}
+
+///任务完成后由执行它的event-loop执行回调，不保存回调的结果
+#[no_mangle]
+pub extern "C" fn task_on_complete(
+    handle: JoinHandle,
+    f: UserContinuationFunc,
+    param: usize,
+    drop_param: UserParamDrop,
+) {
+    let continuation = continuation(f, param, drop_param);
+    handle.on_complete(move |result| _ = continuation(result));
+}
+
+///添加任务监听器，所有event-loop上任务的生命周期事件都通过`f`回调，`param`原样传给`f`
+#[no_mangle]
+pub extern "C" fn task_listen(f: UserTaskListenerFunc, param: usize) {
+    EventLoops::add_task_listener(UserTaskListener::new(f, param));
+}
+
+///取消任务
+#[no_mangle]
+pub extern "C" fn task_cancel(handle: &JoinHandle) -> c_int {
+    if handle.cancel() {
+        0
+    } else {
+        -1
+    }
 }
 
 ///等待任务完成
@@ -110,6 +270,7 @@ pub extern "C" fn task_join(handle: &JoinHandle) -> c_longlong {
             },
             Err(_) => -1,
         },
+        Err(e) if cancelled_error().raw_os_error() == e.raw_os_error() => JOIN_CANCELLED,
         Err(_) => -1,
     }
 }
@@ -125,6 +286,73 @@ pub extern "C" fn task_timeout_join(handle: &JoinHandle, ns_time: u64) -> c_long
             },
             Err(_) => -1,
         },
+        Err(e) if cancelled_error().raw_os_error() == e.raw_os_error() => JOIN_CANCELLED,
+        Err(_) => -1,
+    }
+}
+
+///等待所有任务完成，结果按顺序写入`codes`，同`task_join`的返回值
+///
+/// # Safety
+/// `handles` must point to `len` handles, and `codes` must have room for `len` results.
+#[no_mangle]
+pub unsafe extern "C" fn task_join_all(
+    handles: *const &JoinHandle,
+    len: usize,
+    ns_time: u64,
+    codes: *mut c_longlong,
+) -> c_int {
+    let handles = std::slice::from_raw_parts(handles, len);
+    match join_all(handles, get_timeout_time(Duration::from_nanos(ns_time))) {
+        Ok(results) => {
+            for (i, result) in results.iter().enumerate() {
+                *codes.add(i) = result_code(result);
+            }
+            0
+        }
+        Err(_) => -1,
+    }
+}
+
+///等待任意一个任务完成，返回它的下标，结果写入`code`
+///
+/// # Safety
+/// `handles` must point to `len` handles, and `code` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn task_join_any(
+    handles: *const &JoinHandle,
+    len: usize,
+    ns_time: u64,
+    code: *mut c_longlong,
+) -> c_longlong {
+    let handles = std::slice::from_raw_parts(handles, len);
+    match join_any(handles, get_timeout_time(Duration::from_nanos(ns_time))) {
+        Ok((index, result)) => {
+            *code = result_code(&result);
+            c_longlong::try_from(index).expect("overflow")
+        }
+        Err(_) => -1,
+    }
+}
+
+///等待第一个成功的任务并取消其余任务，返回它的下标，结果写入`code`；
+///全部失败时返回最后一个失败的任务
+///
+/// # Safety
+/// `handles` must point to `len` handles, and `code` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn task_race(
+    handles: *const &JoinHandle,
+    len: usize,
+    ns_time: u64,
+    code: *mut c_longlong,
+) -> c_longlong {
+    let handles = std::slice::from_raw_parts(handles, len);
+    match race(handles, get_timeout_time(Duration::from_nanos(ns_time))) {
+        Ok((index, result)) => {
+            *code = result_code(&result);
+            c_longlong::try_from(index).expect("overflow")
+        }
         Err(_) => -1,
     }
 }
@@ -152,3 +380,29 @@ pub extern "C" fn maybe_grow_stack(
     }
     -1
 }
+
+thread_local! {
+    static PREEMPTION_GUARDS: RefCell<Vec<PreemptionGuard>> = const { RefCell::new(Vec::new()) };
+}
+
+///进入临界区，在退出前当前协程不会被抢占
+#[no_mangle]
+pub extern "C" fn preemption_guard_enter() {
+    PREEMPTION_GUARDS.with(|guards| guards.borrow_mut().push(PreemptionGuard::new()));
+}
+
+///退出临界区
+#[no_mangle]
+pub extern "C" fn preemption_guard_exit() {
+    let guard = PREEMPTION_GUARDS.with(|guards| guards.borrow_mut().pop());
+    drop(guard);
+}
+
+///注册不可抢占的代码区域`[start, end)`
+#[no_mangle]
+pub extern "C" fn register_non_preemptible(start: usize, end: usize) -> c_int {
+    if open_coroutine_core::preempt::register_non_preemptible(start, end).is_ok() {
+        return 0;
+    }
+    -1
+}
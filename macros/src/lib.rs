@@ -64,6 +64,7 @@ pub fn main(args: TokenStream, func: TokenStream) -> TokenStream {
     let mut max_size = usize::MAX;
     let mut keep_alive_time = u64::MAX;
     let mut hook = true;
+    let mut blocked_time = u64::MAX;
     if !args.is_empty() {
         let tea_parser = syn::meta::parser(|meta| {
             if meta.path.is_ident("event_loop_size") {
@@ -78,6 +79,8 @@ pub fn main(args: TokenStream, func: TokenStream) -> TokenStream {
                 keep_alive_time = meta.value()?.parse::<LitInt>()?.base10_parse()?;
             } else if meta.path.is_ident("hook") {
                 hook = meta.value()?.parse::<LitBool>()?.value();
+            } else if meta.path.is_ident("blocked_time") {
+                blocked_time = meta.value()?.parse::<LitInt>()?.base10_parse()?;
             }
             Ok(())
         });
@@ -116,6 +119,9 @@ pub fn main(args: TokenStream, func: TokenStream) -> TokenStream {
             if #hook != true {
                 open_coroutine_config.set_hook(#hook);
             }
+            if #blocked_time != u64::MAX {
+                open_coroutine_config.set_blocked_time(#blocked_time);
+            }
             open_coroutine::init(open_coroutine_config);
             let _open_coroutine_result = #func_block;
             open_coroutine::shutdown();
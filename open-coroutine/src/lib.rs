@@ -47,15 +47,23 @@
 )]
 //! see `https://github.com/acl-dev/open-coroutine`
 
-use open_coroutine_core::co_pool::task::UserTaskFunc;
+pub use open_coroutine_core::co_pool::listener::TaskListener;
+use open_coroutine_core::co_pool::listener::{TaskEvent, UserTaskListenerFunc};
+pub use open_coroutine_core::co_pool::task::TaskPriority;
+use open_coroutine_core::co_pool::task::{
+    cancelled_error, ResultSlot, UserContinuationFunc, UserParamDrop, UserTaskFunc,
+};
 use open_coroutine_core::common::constants::SLICE;
+use open_coroutine_core::common::hash;
 pub use open_coroutine_core::net::config::Config;
+use open_coroutine_core::net::join::JOIN_CANCELLED;
 use open_coroutine_core::net::UserFunc;
 pub use open_coroutine_macros::*;
-use std::cmp::Ordering;
 use std::ffi::{c_int, c_longlong, c_uint, c_void};
+use std::hash::Hash;
 use std::io::{Error, ErrorKind};
 use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
 use std::net::{TcpStream, ToSocketAddrs};
 use std::ops::Deref;
 use std::time::Duration;
@@ -71,11 +79,58 @@ extern "C" {
         f: UserFunc,
         param: usize,
     ) -> c_longlong;
+
+    fn preemption_guard_enter();
+
+    fn preemption_guard_exit();
+
+    #[link_name = "register_non_preemptible"]
+    fn register_non_preemptible_region(start: usize, end: usize) -> c_int;
 }
 
 #[allow(improper_ctypes)]
 extern "C" {
-    fn task_crate(f: UserTaskFunc, param: usize) -> open_coroutine_core::net::join::JoinHandle;
+    fn task_crate_with_priority(
+        f: UserTaskFunc,
+        param: usize,
+        drop_param: UserParamDrop,
+        priority: c_int,
+    ) -> open_coroutine_core::net::join::JoinHandle;
+
+    fn task_crate_keyed(
+        key: u64,
+        f: UserTaskFunc,
+        param: usize,
+        drop_param: UserParamDrop,
+        priority: c_int,
+    ) -> open_coroutine_core::net::join::JoinHandle;
+
+    fn task_then(
+        handle: open_coroutine_core::net::join::JoinHandle,
+        f: UserContinuationFunc,
+        param: usize,
+        drop_param: UserParamDrop,
+    ) -> open_coroutine_core::net::join::JoinHandle;
+
+    fn task_on_complete(
+        handle: open_coroutine_core::net::join::JoinHandle,
+        f: UserContinuationFunc,
+        param: usize,
+        drop_param: UserParamDrop,
+    );
+
+    fn task_spawn_detached(
+        f: UserTaskFunc,
+        param: usize,
+        drop_param: UserParamDrop,
+        priority: c_int,
+    ) -> c_int;
+
+    fn task_detach(handle: open_coroutine_core::net::join::JoinHandle);
+
+    fn task_listen(f: UserTaskListenerFunc, param: usize);
+
+    fn task_cancel(handle: &open_coroutine_core::net::join::JoinHandle) -> c_int;
 
     fn task_join(handle: &open_coroutine_core::net::join::JoinHandle) -> c_longlong;
 
@@ -83,6 +138,27 @@ extern "C" {
         handle: &open_coroutine_core::net::join::JoinHandle,
         ns_time: u64,
     ) -> c_longlong;
+
+    fn task_join_all(
+        handles: *const &open_coroutine_core::net::join::JoinHandle,
+        len: usize,
+        ns_time: u64,
+        codes: *mut c_longlong,
+    ) -> c_int;
+
+    fn task_join_any(
+        handles: *const &open_coroutine_core::net::join::JoinHandle,
+        len: usize,
+        ns_time: u64,
+        code: *mut c_longlong,
+    ) -> c_longlong;
+
+    fn task_race(
+        handles: *const &open_coroutine_core::net::join::JoinHandle,
+        len: usize,
+        ns_time: u64,
+        code: *mut c_longlong,
+    ) -> c_longlong;
 }
 
 /// Init the open-coroutine.
@@ -99,74 +175,382 @@ pub fn shutdown() {
     unsafe { _ = open_coroutine_stop(30) };
 }
 
-/// Create a task.
+/// Add a listener to observe the lifecycle of the tasks in all event-loops, call it
+/// after [`init`].
+pub fn add_task_listener<L: TaskListener + 'static>(listener: L) {
+    extern "C" fn on_event<L: TaskListener + 'static>(param: usize, event: &TaskEvent) {
+        let listener = unsafe { &*(param as *const L) };
+        unsafe { event.dispatch(listener) };
+    }
+    //监听器随event-loop一直存在
+    let listener: &'static L = Box::leak(Box::new(listener));
+    unsafe { task_listen(on_event::<L>, std::ptr::from_ref(listener) as usize) };
+}
+
+/// Create a task, the priority is optional.
 #[macro_export]
 macro_rules! task {
     ( $f: expr , $param:expr $(,)? ) => {
         $crate::task($f, $param)
     };
+    ( $f: expr , $param:expr , $priority:expr $(,)? ) => {
+        $crate::task_with_priority($f, $param, $priority)
+    };
 }
 
 /// Create a task.
 pub fn task<P: 'static, R: 'static, F: FnOnce(P) -> R>(f: F, param: P) -> JoinHandle<R> {
+    task_with_priority(f, param, TaskPriority::default())
+}
+
+/// Create a task with the given priority, the task with higher priority is executed first.
+pub fn task_with_priority<P: 'static, R: 'static, F: FnOnce(P) -> R>(
+    f: F,
+    param: P,
+    priority: TaskPriority,
+) -> JoinHandle<R> {
+    submit(f, param, |f, param, drop_param| unsafe {
+        task_crate_with_priority(f, param, drop_param, priority as c_int)
+    })
+}
+
+/// Create a task which runs strictly after the previous tasks with the same `key`,
+/// while the tasks with different keys run concurrently.
+pub fn task_keyed<K: Hash + ?Sized, P: 'static, R: 'static, F: FnOnce(P) -> R>(
+    key: &K,
+    f: F,
+    param: P,
+) -> JoinHandle<R> {
+    let key = hash(key);
+    submit(f, param, |f, param, drop_param| unsafe {
+        task_crate_keyed(key, f, param, drop_param, TaskPriority::default() as c_int)
+    })
+}
+
+fn submit<P: 'static, R: 'static, F: FnOnce(P) -> R>(
+    f: F,
+    param: P,
+    submit: impl FnOnce(
+        UserTaskFunc,
+        usize,
+        UserParamDrop,
+    ) -> open_coroutine_core::net::join::JoinHandle,
+) -> JoinHandle<R> {
     extern "C" fn task_main<P: 'static, R: 'static, F: FnOnce(P) -> R>(input: usize) -> usize {
-        unsafe {
-            let ptr = &mut *((input as *mut c_void).cast::<(F, P)>());
-            let data = std::ptr::read_unaligned(ptr);
-            let result: &'static mut R = Box::leak(Box::new((data.0)(data.1)));
-            std::ptr::from_mut::<R>(result).cast::<c_void>() as usize
-        }
+        let (f, param, slot) = *unsafe {
+            Box::from_raw((input as *mut c_void).cast::<(F, P, ResultSlot<std::io::Result<R>>)>())
+        };
+        slot.put(Ok(f(param)));
+        0
     }
-    let inner = Box::leak(Box::new((f, param)));
-    unsafe {
-        task_crate(
+    extern "C" fn task_drop<P: 'static, R: 'static, F: FnOnce(P) -> R>(input: usize) {
+        drop(unsafe {
+            Box::from_raw((input as *mut c_void).cast::<(F, P, ResultSlot<std::io::Result<R>>)>())
+        });
+    }
+    let slot = ResultSlot::default();
+    let inner = Box::into_raw(Box::new((f, param, slot.clone())));
+    let handle = submit(
+        task_main::<P, R, F>,
+        inner.cast::<c_void>() as usize,
+        task_drop::<P, R, F>,
+    );
+    JoinHandle::new(handle, slot)
+}
+
+/// Create a task which nobody can join, so its result is never saved.
+pub fn spawn_detached<P: 'static, R: 'static, F: FnOnce(P) -> R>(
+    f: F,
+    param: P,
+) -> std::io::Result<()> {
+    extern "C" fn task_main<P: 'static, R: 'static, F: FnOnce(P) -> R>(input: usize) -> usize {
+        let (f, param) = *unsafe { Box::from_raw((input as *mut c_void).cast::<(F, P)>()) };
+        drop(f(param));
+        0
+    }
+    extern "C" fn task_drop<P: 'static, R: 'static, F: FnOnce(P) -> R>(input: usize) {
+        drop(unsafe { Box::from_raw((input as *mut c_void).cast::<(F, P)>()) });
+    }
+    let inner = Box::into_raw(Box::new((f, param)));
+    if 0 != unsafe {
+        task_spawn_detached(
             task_main::<P, R, F>,
-            std::ptr::from_mut::<(F, P)>(inner).cast::<c_void>() as usize,
+            inner.cast::<c_void>() as usize,
+            task_drop::<P, R, F>,
+            TaskPriority::default() as c_int,
         )
-        .into()
+    } {
+        return Err(Error::new(ErrorKind::Other, "spawn detached task failed"));
     }
+    Ok(())
 }
 
+/// The handle of a task, it owns the result of the task, so the result is dropped
+/// with the handle whether or not it's joined.
+///
+/// The event-loop only keeps whether the task succeeded, failed or was cancelled,
+/// until the task is joined. Dropping the handle without joining detaches the task,
+/// then nothing of the task is kept by the event-loop.
+///
+/// The handle converted from the untyped handle has no place for the result, so
+/// joining it only tells whether the task succeeded, and the result is `None`.
 #[allow(missing_docs)]
 #[repr(C)]
 #[derive(Debug)]
-pub struct JoinHandle<R>(open_coroutine_core::net::join::JoinHandle, PhantomData<R>);
+pub struct JoinHandle<R>(
+    ManuallyDrop<open_coroutine_core::net::join::JoinHandle>,
+    ResultSlot<std::io::Result<R>>,
+);
 
-#[allow(missing_docs)]
 impl<R> JoinHandle<R> {
-    #[allow(clippy::cast_possible_truncation)]
-    pub fn timeout_join(&self, dur: Duration) -> std::io::Result<Option<R>> {
-        unsafe {
-            let ptr = task_timeout_join(self, dur.as_nanos() as u64);
-            match ptr.cmp(&0) {
-                Ordering::Less => Err(Error::new(ErrorKind::Other, "timeout join failed")),
-                Ordering::Equal => Ok(None),
-                Ordering::Greater => Ok(Some(std::ptr::read_unaligned(ptr as *mut R))),
+    fn new(
+        handle: open_coroutine_core::net::join::JoinHandle,
+        slot: ResultSlot<std::io::Result<R>>,
+    ) -> Self {
+        JoinHandle(ManuallyDrop::new(handle), slot)
+    }
+
+    //取出内部的句柄，不再自动分离任务
+    fn into_inner(
+        self,
+    ) -> (
+        open_coroutine_core::net::join::JoinHandle,
+        ResultSlot<std::io::Result<R>>,
+    ) {
+        let mut this = ManuallyDrop::new(self);
+        (
+            unsafe { ManuallyDrop::take(&mut this.0) },
+            std::mem::take(&mut this.1),
+        )
+    }
+}
+
+impl<R> Drop for JoinHandle<R> {
+    fn drop(&mut self) {
+        unsafe { task_detach(ManuallyDrop::take(&mut self.0)) };
+    }
+}
+
+//根据join的返回值取出任务结果
+fn take_result<R>(
+    slot: &ResultSlot<std::io::Result<R>>,
+    code: c_longlong,
+    message: &str,
+) -> std::io::Result<Option<R>> {
+    match code {
+        JOIN_CANCELLED => Err(cancelled_error()),
+        code if code < 0 => Err(slot
+            .take()
+            .and_then(Result::err)
+            .unwrap_or_else(|| Error::new(ErrorKind::Other, message))),
+        _ => slot.take().transpose(),
+    }
+}
+
+//把后续任务`f`转换为C兼容的形式，返回函数、参数、释放参数的函数以及`f`结果的存放处
+fn continuation<R: 'static, U: 'static, F>(
+    previous: ResultSlot<std::io::Result<R>>,
+    f: F,
+) -> (
+    UserContinuationFunc,
+    usize,
+    UserParamDrop,
+    ResultSlot<std::io::Result<U>>,
+)
+where
+    F: FnOnce(std::io::Result<Option<R>>) -> std::io::Result<U> + 'static,
+{
+    type Data<R, U, F> = (
+        F,
+        ResultSlot<std::io::Result<R>>,
+        ResultSlot<std::io::Result<U>>,
+    );
+    extern "C" fn continuation_main<R: 'static, U: 'static, F>(
+        input: usize,
+        code: c_longlong,
+    ) -> c_longlong
+    where
+        F: FnOnce(std::io::Result<Option<R>>) -> std::io::Result<U> + 'static,
+    {
+        let (f, previous, slot) =
+            *unsafe { Box::from_raw((input as *mut c_void).cast::<Data<R, U, F>>()) };
+        match f(take_result(&previous, code, "The previous task failed !")) {
+            Ok(result) => {
+                slot.put(Ok(result));
+                0
+            }
+            Err(e) if cancelled_error().raw_os_error() == e.raw_os_error() => JOIN_CANCELLED,
+            Err(e) => {
+                slot.put(Err(e));
+                -1
             }
         }
     }
+    extern "C" fn continuation_drop<R: 'static, U: 'static, F>(input: usize)
+    where
+        F: FnOnce(std::io::Result<Option<R>>) -> std::io::Result<U> + 'static,
+    {
+        drop(unsafe { Box::from_raw((input as *mut c_void).cast::<Data<R, U, F>>()) });
+    }
+    let slot = ResultSlot::default();
+    let inner = Box::into_raw(Box::new((f, previous, slot.clone())));
+    (
+        continuation_main::<R, U, F>,
+        inner.cast::<c_void>() as usize,
+        continuation_drop::<R, U, F>,
+        slot,
+    )
+}
+
+#[allow(missing_docs)]
+impl<R: 'static> JoinHandle<R> {
+    /// Cancel the task, returns `false` if the task has finished or been cancelled.
+    #[must_use]
+    pub fn cancel(&self) -> bool {
+        unsafe { 0 == task_cancel(self) }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn timeout_join(&self, dur: Duration) -> std::io::Result<Option<R>> {
+        let code = unsafe { task_timeout_join(self, dur.as_nanos() as u64) };
+        take_result(&self.1, code, "timeout join failed")
+    }
 
     pub fn join(self) -> std::io::Result<Option<R>> {
-        unsafe {
-            let ptr = task_join(&self);
-            match ptr.cmp(&0) {
-                Ordering::Less => Err(Error::new(ErrorKind::Other, "join failed")),
-                Ordering::Equal => Ok(None),
-                Ordering::Greater => Ok(Some(std::ptr::read_unaligned(ptr as *mut R))),
-            }
-        }
+        let code = unsafe { task_join(&self) };
+        take_result(&self.1, code, "join failed")
+    }
+
+    /// Run `f` as a new task once this task completes, `f` receives the result, and the
+    /// returned handle joins the result of `f`.
+    pub fn then<U: 'static>(
+        self,
+        f: impl FnOnce(std::io::Result<Option<R>>) -> U + 'static,
+    ) -> JoinHandle<U> {
+        let (handle, previous) = self.into_inner();
+        let (f, param, drop_param, slot) = continuation(previous, move |result| Ok(f(result)));
+        JoinHandle::new(unsafe { task_then(handle, f, param, drop_param) }, slot)
+    }
+
+    /// Like [`JoinHandle::then`], but `f` is only called if this task succeeds,
+    /// otherwise the error is passed on.
+    pub fn map<U: 'static>(self, f: impl FnOnce(Option<R>) -> U + 'static) -> JoinHandle<U> {
+        self.and_then(move |result| Ok(f(result)))
+    }
+
+    /// Like [`JoinHandle::map`], but `f` can fail.
+    pub fn and_then<U: 'static>(
+        self,
+        f: impl FnOnce(Option<R>) -> std::io::Result<U> + 'static,
+    ) -> JoinHandle<U> {
+        let (handle, previous) = self.into_inner();
+        let (f, param, drop_param, slot) = continuation(previous, move |result| result.and_then(f));
+        JoinHandle::new(unsafe { task_then(handle, f, param, drop_param) }, slot)
+    }
+
+    /// Run `callback` as a new task once this task completes,
+    /// and the result of this task is consumed by `callback`.
+    pub fn on_complete(self, callback: impl FnOnce(std::io::Result<Option<R>>) + 'static) {
+        let (handle, previous) = self.into_inner();
+        let (f, param, drop_param, _) = continuation(previous, move |result| {
+            callback(result);
+            Ok(())
+        });
+        unsafe { task_on_complete(handle, f, param, drop_param) };
     }
 }
 
+/// Wait for all the tasks to complete, the results are in the same order as `handles`.
+pub fn join_all<R>(handles: &[JoinHandle<R>]) -> std::io::Result<Vec<std::io::Result<Option<R>>>> {
+    timeout_join_all(handles, Duration::MAX)
+}
+
+/// Wait for all the tasks to complete for up to `dur`, the results are in the same
+/// order as `handles`.
+pub fn timeout_join_all<R>(
+    handles: &[JoinHandle<R>],
+    dur: Duration,
+) -> std::io::Result<Vec<std::io::Result<Option<R>>>> {
+    let raw: Vec<&open_coroutine_core::net::join::JoinHandle> =
+        handles.iter().map(|handle| &**handle).collect();
+    let mut codes = vec![0; raw.len()];
+    if 0 != unsafe { task_join_all(raw.as_ptr(), raw.len(), nanos(dur), codes.as_mut_ptr()) } {
+        return Err(Error::new(ErrorKind::Other, "join all failed"));
+    }
+    Ok(handles
+        .iter()
+        .zip(codes)
+        .map(|(handle, code)| take_result(&handle.1, code, "join failed"))
+        .collect())
+}
+
+/// Wait for the first task to complete, returns its index in `handles` and its result,
+/// the other tasks can still be joined.
+pub fn join_any<R>(
+    handles: &[JoinHandle<R>],
+) -> std::io::Result<(usize, std::io::Result<Option<R>>)> {
+    timeout_join_any(handles, Duration::MAX)
+}
+
+/// Wait for the first task to complete for up to `dur`, see [`join_any`].
+pub fn timeout_join_any<R>(
+    handles: &[JoinHandle<R>],
+    dur: Duration,
+) -> std::io::Result<(usize, std::io::Result<Option<R>>)> {
+    first(handles, dur, task_join_any, "join any failed")
+        .map(|(index, code)| (index, take_result(&handles[index].1, code, "join failed")))
+}
+
+/// Wait for the first task to succeed and cancel the rest, returns its index in
+/// `handles` and its result. If all of them fail, the last failure is returned.
+pub fn race<R>(handles: &[JoinHandle<R>]) -> std::io::Result<(usize, Option<R>)> {
+    timeout_race(handles, Duration::MAX)
+}
+
+/// Wait for the first task to succeed for up to `dur`, see [`race`].
+pub fn timeout_race<R>(
+    handles: &[JoinHandle<R>],
+    dur: Duration,
+) -> std::io::Result<(usize, Option<R>)> {
+    let (index, code) = first(handles, dur, task_race, "race failed")?;
+    take_result(&handles[index].1, code, "race failed").map(|result| (index, result))
+}
+
+//`task_join_any`和`task_race`的公共部分，返回下标和结果
+fn first<R>(
+    handles: &[JoinHandle<R>],
+    dur: Duration,
+    join: unsafe extern "C" fn(
+        *const &open_coroutine_core::net::join::JoinHandle,
+        usize,
+        u64,
+        *mut c_longlong,
+    ) -> c_longlong,
+    message: &str,
+) -> std::io::Result<(usize, c_longlong)> {
+    let raw: Vec<&open_coroutine_core::net::join::JoinHandle> =
+        handles.iter().map(|handle| &**handle).collect();
+    let mut code = 0;
+    let index = unsafe { join(raw.as_ptr(), raw.len(), nanos(dur), &mut code) };
+    usize::try_from(index)
+        .map(|index| (index, code))
+        .map_err(|_| Error::new(ErrorKind::Other, message))
+}
+
+fn nanos(dur: Duration) -> u64 {
+    u64::try_from(dur.as_nanos()).unwrap_or(u64::MAX)
+}
+
 impl<R> From<open_coroutine_core::net::join::JoinHandle> for JoinHandle<R> {
     fn from(val: open_coroutine_core::net::join::JoinHandle) -> Self {
-        Self(val, PhantomData)
+        //没有存放结果的地方，join成功时结果为None
+        Self::new(val, ResultSlot::default())
     }
 }
 
 impl<R> From<JoinHandle<R>> for open_coroutine_core::net::join::JoinHandle {
     fn from(val: JoinHandle<R>) -> Self {
-        val.0
+        val.into_inner().0
     }
 }
 
@@ -231,6 +615,47 @@ pub fn maybe_grow<R: 'static, F: FnOnce() -> R>(
     }
 }
 
+/// Disable preemption of the current coroutine until the guard is dropped.
+///
+/// see [`open_coroutine_core::preempt::PreemptionGuard`].
+#[repr(C)]
+#[derive(Debug)]
+pub struct PreemptionGuard(PhantomData<*const ()>);
+
+impl PreemptionGuard {
+    /// Enter a critical section.
+    #[must_use]
+    pub fn new() -> Self {
+        unsafe { preemption_guard_enter() };
+        PreemptionGuard(PhantomData)
+    }
+}
+
+impl Default for PreemptionGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for PreemptionGuard {
+    fn drop(&mut self) {
+        unsafe { preemption_guard_exit() };
+    }
+}
+
+/// Mark the code in `[start, end)` as non-preemptible.
+///
+/// see [`open_coroutine_core::preempt::register_non_preemptible`].
+pub fn register_non_preemptible(start: usize, end: usize) -> std::io::Result<()> {
+    if unsafe { register_non_preemptible_region(start, end) } == 0 {
+        return Ok(());
+    }
+    Err(Error::new(
+        ErrorKind::InvalidInput,
+        "register non-preemptible region failed",
+    ))
+}
+
 /// Opens a TCP connection to a remote host.
 ///
 /// `addr` is an address of the remote host. Anything which implements
@@ -295,12 +720,33 @@ pub fn connect_timeout<A: ToSocketAddrs>(addr: A, timeout: Duration) -> std::io:
 
 #[cfg(test)]
 mod tests {
-    use crate::{init, shutdown};
+    use crate::{
+        add_task_listener, init, join_all, join_any, race, shutdown, spawn_detached, task_keyed,
+        TaskListener, TaskPriority,
+    };
+    use open_coroutine_core::common::now;
     use open_coroutine_core::net::config::Config;
+    use std::io::{Error, ErrorKind};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    static COMPLETED: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug)]
+    struct Counter;
+
+    impl TaskListener for Counter {
+        fn on_complete(&self, _: &str, _: u64, _: u64, _: Option<usize>) {
+            _ = COMPLETED.fetch_add(1, Ordering::Release);
+        }
+    }
 
     #[test]
     fn test() {
+        static DETACHED: AtomicBool = AtomicBool::new(false);
+        static ORDER: Mutex<Vec<u64>> = Mutex::new(Vec::new());
         init(Config::single());
+        add_task_listener(Counter);
         let join = task!(
             |_| {
                 println!("Hello, world!");
@@ -308,6 +754,89 @@ mod tests {
             (),
         );
         assert_eq!(Some(()), join.join().expect("join failed"));
+        let join = task!(|param| param + 1, 1, TaskPriority::High);
+        assert_eq!(Some(2), join.join().expect("join failed"));
+        //从无类型的句柄转换而来的句柄没有结果
+        let untyped: open_coroutine_core::net::join::JoinHandle = task!(|param| param, 1).into();
+        let join: crate::JoinHandle<i32> = untyped.into();
+        assert_eq!(None, join.join().expect("join failed"));
+        let join = task!(|param| param + 1, 1).map(|result| result.map(|r| r * 2));
+        assert_eq!(Some(Some(4)), join.join().expect("join failed"));
+        let join = task!(|param| param, 1)
+            .and_then(|_| Err::<(), _>(Error::new(ErrorKind::InvalidData, "test")))
+            .then(|result| result.map_err(|e| e.kind()));
+        assert_eq!(
+            Some(Err(ErrorKind::InvalidData)),
+            join.join().expect("join failed")
+        );
+
+        let handles = vec![task!(|param| param, 1), task!(|param| param, 2)];
+        let results: Vec<_> = join_all(&handles)
+            .expect("join all failed")
+            .into_iter()
+            .map(|result| result.expect("task failed"))
+            .collect();
+        assert_eq!(vec![Some(1), Some(2)], results);
+        let handles = vec![
+            task!(|param| param, 1),
+            task!(
+                |param| {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                    param
+                },
+                2
+            ),
+        ];
+        let (index, result) = join_any(&handles).expect("join any failed");
+        assert_eq!(0, index);
+        assert_eq!(Some(1), result.expect("task failed"));
+        assert_eq!(
+            Some(2),
+            handles[1]
+                .timeout_join(std::time::Duration::from_secs(1))
+                .expect("join failed")
+        );
+        let handles = vec![
+            task!(|param| param, 1).and_then(|_| Err(Error::new(ErrorKind::Other, "test"))),
+            task!(|param| param, 2),
+        ];
+        assert_eq!((1, Some(2)), race(&handles).expect("race failed"));
+        //在协程里等待时挂起协程，而不是阻塞线程
+        let join = task!(
+            |()| {
+                let handles = vec![task!(|param| param, 1), task!(|param| param, 2)];
+                join_all(&handles).expect("join all failed").len()
+            },
+            (),
+        );
+        assert_eq!(Some(2), join.join().expect("join failed"));
+
+        spawn_detached(|()| DETACHED.store(true, Ordering::Release), ()).expect("spawn failed");
+        //丢弃句柄后任务照常执行
+        drop(task!(|param| param, 1));
+        let timeout_time = now() + 1_000_000_000;
+        while !DETACHED.load(Ordering::Acquire) && now() < timeout_time {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert!(DETACHED.load(Ordering::Acquire));
+
+        //同一key的任务即使在系统调用中挂起，也按提交顺序执行
+        let handles: Vec<_> = (0..3)
+            .map(|i| {
+                task_keyed(
+                    "session",
+                    |i| {
+                        std::thread::sleep(std::time::Duration::from_millis(30 - i * 10));
+                        ORDER.lock().expect("lock failed").push(i);
+                    },
+                    i,
+                )
+            })
+            .collect();
+        assert_eq!(3, join_all(&handles).expect("join all failed").len());
+        assert_eq!(vec![0, 1, 2], *ORDER.lock().expect("lock failed"));
+        //监听器通过C ABI收到了事件
+        assert!(COMPLETED.load(Ordering::Acquire) > 0);
         shutdown();
     }
 }